@@ -0,0 +1,93 @@
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::runtime;
+use tokio::stream::StreamExt;
+
+use criterion::*;
+
+#[allow(dead_code)]
+mod common;
+
+const LATENCY: Duration = Duration::from_millis(10);
+const SIZE: usize = 4 * 1024 * 1024;
+
+async fn bulk(dst: &str) {
+    let mut conn = lwip::TcpStream::connect_from("10.0.0.1:0", dst)
+        .await
+        .unwrap();
+
+    let data = vec![0x42; 64 * 1024];
+    let mut sent = 0;
+    while sent < SIZE {
+        sent += conn.write(&data).await.unwrap();
+    }
+    conn.shutdown().await.unwrap();
+
+    // wait for the receiver to drain everything.
+    let mut buf = [0; 1];
+    conn.read(&mut buf).await.unwrap();
+}
+
+async fn sink_loop(mut listener: lwip::tcp::TcpListener) {
+    while let Some(Ok(mut conn)) = listener.next().await {
+        tokio::spawn(async move {
+            let mut buf = vec![0; 64 * 1024];
+            while conn.read(&mut buf).await.unwrap() > 0 {}
+            conn.shutdown().await.unwrap();
+        });
+    }
+}
+
+async fn setup() {
+    // 1MB window, enough for ~400Mbps over a 20ms RTT.
+    lwip::StackConfig::new()
+        .tcp_mss(1460)
+        .tcp_wnd(1024 * 1024)
+        .tcp_snd_buf(1024 * 1024)
+        .tcp_snd_queuelen(2048)
+        .start()
+        .unwrap();
+
+    let (dev0, dev1) = lwip::dev::Link::builder().latency(LATENCY).build();
+    let dev0 = lwip::DeviceBuilder::default()
+        .ipv4(Ipv4Addr::new(10, 0, 0, 1), 24)
+        .build(dev0)
+        .unwrap();
+    let dev1 = lwip::DeviceBuilder::default()
+        .ipv4(Ipv4Addr::new(10, 0, 0, 2), 24)
+        .build(dev1)
+        .unwrap();
+    tokio::spawn(dev0.drive());
+    tokio::spawn(dev1.drive());
+
+    let listener = lwip::TcpListener::bind("10.0.0.2:80").await.unwrap();
+    tokio::spawn(sink_loop(listener));
+}
+
+fn benchmark(c: &mut Criterion) {
+    // the link needs the timer, unlike the loopback benchmarks.
+    let mut rt = runtime::Builder::new()
+        .threaded_scheduler()
+        .core_threads(1)
+        .max_threads(1)
+        .enable_time()
+        .build()
+        .unwrap();
+    rt.block_on(setup());
+
+    let mut group = c.benchmark_group("link");
+    group.throughput(Throughput::Bytes(SIZE as u64));
+    group.bench_function("bulk_10ms", |b| {
+        b.iter(|| rt.block_on(bulk(black_box("10.0.0.2:80"))))
+    });
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = common::criterion().sample_size(10);
+    targets = benchmark,
+}
+criterion_main!(benches);
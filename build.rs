@@ -14,6 +14,48 @@ fn main() {
     }
 
     let debug = if cfg!(feature = "debug") { "1" } else { "0" };
+    let sack = if cfg!(feature = "sack") { "1" } else { "0" };
+
+    // Build-time overrides of the lwipopts.h tunables: (env, define,
+    // default, range). The TCP ones are only defaults, see StackConfig.
+    let max = u32::max_value();
+    let tunables = [
+        (
+            "LWIP_IPV6_NUM_ADDRESSES",
+            "LWIP_IPV6_NUM_ADDRESSES",
+            "3",
+            1..=127,
+        ),
+        ("LWIP_TCP_MSS", "LWIP_RS_TCP_MSS", "536", 1..=0xffff),
+        ("LWIP_TCP_WND", "LWIP_RS_TCP_WND", "2144", 1..=max),
+        ("LWIP_TCP_SND_BUF", "LWIP_RS_TCP_SND_BUF", "1072", 1..=max),
+        (
+            "LWIP_TCP_SND_QUEUELEN",
+            "LWIP_RS_TCP_SND_QUEUELEN",
+            "8",
+            1..=0xffff,
+        ),
+        ("LWIP_TCP_MSL", "LWIP_RS_TCP_MSL", "60000", 1..=max),
+        ("LWIP_TCP_RCV_SCALE", "LWIP_RS_TCP_RCV_SCALE", "0", 0..=14),
+        ("LWIP_TCP_MAX_SACK_NUM", "LWIP_TCP_MAX_SACK_NUM", "4", 1..=4),
+    ];
+    let mut defines = Vec::new();
+    for (var, define, default, range) in tunables.iter() {
+        println!("cargo:rerun-if-env-changed={}", var);
+        let value = env::var(var).unwrap_or_else(|_| default.to_string());
+        match value.parse::<u32>() {
+            Ok(n) if range.contains(&n) => {}
+            _ => panic!(
+                "{} must be a number from {} to {}",
+                var,
+                range.start(),
+                range.end()
+            ),
+        }
+        println!("cargo:rustc-env={}={}", define, value);
+        defines.push((*define, value));
+    }
+    defines.push(("LWIP_TCP_SACK_OUT", sack.to_string()));
 
     config
         .file("ffi/lwip/src/core/def.c")
@@ -23,43 +65,77 @@ fn main() {
         .file("ffi/lwip/src/core/ipv4/ip4.c")
         .file("ffi/lwip/src/core/ipv4/ip4_addr.c")
         .file("ffi/lwip/src/core/ipv4/ip4_frag.c")
+        .file("ffi/lwip/src/core/ipv4/etharp.c")
+        .file("ffi/lwip/src/core/ipv4/igmp.c")
+        .file("ffi/lwip/src/core/ipv6/ethip6.c")
         .file("ffi/lwip/src/core/ipv6/icmp6.c")
         .file("ffi/lwip/src/core/ipv6/ip6.c")
         .file("ffi/lwip/src/core/ipv6/ip6_addr.c")
         .file("ffi/lwip/src/core/ipv6/ip6_frag.c")
+        .file("ffi/lwip/src/core/ipv6/mld6.c")
         .file("ffi/lwip/src/core/ipv6/nd6.c")
         .file("ffi/lwip/src/core/mem.c")
         .file("ffi/lwip/src/core/memp.c")
         .file("ffi/lwip/src/core/netif.c")
         .file("ffi/lwip/src/core/pbuf.c")
-        .file("ffi/lwip/src/core/raw.c")
         .file("ffi/lwip/src/core/stats.c")
         .file("ffi/lwip/src/core/udp.c")
         .file("ffi/lwip/src/core/tcp.c")
         .file("ffi/lwip/src/core/tcp_in.c")
         .file("ffi/lwip/src/core/tcp_out.c")
-        .file("ffi/lwip/src/core/timeouts.c")
-        .file("ffi/lwip/src/api/tcpip.c")
+        .file("ffi/lwip/src/netif/ethernet.c")
+        .file("ffi/lwip/src/netif/ppp/auth.c")
+        .file("ffi/lwip/src/netif/ppp/chap-md5.c")
+        .file("ffi/lwip/src/netif/ppp/chap-new.c")
+        .file("ffi/lwip/src/netif/ppp/eui64.c")
+        .file("ffi/lwip/src/netif/ppp/fsm.c")
+        .file("ffi/lwip/src/netif/ppp/ipcp.c")
+        .file("ffi/lwip/src/netif/ppp/ipv6cp.c")
+        .file("ffi/lwip/src/netif/ppp/lcp.c")
+        .file("ffi/lwip/src/netif/ppp/magic.c")
+        .file("ffi/lwip/src/netif/ppp/pppoe.c")
+        .file("ffi/lwip/src/netif/ppp/pppos.c")
+        .file("ffi/lwip/src/netif/ppp/upap.c")
+        .file("ffi/lwip/src/netif/ppp/utils.c")
+        .file("ffi/lwip/src/netif/ppp/polarssl/md5.c")
         .file("ffi/lwip/src/api/api_lib.c")
         .file("ffi/lwip/src/api/api_msg.c")
         .file("ffi/lwip/src/api/netbuf.c")
         .file("ffi/lwip/src/api/err.c")
         .file("ffi/lwip/src/api/netifapi.c")
         .file("ffi/lwip/contrib/addons/ipv6_static_routing/ip6_route_table.c")
+        .file("ffi/src/tcpip.c")
         .file("ffi/src/tcpip_init.c")
+        .file("ffi/src/timeouts.c")
+        .file("ffi/src/raw.c")
+        .file("ffi/src/dhcp.c")
+        .file("ffi/src/ppp.c")
+        .file("ffi/src/route.c")
+        .file("ffi/src/tcp_cc.c")
+        .file("ffi/src/tcp_retrans.c")
         .file("ffi/src/sys.c")
+        .file("ffi/src/diag.c")
+        .file("ffi/src/config.c")
         .include("ffi/src")
         .include("ffi/lwip/contrib/ports/unix")
         .include("ffi/lwip/contrib/ports/unix/port/include")
         .include("ffi/lwip/src/include")
         .flag_if_supported("-Wno-unused-parameter")
         .flag_if_supported("-Wno-unused-variable")
-        .define("FEATURE_DEBUG", debug)
-        .compile("liblwip.a");
+        .define("FEATURE_DEBUG", debug);
+    for (define, value) in defines.iter() {
+        config.define(define, Some(value.as_str()));
+    }
+    config.compile("liblwip.a");
 
     println!("cargo:rustc-link-lib=static=lwip");
 
-    let bindings = bindgen::Builder::default()
+    let mut bindings = bindgen::Builder::default();
+    for (define, value) in defines.iter() {
+        bindings = bindings.clang_arg(format!("-D{}={}", define, value));
+    }
+
+    let bindings = bindings
         .header("ffi/lwip/src/include/lwip/init.h")
         .header("ffi/lwip/src/include/lwip/raw.h")
         .header("ffi/lwip/src/include/lwip/udp.h")
@@ -67,7 +143,18 @@ fn main() {
         .header("ffi/lwip/src/include/lwip/tcpip.h")
         .header("ffi/lwip/src/include/lwip/api.h")
         .header("ffi/lwip/src/include/lwip/netifapi.h")
+        .header("ffi/lwip/src/include/lwip/stats.h")
+        .header("ffi/lwip/src/include/lwip/igmp.h")
+        .header("ffi/lwip/src/include/lwip/mld6.h")
+        .header("ffi/lwip/src/include/lwip/priv/nd6_priv.h")
+        .header("ffi/lwip/src/include/lwip/etharp.h")
+        .header("ffi/lwip/src/include/lwip/ethip6.h")
+        .header("ffi/lwip/src/include/netif/ethernet.h")
+        .header("ffi/lwip/src/include/netif/ppp/pppos.h")
+        .header("ffi/lwip/src/include/netif/ppp/pppoe.h")
         .header("ffi/src/tcpip_init.c")
+        .header("ffi/src/tcp_cc.c")
+        .header("ffi/src/dhcp.c")
         .clang_arg("-Iffi/lwip/src/include")
         .clang_arg("-Iffi/lwip/contrib/ports/unix/port/include")
         .clang_arg("-Iffi/src")
@@ -76,6 +163,10 @@ fn main() {
         .impl_debug(true)
         .whitelist_function("ffi/lwip_init")
         .whitelist_function("tcpip_.*")
+        .whitelist_function("ip_input")
+        .whitelist_function("ethernet_input")
+        .whitelist_function("etharp_output")
+        .whitelist_function("ethip6_output")
         .whitelist_function("netconn_.*")
         .whitelist_function("netifapi_.*")
         .whitelist_function("raw_.*")
@@ -85,15 +176,56 @@ fn main() {
         .whitelist_function("netif_.*")
         .whitelist_function("netbuf_.*")
         .whitelist_function("err_.*")
+        .whitelist_function("igmp_.*")
+        .whitelist_function("mld6_.*")
+        .whitelist_function("dhcp_client_start")
+        .whitelist_function("dhcp_renew")
+        .whitelist_function("dhcp_release_and_stop")
+        .whitelist_function("dhcp_cleanup")
+        .whitelist_function("sys_lock_tcpip_core")
+        .whitelist_function("sys_unlock_tcpip_core")
+        .whitelist_function("sys_check_timeouts")
+        .whitelist_function("sys_timeouts_sleeptime")
+        .whitelist_function("sys_timeout")
+        .whitelist_function("sys_untimeout")
+        .whitelist_function("pppos_create")
+        .whitelist_function("pppos_input")
+        .whitelist_function("pppoe_create")
+        .whitelist_function("ppp_connect")
+        .whitelist_function("ppp_close")
+        .whitelist_function("ppp_free")
+        .whitelist_function("ppp_set_auth")
         .whitelist_type("err_enum_t")
         .whitelist_type("err_t")
         .whitelist_type("lwip_ip_addr_type")
+        .whitelist_type("dhcp_client_lease")
+        .whitelist_type("dhcp_client_event")
+        .whitelist_type("igmp_group")
+        .whitelist_type("mld_group")
+        .whitelist_type("lwip_internal_netif_client_data_index")
+        .whitelist_var("IP6_ADDR_.*")
+        .whitelist_var("NETIF_FLAG_.*")
+        .whitelist_var("LWIP_NSC_.*")
+        .whitelist_var("lwip_stats")
+        .whitelist_var("tcp_active_pcbs")
+        .whitelist_var("tcp_bound_pcbs")
+        .whitelist_var("tcp_listen_pcbs")
+        .whitelist_var("tcp_tw_pcbs")
+        .whitelist_var("udp_pcbs")
+        .whitelist_var("default_router_list")
+        .whitelist_var("prefix_list")
+        .whitelist_var("LWIP_DBG_.*")
+        .whitelist_var("PPPAUTHTYPE_.*")
+        .whitelist_var("PPPERR_.*")
+        .whitelist_var("PPP_PHASE_.*")
         .rustified_enum("err_enum_t")
         .rustified_enum("pbuf_layer")
         .rustified_enum("pbuf_type")
         .rustified_enum("netconn_type")
         .rustified_enum("netconn_state")
         .rustified_enum("netconn_evt")
+        .rustified_enum("tcp_cc_event")
+        .rustified_enum("dhcp_client_event")
         .generate()
         .expect("Unable to generate bindings");
 
@@ -0,0 +1,12 @@
+#include "lwip/opt.h"
+#include "lwip/tcp.h"
+
+unsigned short lwip_rs_tcp_mss = LWIP_RS_TCP_MSS;
+unsigned int lwip_rs_tcp_wnd = LWIP_RS_TCP_WND;
+unsigned int lwip_rs_tcp_snd_buf = LWIP_RS_TCP_SND_BUF;
+unsigned short lwip_rs_tcp_snd_queuelen = LWIP_RS_TCP_SND_QUEUELEN;
+unsigned int lwip_rs_tcp_msl = LWIP_RS_TCP_MSL;
+unsigned char lwip_rs_tcp_rcv_scale = LWIP_RS_TCP_RCV_SCALE;
+
+/* bindgen skips defines with a cast, export the ones the Rust side needs */
+const tcpflags_t lwip_rs_tf_wnd_scale = TF_WND_SCALE;
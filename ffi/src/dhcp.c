@@ -0,0 +1,165 @@
+/* Build the lwIP DHCP client with a wrapper around its UDP receive callback,
+ * so that the lease can be reported once lwIP is done with an ACK. The DNS
+ * servers are picked from the reply itself: lwIP only hands them to its DNS
+ * resolver, which is not compiled in. */
+#include "../lwip/src/core/ipv4/dhcp.c"
+
+#include <stddef.h>
+
+#define DHCP_CLIENT_MAX_DNS 2
+
+enum dhcp_client_event
+{
+    DHCP_CLIENT_EVENT_BOUND,
+    DHCP_CLIENT_EVENT_RENEWING,
+    DHCP_CLIENT_EVENT_REBINDING,
+    DHCP_CLIENT_EVENT_LOST,
+};
+
+struct dhcp_client_lease
+{
+    ip4_addr_t addr;
+    ip4_addr_t netmask;
+    ip4_addr_t gw;
+    /* in seconds */
+    u32_t lease_time;
+    u32_t renew_time;
+    u32_t rebind_time;
+    u8_t dns_count;
+    ip4_addr_t dns[DHCP_CLIENT_MAX_DNS];
+};
+
+/* implemented in src/dev/netif.rs */
+extern void lwip_rs_dhcp_event(struct netif *netif, enum dhcp_client_event event,
+                               const struct dhcp_client_lease *lease);
+
+/* Returns the message type of a DHCP reply and fills in its DNS servers. */
+static u8_t
+dhcp_client_parse(struct pbuf *p, struct dhcp_client_lease *lease)
+{
+    u16_t off = offsetof(struct dhcp_msg, options);
+    u8_t msg_type = 0;
+
+    while (off + 1 < p->tot_len)
+    {
+        u8_t op = pbuf_get_at(p, off);
+        u8_t len;
+        u16_t i;
+
+        if (op == DHCP_OPTION_PAD)
+        {
+            off++;
+            continue;
+        }
+        if (op == DHCP_OPTION_END)
+        {
+            break;
+        }
+
+        len = pbuf_get_at(p, off + 1);
+        off += 2;
+        if (off + len > p->tot_len)
+        {
+            break;
+        }
+
+        if (op == DHCP_OPTION_MESSAGE_TYPE && len == 1)
+        {
+            msg_type = pbuf_get_at(p, off);
+        }
+        else if (op == DHCP_OPTION_DNS_SERVER)
+        {
+            for (i = 0; i + 4 <= len && lease->dns_count < DHCP_CLIENT_MAX_DNS; i += 4)
+            {
+                pbuf_copy_partial(p, &lease->dns[lease->dns_count++], 4, off + i);
+            }
+        }
+        off += len;
+    }
+
+    return msg_type;
+}
+
+static void
+dhcp_client_recv(void *arg, struct udp_pcb *pcb, struct pbuf *p, const ip_addr_t *addr,
+                 u16_t port)
+{
+    struct netif *netif = ip_current_input_netif();
+    struct dhcp *dhcp = netif_dhcp_data(netif);
+    struct dhcp_client_lease lease;
+    u8_t msg_type, state;
+
+    if (dhcp == NULL)
+    {
+        dhcp_recv(arg, pcb, p, addr, port);
+        return;
+    }
+
+    memset(&lease, 0, sizeof(lease));
+    msg_type = dhcp_client_parse(p, &lease);
+    state = dhcp->state;
+
+    /* frees p */
+    dhcp_recv(arg, pcb, p, addr, port);
+
+    if (msg_type == DHCP_ACK && state != DHCP_STATE_BOUND && dhcp->state == DHCP_STATE_BOUND)
+    {
+        ip4_addr_copy(lease.addr, *netif_ip4_addr(netif));
+        ip4_addr_copy(lease.netmask, *netif_ip4_netmask(netif));
+        ip4_addr_copy(lease.gw, *netif_ip4_gw(netif));
+        lease.lease_time = dhcp->offered_t0_lease;
+        lease.renew_time = dhcp->offered_t1_renew;
+        lease.rebind_time = dhcp->offered_t2_rebind;
+        lwip_rs_dhcp_event(netif, DHCP_CLIENT_EVENT_BOUND, &lease);
+    }
+}
+
+/* lwIP creates its pcb again with the plain dhcp_recv whenever it starts
+ * over, e.g. once the lease expired. */
+static void
+dhcp_client_hook_recv(void)
+{
+    if (dhcp_pcb != NULL && dhcp_pcb->recv != dhcp_client_recv)
+    {
+        udp_recv(dhcp_pcb, dhcp_client_recv, NULL);
+    }
+}
+
+/* LWIP_HOOK_DHCP_APPEND_OPTIONS, called for every message sent. */
+void
+dhcp_client_append_options(struct netif *netif, u8_t state, u8_t msg_type)
+{
+    dhcp_client_hook_recv();
+
+    if (msg_type == DHCP_DISCOVER || msg_type == DHCP_RELEASE)
+    {
+        /* the lease expired or was refused, or the client stops */
+        lwip_rs_dhcp_event(netif, DHCP_CLIENT_EVENT_LOST, NULL);
+    }
+    else if (msg_type != DHCP_REQUEST)
+    {
+        return;
+    }
+    else if (state == DHCP_STATE_RENEWING)
+    {
+        lwip_rs_dhcp_event(netif, DHCP_CLIENT_EVENT_RENEWING, NULL);
+    }
+    else if (state == DHCP_STATE_REBINDING)
+    {
+        lwip_rs_dhcp_event(netif, DHCP_CLIENT_EVENT_REBINDING, NULL);
+    }
+}
+
+/* dhcp_start() with the lease reporting. Must be called with the core lock
+ * held. */
+err_t
+dhcp_client_start(struct netif *netif)
+{
+    err_t err = dhcp_start(netif);
+
+    if (err == ERR_OK)
+    {
+        dhcp_client_hook_recv();
+    }
+    return err;
+}
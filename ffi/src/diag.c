@@ -0,0 +1,54 @@
+#include <stdarg.h>
+#include <stdio.h>
+
+#include "lwip/debug.h"
+
+/* implemented in src/diag.rs */
+extern int lwip_rs_log_enabled(unsigned int debug);
+extern void lwip_rs_log(unsigned int debug, const char *msg, size_t len);
+
+#define DIAG_BUF_SIZE 512
+
+/* lwIP sometimes builds a single line out of several LWIP_DEBUGF() calls:
+ * buffer until the newline. */
+static __thread unsigned int diag_debug;
+static __thread char diag_buf[DIAG_BUF_SIZE];
+static __thread size_t diag_len;
+
+int
+lwip_rs_diag_begin(unsigned int debug)
+{
+  if (!lwip_rs_log_enabled(debug)) {
+    return 0;
+  }
+
+  diag_debug = debug;
+  return 1;
+}
+
+void
+lwip_rs_diag_printf(const char *fmt, ...)
+{
+  va_list ap;
+  int n;
+
+  va_start(ap, fmt);
+  n = vsnprintf(diag_buf + diag_len, DIAG_BUF_SIZE - diag_len, fmt, ap);
+  va_end(ap);
+
+  if (n < 0) {
+    return;
+  }
+
+  diag_len += (size_t)n;
+  if (diag_len >= DIAG_BUF_SIZE) {
+    /* truncated */
+    diag_len = DIAG_BUF_SIZE - 1;
+    diag_buf[diag_len - 1] = '\n';
+  }
+
+  if (diag_len > 0 && diag_buf[diag_len - 1] == '\n') {
+    lwip_rs_log(diag_debug, diag_buf, diag_len - 1);
+    diag_len = 0;
+  }
+}
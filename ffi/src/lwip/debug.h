@@ -0,0 +1,39 @@
+/*
+ * Wrapper around lwIP's debug.h: every LWIP_DEBUGF() message is handed to
+ * the Rust side (see src/diag.rs) instead of being printed on stdout.
+ *
+ * This header shadows "lwip/debug.h" as ffi/src comes first in the include
+ * path.
+ */
+#ifndef LWIP_RS_DEBUG_H
+#define LWIP_RS_DEBUG_H
+
+#include_next "lwip/debug.h"
+
+int lwip_rs_diag_begin(unsigned int debug);
+void lwip_rs_diag_printf(const char *fmt, ...);
+
+#undef LWIP_PLATFORM_DIAG
+#define LWIP_PLATFORM_DIAG(x) do { \
+                               if (lwip_rs_diag_begin(LWIP_DBG_ON)) { \
+                                 lwip_rs_diag_printf x; \
+                               } \
+                             } while(0)
+
+#ifdef LWIP_DEBUG
+/* The level check is left to the logger so it can be changed at runtime. */
+#undef LWIP_DEBUGF
+#define LWIP_DEBUGF(debug, message) do { \
+                               if ( \
+                                   ((debug) & LWIP_DBG_ON) && \
+                                   ((debug) & LWIP_DBG_TYPES_ON) && \
+                                   lwip_rs_diag_begin(debug)) { \
+                                 lwip_rs_diag_printf message; \
+                                 if ((debug) & LWIP_DBG_HALT) { \
+                                   while(1); \
+                                 } \
+                               } \
+                             } while(0)
+#endif /* LWIP_DEBUG */
+
+#endif /* LWIP_RS_DEBUG_H */
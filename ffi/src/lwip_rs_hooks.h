@@ -0,0 +1,30 @@
+#ifndef LWIP_RS_HOOKS_H
+#define LWIP_RS_HOOKS_H
+
+struct netif;
+struct ip4_addr;
+struct tcp_pcb;
+struct tcp_hdr;
+
+/* ffi/src/tcp_cc.c */
+err_t tcp_cc_inpacket(struct tcp_pcb *pcb, struct tcp_hdr *hdr, u16_t optlen,
+                      u16_t opt1len, u8_t *opt2);
+
+/* ffi/src/tcp_retrans.c */
+void tcp_retrans_inpacket(struct tcp_pcb *pcb);
+
+#define LWIP_HOOK_TCP_INPACKET_PCB(pcb, hdr, optlen, opt1len, opt2, p) \
+    (tcp_retrans_inpacket(pcb), tcp_cc_inpacket(pcb, hdr, optlen, opt1len, opt2))
+
+/* ffi/src/dhcp.c */
+void dhcp_client_append_options(struct netif *netif, u8_t state, u8_t msg_type);
+
+#define LWIP_HOOK_DHCP_APPEND_OPTIONS(netif, dhcp, state, msg, msg_type, options_len_ptr) \
+    dhcp_client_append_options(netif, state, msg_type)
+
+/* ffi/src/route.c */
+struct netif *ip4_route_broadcast(const struct ip4_addr *src, const struct ip4_addr *dest);
+
+#define LWIP_HOOK_IP4_ROUTE_SRC(src, dest) ip4_route_broadcast(src, dest)
+
+#endif /* LWIP_RS_HOOKS_H */
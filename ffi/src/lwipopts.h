@@ -8,7 +8,9 @@
 #define LWIP_RAW 1
 #define LWIP_UDP 1
 #define LWIP_TCP 1
-#define LWIP_ARP 0
+// Ethernet devices such as TAP ones, see DeviceBuilder::ethernet
+#define LWIP_ARP 1
+#define LWIP_ETHERNET 1
 #define LWIP_ICMP 0
 #define LWIP_HAVE_LOOPIF 0
 #define LWIP_NETCONN 1
@@ -23,58 +25,132 @@
 // Define the netif struct
 #define LWIP_IPV4 1
 #define LWIP_IPV6 1
+#ifndef LWIP_IPV6_NUM_ADDRESSES
 #define LWIP_IPV6_NUM_ADDRESSES 3
-#define LWIP_NETIF_STATUS_CALLBACK 0
-#define LWIP_NETIF_LINK_CALLBACK 0
-#define LWIP_DHCP 0
+#endif
+#define LWIP_NETIF_STATUS_CALLBACK 1
+#define LWIP_NETIF_LINK_CALLBACK 1
+#define LWIP_NETIF_EXT_STATUS_CALLBACK 1
+#define LWIP_DHCP 1
+// the lease is reported once the ACK is processed, see ffi/src/dhcp.c
+#define LWIP_DHCP_DOES_ARP_CHECK 0
 #define LWIP_AUTOIP 0
-#define LWIP_IGMP 0
-#define LWIP_IPV6_MLD 0
-#define LWIP_NUM_NETIF_CLIENT_DATA 0
+#define LWIP_IGMP 1
+#define LWIP_IPV6_MLD 1
+// IP_MULTICAST_LOOP is done by the netif output, see netif_common_output
+#define LWIP_MULTICAST_TX_OPTIONS 1
+// sending to broadcast addresses needs SOF_BROADCAST, see
+// UdpSocket::set_broadcast
+#define IP_SOF_BROADCAST 1
+// lets mDNS responders on several interfaces share port 5353
+#define SO_REUSE 1
+// the NetIf state, see src/dev/netif.rs
+#define LWIP_NUM_NETIF_CLIENT_DATA 1
 #define LWIP_IPV6_AUTOCONFIG 1
-#define LWIP_IPV6_SEND_ROUTER_SOLICIT 0
+// only sent by interfaces with ip6_autoconfig_enabled (see
+// ffi/src/timeouts.c), once the link-local address is configured. See
+// DeviceBuilder::ipv6_autoconfig
+#define LWIP_IPV6_SEND_ROUTER_SOLICIT 1
 #define LWIP_NETIF_HOSTNAME 0
 #define LWIP_CHECKSUM_CTRL_PER_NETIF 0
-#define MIB2_STATS 0
+#define MIB2_STATS 1
 #define LWIP_NETIF_HWADDRHINT 0
 #define LWIP_NETIF_LOOPBACK 0
 #define LWIP_NETIF_API 1
 #define LWIP_NETIF_REMOVE_CALLBACK 1
 
+// PPP over byte streams and PPPoE over Ethernet interfaces, see PppDevice
+// and PppoeDevice. The interface counts the packets, see ffi/src/ppp.c
+#define PPP_SUPPORT 1
+#define PPPOS_SUPPORT 1
+#define PPPOE_SUPPORT 1
+// lwIP authenticates the peer against its own credentials, see
+// PppBuilder::require_auth
+#define PPP_SERVER 1
+#define PPP_IPV6_SUPPORT 1
+#define PAP_SUPPORT 1
+#define CHAP_SUPPORT 1
+#define VJ_SUPPORT 0
+#define LWIP_INCLUDED_POLARSSL_MD5 1
+#define MEMP_NUM_PPP_PCB 4
+
+// Define the stats struct
+#define LWIP_STATS 1
+#define LWIP_STATS_LARGE 1
+#define MEM_STATS 1
+#define MEMP_STATS 1
+
 // Define the tcp_pcb struct
 #define LWIP_TCP_TIMESTAMPS 1
 
+// TCP tunables, set at runtime through StackConfig (ffi/src/config.c).
+// LWIP_RS_TCP_* hold the build-time defaults; values used by the
+// preprocessor or by static pool descriptors stay constant.
+extern unsigned short lwip_rs_tcp_mss;
+extern unsigned int lwip_rs_tcp_wnd;
+extern unsigned int lwip_rs_tcp_snd_buf;
+extern unsigned short lwip_rs_tcp_snd_queuelen;
+extern unsigned int lwip_rs_tcp_msl;
+extern unsigned char lwip_rs_tcp_rcv_scale;
+#define TCP_MSS lwip_rs_tcp_mss
+#define TCP_WND lwip_rs_tcp_wnd
+#define TCP_SND_BUF lwip_rs_tcp_snd_buf
+#define TCP_SND_QUEUELEN lwip_rs_tcp_snd_queuelen
+#define TCP_MSL lwip_rs_tcp_msl
+#define LWIP_WND_SCALE 1
+#define TCP_RCV_SCALE lwip_rs_tcp_rcv_scale
+#define TCP_OVERSIZE LWIP_RS_TCP_MSS
+#define MEMP_NUM_TCP_SEG LWIP_RS_TCP_SND_QUEUELEN
+#define PBUF_POOL_BUFSIZE LWIP_MEM_ALIGN_SIZE(LWIP_RS_TCP_MSS + 40 + PBUF_LINK_ENCAPSULATION_HLEN + PBUF_LINK_HLEN)
+#ifndef LWIP_TCP_SACK_OUT
+#define LWIP_TCP_SACK_OUT 1
+#endif
+#ifndef LWIP_TCP_MAX_SACK_NUM
+#define LWIP_TCP_MAX_SACK_NUM 4
+#endif
+// per-PCB congestion control state and retransmission counts
+// (ffi/src/tcp_cc.c, ffi/src/tcp_retrans.c)
+#define LWIP_TCP_PCB_NUM_EXT_ARGS 2
+#define LWIP_HOOK_FILENAME "lwip_rs_hooks.h"
+// checked by StackConfig instead
+#define LWIP_DISABLE_TCP_SANITY_CHECKS 1
+// netconn recv_avail, the receive queue reported by tcp::connections()
+#define LWIP_SO_RCVBUF 1
+
 #define IP_TRANSPARENT 1
 #define TCP_TRANSPARENT 1
 
 #if FEATURE_DEBUG == 1
+// The module is encoded above the LWIP_DBG_* bits so that src/diag.rs can
+// pick a log target. Levels are filtered at runtime by the logger.
+#define LWIP_RS_DBG_MODULE(n)      ((n) << 8)
 #define LWIP_DBG_MIN_LEVEL         LWIP_DBG_LEVEL_ALL
 #define LWIP_DEBUG 1
-#define RAW_DEBUG                  LWIP_DBG_ON
-#define PPP_DEBUG                  LWIP_DBG_ON
-#define MEM_DEBUG                  LWIP_DBG_ON
-#define MEMP_DEBUG                 LWIP_DBG_ON
-#define PBUF_DEBUG                 LWIP_DBG_ON
-#define API_LIB_DEBUG              LWIP_DBG_ON
-#define API_MSG_DEBUG              LWIP_DBG_ON
-#define TCPIP_DEBUG                LWIP_DBG_ON
-#define NETIF_DEBUG                LWIP_DBG_ON
-#define SOCKETS_DEBUG              LWIP_DBG_ON
-#define DNS_DEBUG                  LWIP_DBG_ON
-#define AUTOIP_DEBUG               LWIP_DBG_ON
-#define DHCP_DEBUG                 LWIP_DBG_ON
-#define IP_DEBUG                   LWIP_DBG_ON
-#define IP_REASS_DEBUG             LWIP_DBG_ON
-#define IP6_DEBUG                  LWIP_DBG_ON
-#define ICMP_DEBUG                 LWIP_DBG_ON
-#define IGMP_DEBUG                 LWIP_DBG_ON
-#define UDP_DEBUG                  LWIP_DBG_ON
-#define TCP_DEBUG                  LWIP_DBG_ON
-#define TCP_INPUT_DEBUG            LWIP_DBG_ON
-#define TCP_OUTPUT_DEBUG           LWIP_DBG_ON
-#define TCP_RST_DEBUG              LWIP_DBG_ON
-#define TCP_RTO_DEBUG              LWIP_DBG_ON
-#define TCP_CWND_DEBUG             LWIP_DBG_ON
+#define RAW_DEBUG                  (LWIP_DBG_ON | LWIP_RS_DBG_MODULE(1))
+#define PPP_DEBUG                  (LWIP_DBG_ON | LWIP_RS_DBG_MODULE(2))
+#define MEM_DEBUG                  (LWIP_DBG_ON | LWIP_RS_DBG_MODULE(3))
+#define MEMP_DEBUG                 (LWIP_DBG_ON | LWIP_RS_DBG_MODULE(4))
+#define PBUF_DEBUG                 (LWIP_DBG_ON | LWIP_RS_DBG_MODULE(5))
+#define API_LIB_DEBUG              (LWIP_DBG_ON | LWIP_RS_DBG_MODULE(6))
+#define API_MSG_DEBUG              (LWIP_DBG_ON | LWIP_RS_DBG_MODULE(7))
+#define TCPIP_DEBUG                (LWIP_DBG_ON | LWIP_RS_DBG_MODULE(8))
+#define NETIF_DEBUG                (LWIP_DBG_ON | LWIP_RS_DBG_MODULE(9))
+#define SOCKETS_DEBUG              (LWIP_DBG_ON | LWIP_RS_DBG_MODULE(10))
+#define DNS_DEBUG                  (LWIP_DBG_ON | LWIP_RS_DBG_MODULE(11))
+#define AUTOIP_DEBUG               (LWIP_DBG_ON | LWIP_RS_DBG_MODULE(12))
+#define DHCP_DEBUG                 (LWIP_DBG_ON | LWIP_RS_DBG_MODULE(13))
+#define IP_DEBUG                   (LWIP_DBG_ON | LWIP_RS_DBG_MODULE(14))
+#define IP_REASS_DEBUG             (LWIP_DBG_ON | LWIP_RS_DBG_MODULE(15))
+#define IP6_DEBUG                  (LWIP_DBG_ON | LWIP_RS_DBG_MODULE(16))
+#define ICMP_DEBUG                 (LWIP_DBG_ON | LWIP_RS_DBG_MODULE(17))
+#define IGMP_DEBUG                 (LWIP_DBG_ON | LWIP_RS_DBG_MODULE(18))
+#define UDP_DEBUG                  (LWIP_DBG_ON | LWIP_RS_DBG_MODULE(19))
+#define TCP_DEBUG                  (LWIP_DBG_ON | LWIP_RS_DBG_MODULE(20))
+#define TCP_INPUT_DEBUG            (LWIP_DBG_ON | LWIP_RS_DBG_MODULE(21))
+#define TCP_OUTPUT_DEBUG           (LWIP_DBG_ON | LWIP_RS_DBG_MODULE(22))
+#define TCP_RST_DEBUG              (LWIP_DBG_ON | LWIP_RS_DBG_MODULE(23))
+#define TCP_RTO_DEBUG              (LWIP_DBG_ON | LWIP_RS_DBG_MODULE(24))
+#define TCP_CWND_DEBUG             (LWIP_DBG_ON | LWIP_RS_DBG_MODULE(25))
 #endif
 
 #endif /* LWIP_CUSTOM_LWIPOPTS_H */
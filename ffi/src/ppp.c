@@ -0,0 +1,75 @@
+/* Build the lwIP PPP core so that its interface counts the packets it
+ * carries for IP, not the HDLC or PPPoE framing of the link: the output
+ * functions are wrapped once netif_add() set them, and input is counted as
+ * PPP hands it to IP. */
+#include "lwip/netif.h"
+
+static struct netif *ppp_netif_add_rs(struct netif *netif, const ip4_addr_t *ipaddr,
+                                      const ip4_addr_t *netmask, const ip4_addr_t *gw,
+                                      void *state, netif_init_fn init,
+                                      netif_input_fn input);
+err_t ppp_ip4_input_rs(struct pbuf *p, struct netif *inp);
+err_t ppp_ip6_input_rs(struct pbuf *p, struct netif *inp);
+
+#define netif_add ppp_netif_add_rs
+#define ip4_input ppp_ip4_input_rs
+#define ip6_input ppp_ip6_input_rs
+#include "../lwip/src/netif/ppp/ppp.c"
+#undef netif_add
+#undef ip4_input
+#undef ip6_input
+
+err_t ip4_input(struct pbuf *p, struct netif *inp);
+err_t ip6_input(struct pbuf *p, struct netif *inp);
+
+/* implemented in src/dev/netif.rs */
+extern void lwip_rs_netif_input(struct netif *netif, u16_t len);
+extern void lwip_rs_netif_output(struct netif *netif, u16_t len, err_t err);
+
+err_t
+ppp_ip4_input_rs(struct pbuf *p, struct netif *inp)
+{
+    lwip_rs_netif_input(inp, p->tot_len);
+    return ip4_input(p, inp);
+}
+
+err_t
+ppp_ip6_input_rs(struct pbuf *p, struct netif *inp)
+{
+    lwip_rs_netif_input(inp, p->tot_len);
+    return ip6_input(p, inp);
+}
+
+static err_t
+ppp_netif_output_ip4_rs(struct netif *netif, struct pbuf *p, const ip4_addr_t *ipaddr)
+{
+    u16_t len = p->tot_len;
+    err_t err = ppp_netif_output_ip4(netif, p, ipaddr);
+
+    lwip_rs_netif_output(netif, len, err);
+    return err;
+}
+
+static err_t
+ppp_netif_output_ip6_rs(struct netif *netif, struct pbuf *p, const ip6_addr_t *ipaddr)
+{
+    u16_t len = p->tot_len;
+    err_t err = ppp_netif_output_ip6(netif, p, ipaddr);
+
+    lwip_rs_netif_output(netif, len, err);
+    return err;
+}
+
+static struct netif *
+ppp_netif_add_rs(struct netif *netif, const ip4_addr_t *ipaddr, const ip4_addr_t *netmask,
+                 const ip4_addr_t *gw, void *state, netif_init_fn init, netif_input_fn input)
+{
+    if (netif_add(netif, ipaddr, netmask, gw, state, init, input) == NULL)
+    {
+        return NULL;
+    }
+
+    netif->output = ppp_netif_output_ip4_rs;
+    netif->output_ip6 = ppp_netif_output_ip6_rs;
+    return netif;
+}
@@ -0,0 +1,9 @@
+/* Build lwIP raw PCBs with an accessor to the static list of PCBs, used to
+ * list the connections of the stack. */
+#include "../lwip/src/core/raw.c"
+
+struct raw_pcb *
+raw_get_pcbs(void)
+{
+    return raw_pcbs;
+}
@@ -0,0 +1,29 @@
+/* Routing of the limited broadcast address.
+ *
+ * No interface route matches 255.255.255.255, and there is no default
+ * interface: it is sent from the interface with the source address the PCB
+ * is bound to. */
+
+#include "lwip/ip4_addr.h"
+#include "lwip/netif.h"
+
+/* LWIP_HOOK_IP4_ROUTE_SRC, src is NULL when called from ip4_route(). */
+struct netif *
+ip4_route_broadcast(const ip4_addr_t *src, const ip4_addr_t *dest)
+{
+    struct netif *netif;
+
+    if (src == NULL || ip4_addr_isany(src) || ip4_addr_get_u32(dest) != IPADDR_BROADCAST)
+    {
+        return NULL;
+    }
+
+    NETIF_FOREACH(netif)
+    {
+        if (netif_is_up(netif) && ip4_addr_cmp(src, netif_ip4_addr(netif)))
+        {
+            return netif;
+        }
+    }
+    return NULL;
+}
@@ -671,11 +671,42 @@ sys_mutex_free(struct sys_mutex **mutex)
 
 /*-----------------------------------------------------------------------------------*/
 /* Time */
+
+/* When enabled, sys_now() returns a virtual time that only moves forward
+ * through sys_virtual_clock_advance(). */
+static int virtual_clock_enabled = 0;
+static u32_t virtual_clock_now = 0;
+
+void
+sys_virtual_clock_enable(int enable)
+{
+  struct timespec ts;
+
+  if (enable && !__atomic_load_n(&virtual_clock_enabled, __ATOMIC_SEQ_CST)) {
+    /* start from the current time so pending timeouts stay ordered */
+    get_monotonic_time(&ts);
+    __atomic_store_n(&virtual_clock_now,
+                     (u32_t)(ts.tv_sec * 1000L + ts.tv_nsec / 1000000L),
+                     __ATOMIC_SEQ_CST);
+  }
+  __atomic_store_n(&virtual_clock_enabled, enable, __ATOMIC_SEQ_CST);
+}
+
+void
+sys_virtual_clock_advance(u32_t ms)
+{
+  __atomic_add_fetch(&virtual_clock_now, ms, __ATOMIC_SEQ_CST);
+}
+
 u32_t
 sys_now(void)
 {
   struct timespec ts;
 
+  if (__atomic_load_n(&virtual_clock_enabled, __ATOMIC_SEQ_CST)) {
+    return __atomic_load_n(&virtual_clock_now, __ATOMIC_SEQ_CST);
+  }
+
   get_monotonic_time(&ts);
   return (u32_t)(ts.tv_sec * 1000L + ts.tv_nsec / 1000000L);
 }
@@ -0,0 +1,205 @@
+/* Congestion control shim.
+ *
+ * lwIP updates cwnd inline in tcp_in.c. A controller attached to a PCB
+ * overrides the result: ACKs are seen through the PCB sent callback, which
+ * runs after lwIP's own update, and losses are noticed from the PCB state
+ * when the next segment comes in. RTT samples come from the echoed
+ * timestamps, lwIP itself only measures RTT in slow timer ticks. */
+
+#include <stdlib.h>
+
+#include "lwip/sys.h"
+#include "lwip/tcp.h"
+#include "lwip/priv/tcp_priv.h"
+
+enum tcp_cc_event
+{
+    TCP_CC_EVENT_ACK,
+    TCP_CC_EVENT_FAST_RETRANSMIT,
+    TCP_CC_EVENT_TIMEOUT,
+};
+
+typedef void (*tcp_cc_event_fn)(void *cc, struct tcp_pcb *pcb, enum tcp_cc_event event,
+                                u32_t acked, u32_t rtt);
+typedef void (*tcp_cc_free_fn)(void *cc);
+
+struct tcp_cc
+{
+    void *cc;
+    tcp_cc_event_fn event;
+    tcp_cc_free_fn free;
+    tcp_sent_fn sent;
+    u8_t nrtx;
+    u8_t in_recovery;
+    /* latest RTT sample in ms, 0 if none */
+    u32_t rtt;
+};
+
+static u8_t tcp_cc_id = LWIP_TCP_PCB_NUM_EXT_ARGS;
+
+static void
+tcp_cc_destroy(u8_t id, void *data)
+{
+    struct tcp_cc *s = (struct tcp_cc *)data;
+    LWIP_UNUSED_ARG(id);
+
+    if (s != NULL)
+    {
+        s->free(s->cc);
+        free(s);
+    }
+}
+
+static const struct tcp_ext_arg_callbacks tcp_cc_callbacks = {
+    tcp_cc_destroy,
+    NULL,
+};
+
+static struct tcp_cc *
+tcp_cc_get(struct tcp_pcb *pcb)
+{
+    if (tcp_cc_id == LWIP_TCP_PCB_NUM_EXT_ARGS)
+    {
+        return NULL;
+    }
+    return (struct tcp_cc *)tcp_ext_arg_get(pcb, tcp_cc_id);
+}
+
+static void
+tcp_cc_check_loss(struct tcp_pcb *pcb, struct tcp_cc *s)
+{
+    u8_t in_recovery = (pcb->flags & TF_INFR) != 0;
+
+    if (pcb->nrtx > s->nrtx)
+    {
+        s->event(s->cc, pcb, TCP_CC_EVENT_TIMEOUT, 0, 0);
+    }
+    else if (in_recovery && !s->in_recovery)
+    {
+        s->event(s->cc, pcb, TCP_CC_EVENT_FAST_RETRANSMIT, 0, 0);
+    }
+
+    s->nrtx = pcb->nrtx;
+    s->in_recovery = in_recovery;
+}
+
+/* Returns the TSecr of the timestamp option, 0 if absent. */
+static u32_t
+tcp_cc_tsecr(struct tcp_hdr *hdr, u16_t optlen, u16_t opt1len, u8_t *opt2)
+{
+    u8_t *opt1 = (u8_t *)hdr + TCP_HLEN;
+    u8_t opt[40];
+    u16_t i;
+
+    if (optlen > sizeof(opt))
+    {
+        return 0;
+    }
+    for (i = 0; i < optlen; i++)
+    {
+        opt[i] = i < opt1len ? opt1[i] : opt2[i - opt1len];
+    }
+
+    i = 0;
+    while (i < optlen)
+    {
+        if (opt[i] == LWIP_TCP_OPT_EOL)
+        {
+            break;
+        }
+        if (opt[i] == LWIP_TCP_OPT_NOP)
+        {
+            i++;
+            continue;
+        }
+        if (i + 1 >= optlen || opt[i + 1] < 2)
+        {
+            break;
+        }
+        if (opt[i] == LWIP_TCP_OPT_TS && opt[i + 1] == LWIP_TCP_OPT_LEN_TS && i + 10 <= optlen)
+        {
+            return ((u32_t)opt[i + 6] << 24) | ((u32_t)opt[i + 7] << 16) |
+                   ((u32_t)opt[i + 8] << 8) | (u32_t)opt[i + 9];
+        }
+        i += opt[i + 1];
+    }
+    return 0;
+}
+
+err_t
+tcp_cc_inpacket(struct tcp_pcb *pcb, struct tcp_hdr *hdr, u16_t optlen, u16_t opt1len,
+                u8_t *opt2)
+{
+    struct tcp_cc *s = tcp_cc_get(pcb);
+    u32_t tsecr;
+
+    if (s != NULL)
+    {
+        tcp_cc_check_loss(pcb, s);
+
+        /* our TSval is sys_now() */
+        tsecr = tcp_cc_tsecr(hdr, optlen, opt1len, opt2);
+        s->rtt = tsecr != 0 ? sys_now() - tsecr : 0;
+    }
+    return ERR_OK;
+}
+
+static err_t
+tcp_cc_sent(void *arg, struct tcp_pcb *pcb, u16_t len)
+{
+    struct tcp_cc *s = tcp_cc_get(pcb);
+
+    if (s == NULL)
+    {
+        return ERR_OK;
+    }
+
+    tcp_cc_check_loss(pcb, s);
+    s->event(s->cc, pcb, TCP_CC_EVENT_ACK, len, s->rtt);
+    s->rtt = 0;
+
+    return s->sent != NULL ? s->sent(arg, pcb, len) : ERR_OK;
+}
+
+/* Attaches a controller to an established PCB, replacing the previous one.
+ * Must be called with the core lock held. */
+err_t
+tcp_cc_set(struct tcp_pcb *pcb, void *cc, tcp_cc_event_fn event, tcp_cc_free_fn free_fn)
+{
+    struct tcp_cc *s;
+
+    LWIP_ASSERT_CORE_LOCKED();
+
+    if (tcp_cc_id == LWIP_TCP_PCB_NUM_EXT_ARGS)
+    {
+        tcp_cc_id = tcp_ext_arg_alloc_id();
+    }
+
+    s = tcp_cc_get(pcb);
+    if (s != NULL)
+    {
+        s->free(s->cc);
+    }
+    else
+    {
+        s = (struct tcp_cc *)malloc(sizeof(struct tcp_cc));
+        if (s == NULL)
+        {
+            return ERR_MEM;
+        }
+        s->sent = pcb->sent;
+        s->nrtx = pcb->nrtx;
+        s->in_recovery = (pcb->flags & TF_INFR) != 0;
+        s->rtt = 0;
+
+        tcp_ext_arg_set_callbacks(pcb, tcp_cc_id, &tcp_cc_callbacks);
+        tcp_ext_arg_set(pcb, tcp_cc_id, s);
+        tcp_sent(pcb, tcp_cc_sent);
+    }
+
+    s->cc = cc;
+    s->event = event;
+    s->free = free_fn;
+
+    return ERR_OK;
+}
@@ -0,0 +1,127 @@
+/* Retransmissions counted per interface.
+ *
+ * lwIP only counts them for the whole stack. A PCB's nrtx goes up with each
+ * retransmission and back to 0 once new data is acked, so it is looked at
+ * before an incoming segment is processed, around the TCP timers, which
+ * retransmit on timeouts, and when the PCB is freed. The retransmissions are
+ * counted on the interface the PCB sends through. */
+
+#include <stdlib.h>
+
+#include "lwip/ip.h"
+#include "lwip/netif.h"
+#include "lwip/tcp.h"
+#include "lwip/priv/tcp_priv.h"
+
+/* src/dev/netif.rs */
+void lwip_rs_netif_retransmits(struct netif *netif, u8_t n);
+
+struct tcp_retrans
+{
+    struct tcp_pcb *pcb;
+    /* nrtx when last looked at */
+    u8_t nrtx;
+};
+
+static u8_t tcp_retrans_id = LWIP_TCP_PCB_NUM_EXT_ARGS;
+
+static void
+tcp_retrans_count(struct tcp_retrans *s)
+{
+    struct tcp_pcb *pcb = s->pcb;
+    struct netif *netif;
+
+    if (pcb->nrtx > s->nrtx)
+    {
+        if (pcb->netif_idx != NETIF_NO_INDEX)
+        {
+            netif = netif_get_by_index(pcb->netif_idx);
+        }
+        else
+        {
+            netif = ip_route(&pcb->local_ip, &pcb->remote_ip);
+        }
+        if (netif != NULL)
+        {
+            lwip_rs_netif_retransmits(netif, pcb->nrtx - s->nrtx);
+        }
+    }
+    s->nrtx = pcb->nrtx;
+}
+
+static void
+tcp_retrans_destroy(u8_t id, void *data)
+{
+    struct tcp_retrans *s = (struct tcp_retrans *)data;
+    LWIP_UNUSED_ARG(id);
+
+    if (s != NULL)
+    {
+        tcp_retrans_count(s);
+        free(s);
+    }
+}
+
+static const struct tcp_ext_arg_callbacks tcp_retrans_callbacks = {
+    tcp_retrans_destroy,
+    NULL,
+};
+
+/* Counts the retransmissions of pcb since it was last looked at, starting to
+ * follow it if needed. */
+static void
+tcp_retrans_update(struct tcp_pcb *pcb)
+{
+    struct tcp_retrans *s;
+
+    if (pcb->state == LISTEN)
+    {
+        return;
+    }
+    if (tcp_retrans_id == LWIP_TCP_PCB_NUM_EXT_ARGS)
+    {
+        tcp_retrans_id = tcp_ext_arg_alloc_id();
+    }
+
+    s = (struct tcp_retrans *)tcp_ext_arg_get(pcb, tcp_retrans_id);
+    if (s != NULL)
+    {
+        tcp_retrans_count(s);
+        return;
+    }
+
+    s = (struct tcp_retrans *)malloc(sizeof(struct tcp_retrans));
+    if (s == NULL)
+    {
+        return;
+    }
+    s->pcb = pcb;
+    s->nrtx = pcb->nrtx;
+
+    tcp_ext_arg_set_callbacks(pcb, tcp_retrans_id, &tcp_retrans_callbacks);
+    tcp_ext_arg_set(pcb, tcp_retrans_id, s);
+}
+
+void
+tcp_retrans_inpacket(struct tcp_pcb *pcb)
+{
+    tcp_retrans_update(pcb);
+}
+
+/* Replaces tcp_tmr in the lwIP timers, see ffi/src/timeouts.c. */
+void
+tcp_tmr_rs(void)
+{
+    struct tcp_pcb *pcb;
+
+    for (pcb = tcp_active_pcbs; pcb != NULL; pcb = pcb->next)
+    {
+        tcp_retrans_update(pcb);
+    }
+    tcp_tmr();
+    /* the PCBs freed by the timers were counted then */
+    for (pcb = tcp_active_pcbs; pcb != NULL; pcb = pcb->next)
+    {
+        tcp_retrans_update(pcb);
+    }
+}
@@ -0,0 +1,13 @@
+/* Build the lwIP tcpip thread with a way to free its mailbox and the core
+ * lock once the thread stopped, so that restarting the stack does not leak
+ * them. */
+#include "../lwip/src/api/tcpip.c"
+
+void
+tcpip_free(void)
+{
+    sys_mbox_free(&tcpip_mbox);
+    sys_mbox_set_invalid(&tcpip_mbox);
+    sys_mutex_free(&lock_tcpip_core);
+    sys_mutex_set_invalid(&lock_tcpip_core);
+}
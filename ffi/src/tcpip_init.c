@@ -26,4 +26,175 @@ tcpip_init_block(void)
     sys_sem_free(&init_sem);
 
     return ERR_OK;
-}
\ No newline at end of file
+}
+#include "lwip/api.h"
+#include "lwip/dhcp.h"
+#include "lwip/memp.h"
+#include "lwip/netif.h"
+#include "lwip/raw.h"
+#include "lwip/udp.h"
+#include "lwip/priv/api_msg.h"
+#include "lwip/priv/tcp_priv.h"
+
+#include <pthread.h>
+
+/* Exposed by ffi/src/timeouts.c */
+struct sys_timeo **sys_timeouts_get_next_timeout(void);
+
+/* Exposed by ffi/src/raw.c */
+struct raw_pcb *raw_get_pcbs(void);
+
+/* Exposed by ffi/src/tcpip.c */
+void tcpip_free(void);
+
+/* Detaches a netconn from its PCB and reports ERR_ABRT to its owner.
+ * Must be called with the core lock held. */
+void
+netconn_abort_pcb(struct netconn *conn)
+{
+    if (conn->pcb.tcp == NULL)
+    {
+        return;
+    }
+
+    switch (NETCONNTYPE_GROUP(conn->type))
+    {
+    case NETCONN_TCP:
+        if (conn->pcb.tcp->state == LISTEN)
+        {
+            tcp_arg(conn->pcb.tcp, NULL);
+            tcp_accept(conn->pcb.tcp, NULL);
+            tcp_close(conn->pcb.tcp);
+        }
+        else
+        {
+            /* err_tcp() takes care of the netconn */
+            tcp_abort(conn->pcb.tcp);
+            return;
+        }
+        break;
+    case NETCONN_RAW:
+        raw_remove(conn->pcb.raw);
+        break;
+    case NETCONN_UDP:
+        udp_remove(conn->pcb.udp);
+        break;
+    default:
+        return;
+    }
+
+    conn->pcb.tcp = NULL;
+    conn->pending_err = ERR_ABRT;
+    if (conn->callback != NULL)
+    {
+        conn->callback(conn, NETCONN_EVT_ERROR, 0);
+    }
+}
+
+/* Number of TCP connections closing their side, waiting for the FIN or
+ * its ACK. */
+int
+tcpip_closing_pcbs(void)
+{
+    struct tcp_pcb *pcb;
+    int n = 0;
+
+    for (pcb = tcp_active_pcbs; pcb != NULL; pcb = pcb->next)
+    {
+        switch (pcb->state)
+        {
+        case FIN_WAIT_1:
+        case FIN_WAIT_2:
+        case CLOSING:
+        case LAST_ACK:
+            n++;
+            break;
+        default:
+            break;
+        }
+    }
+    return n;
+}
+
+static void
+tcpip_shutdown_priv(void *arg)
+{
+    sys_sem_t *shutdown_sem = (sys_sem_t *)arg;
+    struct sys_timeo **timeouts;
+    struct raw_pcb *raw;
+
+    /* the netconns were detached already, what is left are connections
+     * closing without their netconn and the PCBs of the servers */
+    while (tcp_active_pcbs != NULL)
+    {
+        tcp_abort(tcp_active_pcbs);
+    }
+    while (tcp_tw_pcbs != NULL)
+    {
+        tcp_abort(tcp_tw_pcbs);
+    }
+    while (tcp_bound_pcbs != NULL)
+    {
+        tcp_abort(tcp_bound_pcbs);
+    }
+    while (tcp_listen_pcbs.pcbs != NULL)
+    {
+        tcp_arg(tcp_listen_pcbs.pcbs, NULL);
+        tcp_accept(tcp_listen_pcbs.pcbs, NULL);
+        tcp_close(tcp_listen_pcbs.pcbs);
+    }
+
+    while (netif_list != NULL)
+    {
+        dhcp_cleanup(netif_list);
+        netif_remove(netif_list);
+    }
+
+    while (udp_pcbs != NULL)
+    {
+        udp_remove(udp_pcbs);
+    }
+    while ((raw = raw_get_pcbs()) != NULL)
+    {
+        raw_remove(raw);
+    }
+
+    /* drop all pending timeouts, lwip_init() registers the cyclic ones
+     * again on the next start */
+    timeouts = sys_timeouts_get_next_timeout();
+    while (*timeouts != NULL)
+    {
+        struct sys_timeo *t = *timeouts;
+        *timeouts = t->next;
+        memp_free(MEMP_SYS_TIMEOUT, t);
+    }
+
+    /* the tcpip thread holds the core lock while running callbacks */
+    UNLOCK_TCPIP_CORE();
+    sys_sem_signal(shutdown_sem);
+    pthread_exit(NULL);
+}
+
+err_t
+tcpip_shutdown_block(void)
+{
+    err_t err;
+    sys_sem_t shutdown_sem;
+
+    err = sys_sem_new(&shutdown_sem, 0);
+    if (err != ERR_OK)
+    {
+        return err;
+    }
+
+    err = tcpip_callback(tcpip_shutdown_priv, &shutdown_sem);
+    if (err == ERR_OK)
+    {
+        sys_sem_wait(&shutdown_sem);
+        /* tcpip_init() creates new ones on the next start */
+        tcpip_free();
+    }
+    sys_sem_free(&shutdown_sem);
+
+    return err;
+}
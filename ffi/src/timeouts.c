@@ -0,0 +1,30 @@
+/* Build lwIP timeouts with LWIP_TESTMODE so that the list of pending
+ * timeouts can be cleared on stack shutdown. */
+#define LWIP_TESTMODE 1
+/* the ND6 timer of the cyclic timers, see nd6_tmr_rs below */
+#define nd6_tmr nd6_tmr_rs
+/* counts the retransmissions of the TCP timers, see ffi/src/tcp_retrans.c */
+#define tcp_tmr tcp_tmr_rs
+#include "../lwip/src/core/timeouts.c"
+#undef nd6_tmr
+#undef tcp_tmr
+
+void nd6_tmr(void);
+
+/* Router solicitations are only sent by the interfaces doing IPv6
+ * autoconfiguration: lwIP arms rs_count again whenever an interface comes
+ * up, and only this timer sends them. */
+void
+nd6_tmr_rs(void)
+{
+    struct netif *netif;
+
+    NETIF_FOREACH(netif)
+    {
+        if (!netif->ip6_autoconfig_enabled)
+        {
+            netif->rs_count = 0;
+        }
+    }
+    nd6_tmr();
+}
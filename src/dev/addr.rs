@@ -0,0 +1,154 @@
+use std::net::{IpAddr, Ipv6Addr};
+use std::time::Duration;
+
+use ipnetwork::{Ipv4Network, Ipv6Network};
+
+use crate::lwip;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Ipv6AddrState {
+    Invalid,
+    Tentative,
+    Preferred,
+    Deprecated,
+    Duplicated,
+}
+
+impl Ipv6AddrState {
+    pub(crate) fn value(&self) -> u8 {
+        match *self {
+            Ipv6AddrState::Invalid => lwip::IP6_ADDR_INVALID as u8,
+            Ipv6AddrState::Tentative => lwip::IP6_ADDR_TENTATIVE as u8,
+            Ipv6AddrState::Preferred => lwip::IP6_ADDR_PREFERRED as u8,
+            Ipv6AddrState::Deprecated => lwip::IP6_ADDR_DEPRECATED as u8,
+            Ipv6AddrState::Duplicated => lwip::IP6_ADDR_DUPLICATED as u8,
+        }
+    }
+
+    pub(crate) fn from_value(value: u8) -> Self {
+        let value = value as u32;
+        if value == lwip::IP6_ADDR_PREFERRED {
+            Ipv6AddrState::Preferred
+        } else if value == lwip::IP6_ADDR_DEPRECATED {
+            Ipv6AddrState::Deprecated
+        } else if value == lwip::IP6_ADDR_DUPLICATED {
+            Ipv6AddrState::Duplicated
+        } else if value & lwip::IP6_ADDR_TENTATIVE != 0 {
+            // TENTATIVE_1..7 count the DAD probes already sent.
+            Ipv6AddrState::Tentative
+        } else {
+            Ipv6AddrState::Invalid
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Lifetime {
+    // never expired by the ND timer
+    Static,
+    Infinite,
+    Finite(Duration),
+}
+
+impl Lifetime {
+    pub(crate) fn value(&self) -> u32 {
+        match *self {
+            Lifetime::Static => lwip::IP6_ADDR_LIFE_STATIC,
+            Lifetime::Infinite => lwip::IP6_ADDR_LIFE_INFINITE,
+            // 0 and u32::MAX are reserved for static and infinite.
+            Lifetime::Finite(d) => d.as_secs().max(1).min(std::u32::MAX as u64 - 1) as u32,
+        }
+    }
+
+    pub(crate) fn from_value(value: u32) -> Self {
+        if value == lwip::IP6_ADDR_LIFE_STATIC {
+            Lifetime::Static
+        } else if value == lwip::IP6_ADDR_LIFE_INFINITE {
+            Lifetime::Infinite
+        } else {
+            Lifetime::Finite(Duration::from_secs(value as u64))
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Ipv6AddrInfo {
+    pub addr: Ipv6Addr,
+    pub state: Ipv6AddrState,
+    pub valid_life: Lifetime,
+    pub preferred_life: Lifetime,
+}
+
+impl Ipv6AddrInfo {
+    pub fn new(addr: Ipv6Addr) -> Self {
+        Ipv6AddrInfo {
+            addr,
+            state: Ipv6AddrState::Preferred,
+            valid_life: Lifetime::Static,
+            preferred_life: Lifetime::Static,
+        }
+    }
+
+    pub fn state(mut self, state: Ipv6AddrState) -> Self {
+        self.state = state;
+        self
+    }
+
+    pub fn valid_life(mut self, life: Lifetime) -> Self {
+        self.valid_life = life;
+        self
+    }
+
+    pub fn preferred_life(mut self, life: Lifetime) -> Self {
+        self.preferred_life = life;
+        self
+    }
+}
+
+impl From<Ipv6Addr> for Ipv6AddrInfo {
+    fn from(addr: Ipv6Addr) -> Self {
+        Ipv6AddrInfo::new(addr)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NetIfAddr {
+    V4(Ipv4Network),
+    V6(Ipv6AddrInfo),
+}
+
+impl NetIfAddr {
+    pub fn ip(&self) -> IpAddr {
+        match *self {
+            NetIfAddr::V4(net) => IpAddr::V4(net.ip()),
+            NetIfAddr::V6(info) => IpAddr::V6(info.addr),
+        }
+    }
+}
+
+/// A default router learned from router advertisements.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Ipv6Router {
+    pub addr: Ipv6Addr,
+    /// Time left before the router expires.
+    pub lifetime: Duration,
+}
+
+/// An on-link prefix learned from router advertisements.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Ipv6Prefix {
+    pub prefix: Ipv6Network,
+    /// Time left before the prefix expires.
+    pub valid_life: Duration,
+}
+
+/// IPv6 configuration learned by an interface in autoconfiguration mode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ipv6Autoconf {
+    /// Generated from the interface MAC address.
+    pub link_local: Option<Ipv6AddrInfo>,
+    /// Addresses formed from the advertised prefixes (SLAAC).
+    pub addrs: Vec<Ipv6AddrInfo>,
+    pub prefixes: Vec<Ipv6Prefix>,
+    pub routers: Vec<Ipv6Router>,
+}
@@ -12,6 +12,29 @@ pub trait Device {
     fn ipv4(&self) -> Ipv4Network;
     fn ipv6(&self) -> Vec<Ipv6Network>;
     fn mtu(&self) -> u16;
+
+    /// MAC address of the interface, a random locally administered one is
+    /// used if none.
+    fn hwaddr(&self) -> Option<[u8; 6]> {
+        None
+    }
+
+    /// Whether the device carries Ethernet frames, such as a TAP device,
+    /// instead of IP packets.
+    fn ethernet(&self) -> bool {
+        false
+    }
+
+    /// Whether the IPv4 address is obtained through DHCP.
+    fn dhcp(&self) -> bool {
+        false
+    }
+
+    /// Whether IPv6 is brought up with a link-local address, DAD, router
+    /// solicitations and SLAAC.
+    fn ipv6_autoconfig(&self) -> bool {
+        false
+    }
 }
 
 #[derive(Debug)]
@@ -19,6 +42,10 @@ pub struct DeviceBuilder {
     mtu: u16,
     ipv4: Ipv4Network,
     ipv6: Vec<Ipv6Network>,
+    hwaddr: Option<[u8; 6]>,
+    ethernet: bool,
+    dhcp: bool,
+    ipv6_autoconfig: bool,
 }
 
 impl Default for DeviceBuilder {
@@ -27,6 +54,10 @@ impl Default for DeviceBuilder {
             mtu: 1500,
             ipv4: Ipv4Network::new(Ipv4Addr::UNSPECIFIED, 0).unwrap(),
             ipv6: Vec::new(),
+            hwaddr: None,
+            ethernet: false,
+            dhcp: false,
+            ipv6_autoconfig: false,
         }
     }
 }
@@ -56,6 +87,41 @@ impl DeviceBuilder {
         self
     }
 
+    pub fn hwaddr(mut self, hwaddr: [u8; 6]) -> Self {
+        self.hwaddr = Some(hwaddr);
+        self
+    }
+
+    /// Exchanges Ethernet frames with the device, such as a TAP device,
+    /// instead of IP packets: lwIP resolves its neighbours with ARP and
+    /// neighbor discovery and answers them. The `hwaddr` is the MAC address
+    /// of the frames sent.
+    pub fn ethernet(mut self) -> Self {
+        self.ethernet = true;
+        self
+    }
+
+    /// Runs a DHCP client on the interface instead of using the `ipv4`
+    /// address. The lease is reported through `NetIf::watch`.
+    ///
+    /// DHCP servers expect to reach their clients over Ethernet, see
+    /// `ethernet`. Over a device carrying IP packets the server must answer
+    /// by broadcast or to the offered address.
+    pub fn dhcp(mut self) -> Self {
+        self.dhcp = true;
+        self
+    }
+
+    /// Brings IPv6 up like a host would: a link-local address is generated
+    /// from the MAC address, the `ipv6` addresses go through duplicate
+    /// address detection, routers are solicited and their advertisements
+    /// configure addresses (SLAAC) and default routes. See
+    /// `NetIf::ipv6_autoconf`.
+    pub fn ipv6_autoconfig(mut self) -> Self {
+        self.ipv6_autoconfig = true;
+        self
+    }
+
     pub fn build<D: AsyncRead + AsyncWrite>(
         self,
         underlying: D,
@@ -117,16 +183,62 @@ where
     }
 }
 
+impl Device for DeviceBuilder {
+    fn ipv4(&self) -> Ipv4Network {
+        self.ipv4
+    }
+
+    fn ipv6(&self) -> Vec<Ipv6Network> {
+        self.ipv6.clone()
+    }
+
+    fn mtu(&self) -> u16 {
+        self.mtu
+    }
+
+    fn hwaddr(&self) -> Option<[u8; 6]> {
+        self.hwaddr
+    }
+
+    fn ethernet(&self) -> bool {
+        self.ethernet
+    }
+
+    fn dhcp(&self) -> bool {
+        self.dhcp
+    }
+
+    fn ipv6_autoconfig(&self) -> bool {
+        self.ipv6_autoconfig
+    }
+}
+
 impl<D> Device for DeviceWrapper<D> {
     fn ipv4(&self) -> Ipv4Network {
-        self.builder.ipv4
+        Device::ipv4(&self.builder)
     }
 
     fn ipv6(&self) -> Vec<Ipv6Network> {
-        self.builder.ipv6.clone()
+        Device::ipv6(&self.builder)
     }
 
     fn mtu(&self) -> u16 {
-        self.builder.mtu
+        Device::mtu(&self.builder)
+    }
+
+    fn hwaddr(&self) -> Option<[u8; 6]> {
+        Device::hwaddr(&self.builder)
+    }
+
+    fn ethernet(&self) -> bool {
+        Device::ethernet(&self.builder)
+    }
+
+    fn dhcp(&self) -> bool {
+        Device::dhcp(&self.builder)
+    }
+
+    fn ipv6_autoconfig(&self) -> bool {
+        Device::ipv6_autoconfig(&self.builder)
     }
 }
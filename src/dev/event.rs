@@ -0,0 +1,58 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures::Stream;
+use ipnetwork::Ipv4Network;
+use tokio::sync::mpsc;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NetIfEvent {
+    Up,
+    Down,
+    LinkUp,
+    LinkDown,
+    AddrAdded(IpAddr),
+    AddrRemoved(IpAddr),
+    DadFinished {
+        addr: Ipv6Addr,
+        duplicate: bool,
+    },
+    /// A DHCP lease was obtained, renewed or rebound.
+    DhcpBound(DhcpLease),
+    /// The renewal time passed, the lease is being renewed with its server.
+    DhcpRenewing,
+    /// The server did not answer before the rebinding time, the lease is
+    /// being requested from any server.
+    DhcpRebinding,
+    /// The lease expired, was refused or released: the address was given
+    /// up and, unless the client was stopped, a new lease is requested.
+    DhcpLost,
+    /// A multicast group was joined on the interface, its packets are now
+    /// accepted.
+    MulticastJoined(IpAddr),
+    MulticastLeft(IpAddr),
+    Removed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DhcpLease {
+    pub addr: Ipv4Network,
+    pub router: Option<Ipv4Addr>,
+    pub dns: Vec<Ipv4Addr>,
+    pub lease_time: Duration,
+    pub renew_time: Duration,
+    pub rebind_time: Duration,
+}
+
+#[derive(Debug)]
+pub struct NetIfWatch(pub(crate) mpsc::UnboundedReceiver<NetIfEvent>);
+
+impl Stream for NetIfWatch {
+    type Item = NetIfEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.0.poll_recv(cx)
+    }
+}
@@ -0,0 +1,96 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::io::AsyncWrite;
+
+/// Write side of the devices framing packets over a byte stream.
+///
+/// Like a sink, a packet is taken as soon as the frame of the previous one
+/// is out, and its frame is written to the stream by the following writes
+/// and flushes.
+#[derive(Debug, Default)]
+pub(crate) struct FrameWriter {
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl FrameWriter {
+    /// Takes a packet of `len` bytes once the previous frame is written,
+    /// `encode` appends its frame to the empty buffer.
+    pub fn poll_write<T, F>(
+        &mut self,
+        mut underlying: Pin<&mut T>,
+        cx: &mut Context<'_>,
+        len: usize,
+        encode: F,
+    ) -> Poll<io::Result<usize>>
+    where
+        T: AsyncWrite,
+        F: FnOnce(&mut Vec<u8>),
+    {
+        match self.poll_drain(underlying.as_mut(), cx) {
+            Poll::Ready(Ok(())) => {}
+            Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+            Poll::Pending => return Poll::Pending,
+        }
+
+        encode(&mut self.buf);
+        // as much as the stream takes right away
+        match self.poll_drain(underlying, cx) {
+            Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+            _ => Poll::Ready(Ok(len)),
+        }
+    }
+
+    pub fn poll_flush<T>(
+        &mut self,
+        mut underlying: Pin<&mut T>,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>>
+    where
+        T: AsyncWrite,
+    {
+        match self.poll_drain(underlying.as_mut(), cx) {
+            Poll::Ready(Ok(())) => underlying.poll_flush(cx),
+            poll => poll,
+        }
+    }
+
+    pub fn poll_shutdown<T>(
+        &mut self,
+        mut underlying: Pin<&mut T>,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>>
+    where
+        T: AsyncWrite,
+    {
+        match self.poll_drain(underlying.as_mut(), cx) {
+            Poll::Ready(Ok(())) => underlying.poll_shutdown(cx),
+            poll => poll,
+        }
+    }
+
+    /// Writes the rest of the pending frame.
+    fn poll_drain<T>(
+        &mut self,
+        mut underlying: Pin<&mut T>,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>>
+    where
+        T: AsyncWrite,
+    {
+        while self.pos < self.buf.len() {
+            match underlying.as_mut().poll_write(cx, &self.buf[self.pos..]) {
+                Poll::Ready(Ok(0)) => return Poll::Ready(Err(io::ErrorKind::WriteZero.into())),
+                Poll::Ready(Ok(len)) => self.pos += len,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+
+        self.buf.clear();
+        self.pos = 0;
+        Poll::Ready(Ok(()))
+    }
+}
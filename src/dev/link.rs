@@ -0,0 +1,320 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::time::{delay_until, Delay, Instant};
+
+#[derive(Debug, Clone)]
+pub struct LinkBuilder {
+    latency: Duration,
+    jitter: Duration,
+    loss: f64,
+    duplicate: f64,
+    reorder: f64,
+    rate: Option<u64>,
+    mtu: usize,
+    seed: Option<u64>,
+}
+
+impl Default for LinkBuilder {
+    fn default() -> Self {
+        LinkBuilder {
+            latency: Duration::from_secs(0),
+            jitter: Duration::from_secs(0),
+            loss: 0.0,
+            duplicate: 0.0,
+            reorder: 0.0,
+            rate: None,
+            mtu: std::u16::MAX as usize,
+            seed: None,
+        }
+    }
+}
+
+impl LinkBuilder {
+    pub fn latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    // uniformly distributed on top of the latency
+    pub fn jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    pub fn loss(mut self, probability: f64) -> Self {
+        self.loss = probability;
+        self
+    }
+
+    pub fn duplicate(mut self, probability: f64) -> Self {
+        self.duplicate = probability;
+        self
+    }
+
+    // reordered packets skip the latency and overtake the ones in flight,
+    // like netem does.
+    pub fn reorder(mut self, probability: f64) -> Self {
+        self.reorder = probability;
+        self
+    }
+
+    // in bits per second, 0 for unlimited
+    pub fn rate(mut self, bps: u64) -> Self {
+        self.rate = if bps > 0 { Some(bps) } else { None };
+        self
+    }
+
+    // larger packets are dropped
+    pub fn mtu(mut self, mtu: usize) -> Self {
+        self.mtu = mtu;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn build(self) -> (LinkEnd, LinkEnd) {
+        let seed = self.seed.unwrap_or_else(rand::random);
+        let config = Arc::new(self);
+
+        let dir0 = Arc::new(Mutex::new(LinkQueue::new(seed)));
+        let dir1 = Arc::new(Mutex::new(LinkQueue::new(seed.wrapping_add(1))));
+
+        (
+            LinkEnd {
+                config: config.clone(),
+                tx: dir0.clone(),
+                rx: dir1.clone(),
+                delay: None,
+            },
+            LinkEnd {
+                config: config,
+                tx: dir1,
+                rx: dir0,
+                delay: None,
+            },
+        )
+    }
+}
+
+pub struct Link;
+
+impl Link {
+    pub fn new() -> (LinkEnd, LinkEnd) {
+        Self::builder().build()
+    }
+
+    pub fn builder() -> LinkBuilder {
+        LinkBuilder::default()
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct LinkStats {
+    pub sent: u64,
+    pub delivered: u64,
+    pub lost: u64,
+    pub oversized: u64,
+    pub duplicated: u64,
+    pub reordered: u64,
+}
+
+#[derive(Debug)]
+struct InFlight {
+    at: Instant,
+    seq: u64,
+    pkt: Vec<u8>,
+}
+
+impl PartialEq for InFlight {
+    fn eq(&self, other: &Self) -> bool {
+        (self.at, self.seq) == (other.at, other.seq)
+    }
+}
+
+impl Eq for InFlight {}
+
+impl PartialOrd for InFlight {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for InFlight {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.at, self.seq).cmp(&(other.at, other.seq))
+    }
+}
+
+#[derive(Debug)]
+struct LinkQueue {
+    queue: BinaryHeap<Reverse<InFlight>>,
+    seq: u64,
+    // when the serialisation of the last packet ends
+    busy_until: Option<Instant>,
+    rng: StdRng,
+    task: Option<Waker>,
+    stats: LinkStats,
+}
+
+impl LinkQueue {
+    fn new(seed: u64) -> Self {
+        LinkQueue {
+            queue: BinaryHeap::new(),
+            seq: 0,
+            busy_until: None,
+            rng: StdRng::seed_from_u64(seed),
+            task: None,
+            stats: LinkStats::default(),
+        }
+    }
+
+    fn push(&mut self, config: &LinkBuilder, pkt: &[u8]) {
+        self.stats.sent += 1;
+
+        if pkt.len() > config.mtu {
+            self.stats.oversized += 1;
+            return;
+        }
+
+        let now = Instant::now();
+        let mut sent_at = now;
+        if let Some(rate) = config.rate {
+            let start = self.busy_until.map(|t| t.max(now)).unwrap_or(now);
+            let tx = Duration::from_secs_f64(pkt.len() as f64 * 8.0 / rate as f64);
+            sent_at = start + tx;
+            self.busy_until = Some(sent_at);
+        }
+
+        if config.loss > 0.0 && self.rng.gen_bool(config.loss.min(1.0)) {
+            self.stats.lost += 1;
+            return;
+        }
+
+        let copies = if config.duplicate > 0.0 && self.rng.gen_bool(config.duplicate.min(1.0)) {
+            self.stats.duplicated += 1;
+            2
+        } else {
+            1
+        };
+
+        for _ in 0..copies {
+            let at = if config.reorder > 0.0 && self.rng.gen_bool(config.reorder.min(1.0)) {
+                self.stats.reordered += 1;
+                sent_at
+            } else {
+                let jitter = if config.jitter > Duration::from_secs(0) {
+                    config.jitter.mul_f64(self.rng.gen::<f64>())
+                } else {
+                    Duration::from_secs(0)
+                };
+                sent_at + config.latency + jitter
+            };
+
+            self.seq += 1;
+            self.queue.push(Reverse(InFlight {
+                at,
+                seq: self.seq,
+                pkt: pkt.to_vec(),
+            }));
+        }
+
+        if let Some(task) = self.task.take() {
+            task.wake();
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct LinkEnd {
+    config: Arc<LinkBuilder>,
+    tx: Arc<Mutex<LinkQueue>>,
+    rx: Arc<Mutex<LinkQueue>>,
+    delay: Option<Delay>,
+}
+
+impl LinkEnd {
+    // statistics of the packets sent from this end
+    pub fn stats(&self) -> LinkStats {
+        self.tx.lock().unwrap().stats
+    }
+}
+
+impl AsyncRead for LinkEnd {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        loop {
+            let mut rx = this.rx.lock().unwrap();
+
+            let at = match rx.queue.peek() {
+                Some(Reverse(head)) => head.at,
+                None => {
+                    rx.task = Some(cx.waker().clone());
+                    return Poll::Pending;
+                }
+            };
+
+            if at <= Instant::now() {
+                let Reverse(head) = rx.queue.pop().unwrap();
+                rx.stats.delivered += 1;
+                this.delay = None;
+
+                let len = head.pkt.len().min(buf.len());
+                buf[..len].copy_from_slice(&head.pkt[..len]);
+                return Poll::Ready(Ok(len));
+            }
+
+            // a new packet may be due earlier than the current head.
+            rx.task = Some(cx.waker().clone());
+            drop(rx);
+
+            match &mut this.delay {
+                Some(delay) if delay.deadline() == at => {}
+                delay => *delay = Some(delay_until(at)),
+            }
+
+            match Pin::new(this.delay.as_mut().unwrap()).poll(cx) {
+                Poll::Ready(()) => continue,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl AsyncWrite for LinkEnd {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        let mut tx = self.tx.lock().unwrap();
+
+        tx.push(&self.config, buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        Poll::Ready(Ok(()))
+    }
+}
@@ -1,8 +1,34 @@
 mod device;
 pub use self::device::*;
 
+mod addr;
+pub use self::addr::*;
+
+mod event;
+pub use self::event::*;
+
 mod netif;
 pub use self::netif::*;
 
 mod loopback;
 pub use self::loopback::*;
+
+mod link;
+pub use self::link::*;
+
+mod ppp;
+pub use self::ppp::*;
+
+mod frame;
+
+mod slip;
+pub use self::slip::*;
+
+mod tunnel;
+pub use self::tunnel::*;
+
+mod pcap;
+pub use self::pcap::*;
+
+mod replay;
+pub use self::replay::*;
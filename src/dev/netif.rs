@@ -1,25 +1,87 @@
+use std::future::Future;
 use std::io::{self, Read};
 use std::mem;
-use std::net::Ipv4Addr;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::pin::Pin;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, Once};
 use std::task::{Context, Poll};
+use std::time::Duration;
 
 use bytes::{Bytes, BytesMut};
+use ipnetwork::{ipv4_mask_to_prefix, Ipv4Network, Ipv6Network};
 use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::sync::mpsc;
 use transfer_async::{transfer, Transfer};
 
 use crate::lwip::{self, FromPbuf, IntoPbuf};
-use crate::Device;
+use crate::stats::NetIfCounters;
+use crate::{
+    Capture, Device, DhcpLease, Ipv6AddrInfo, Ipv6AddrState, Ipv6Autoconf, Ipv6Prefix, Ipv6Router,
+    Lifetime, NetIfAddr, NetIfEvent, NetIfStats, NetIfWatch, PcapFormat,
+};
+
+const PBUF_FLAG_MCASTLOOP: u8 = 0x04; /* TODO: bindgen skips casted defines */
+const NETIF_FLAGS_ETHERNET: u8 = (lwip::NETIF_FLAG_ETHARP | lwip::NETIF_FLAG_ETHERNET) as u8;
+
+static NETIF_EXT_CALLBACK_ONCE: Once = Once::new();
+static mut NETIF_EXT_CALLBACK: lwip::netif_ext_callback_t = lwip::netif_ext_callback_t {
+    callback_fn: None,
+    next: std::ptr::null_mut(),
+};
+// slot of the NetIfCState in the netif client data, lwIP interfaces such as
+// PPP ones use `state` themselves
+static mut NETIF_CLIENT_DATA_ID: u8 = 0;
+
+fn netif_setup() {
+    crate::stack::stack_init();
+    NETIF_EXT_CALLBACK_ONCE.call_once(|| {
+        lwip::with_core_lock(|| unsafe {
+            lwip::netif_add_ext_callback(&mut NETIF_EXT_CALLBACK, Some(netif_ext_callback));
+            NETIF_CLIENT_DATA_ID = lwip::netif_alloc_client_data_id();
+        })
+    });
+}
+
+/// Null if not one of ours, or not fully initialised yet.
+unsafe fn netif_cstate(netif: *mut lwip::netif) -> *mut NetIfCState {
+    (*netif).client_data[NETIF_CLIENT_DATA_ID as usize] as *mut NetIfCState
+}
+
+unsafe fn netif_set_cstate(netif: *mut lwip::netif, state: *mut NetIfCState) {
+    (*netif).client_data[NETIF_CLIENT_DATA_ID as usize] = state as *mut _;
+}
 
 #[derive(Debug)]
-struct NetIfCState(Arc<Mutex<mpsc::UnboundedSender<Bytes>>>);
+struct NetIfCState {
+    tx: Arc<Mutex<mpsc::UnboundedSender<Bytes>>>,
+    watchers: Mutex<Vec<mpsc::UnboundedSender<NetIfEvent>>>,
+    counters: Arc<NetIfCounters>,
+    dhcp: Mutex<DhcpState>,
+    // address slot whose state is being set by add_addr: it leaves the
+    // tentative state without DAD
+    skip_dad: Mutex<Option<usize>>,
+}
+
+#[derive(Debug, Default)]
+struct DhcpState {
+    lease: Option<DhcpLease>,
+    renewing: bool,
+    rebinding: bool,
+}
+
+impl NetIfCState {
+    fn notify(&self, event: NetIfEvent) {
+        let mut watchers = self.watchers.lock().unwrap();
+        watchers.retain(|w| w.send(event.clone()).is_ok());
+    }
+}
 
 #[derive(Debug)]
 struct NetIfInner {
     pcb: *mut lwip::netif,
+    generation: usize,
     rx: mpsc::UnboundedReceiver<Bytes>,
+    counters: Arc<NetIfCounters>,
 }
 
 #[derive(Debug)]
@@ -27,15 +89,114 @@ pub struct NetIf {
     inner: Arc<Mutex<NetIfInner>>,
 }
 
+/// Queues `pkt` for the device, it is read through `AsyncRead`. Called
+/// with the core lock held.
+pub(crate) unsafe fn netif_queue_output(netif: *mut lwip::netif, pkt: Bytes) -> lwip::err_t {
+    let len = pkt.len();
+    let ret = netif_queue_frame(netif, pkt);
+    count_output(&*netif_cstate(netif), len, ret);
+    ret
+}
+
+/// Queues the link framing of a driver for the device, such as the HDLC
+/// frames of PPP. It is not counted: the driver counts the packets of the
+/// interface, see `lwip_rs_netif_output`. Called with the core lock held.
+pub(crate) unsafe fn netif_queue_frame(netif: *mut lwip::netif, pkt: Bytes) -> lwip::err_t {
+    let state = &*netif_cstate(netif);
+    let tx = state.tx.lock().unwrap();
+
+    // send to channel:
+    if tx.send(pkt).is_err() {
+        return lwip::err_enum_t::ERR_IF;
+    }
+    lwip::err_enum_t::ERR_OK
+}
+
+fn count_output(state: &NetIfCState, len: usize, ret: lwip::err_t) {
+    if ret == lwip::err_enum_t::ERR_OK {
+        state.counters.output(len);
+    } else {
+        state.counters.output_drop();
+    }
+}
+
+/// Counts a packet handed to IP by a driver that does not go through
+/// `netif_input`, such as PPP (see ffi/src/ppp.c). Runs in the tcpip thread.
+#[no_mangle]
+pub unsafe extern "C" fn lwip_rs_netif_input(netif: *mut lwip::netif, len: u16) {
+    let ptr = netif_cstate(netif);
+    if !ptr.is_null() {
+        (*ptr).counters.input(len as usize);
+    }
+}
+
+/// Counts a packet sent by a driver that frames it itself, see
+/// `netif_queue_frame`. Called with the core lock held.
+#[no_mangle]
+pub unsafe extern "C" fn lwip_rs_netif_output(netif: *mut lwip::netif, len: u16, err: lwip::err_t) {
+    let ptr = netif_cstate(netif);
+    if !ptr.is_null() {
+        count_output(&*ptr, len as usize, err);
+    }
+}
+
+/// Counts the TCP retransmissions of a PCB routed through `netif`, see
+/// ffi/src/tcp_retrans.c. Called with the core lock held.
+#[no_mangle]
+pub unsafe extern "C" fn lwip_rs_netif_retransmits(netif: *mut lwip::netif, n: u8) {
+    let ptr = netif_cstate(netif);
+    if !ptr.is_null() {
+        (*ptr).counters.retransmits(n as u32);
+    }
+}
+
+/// Checksum errors counted by lwIP so far, with the core lock held.
+unsafe fn checksum_errors() -> u32 {
+    let s = &lwip::lwip_stats;
+    [s.ip.chkerr, s.icmp6.chkerr, s.udp.chkerr, s.tcp.chkerr]
+        .iter()
+        .fold(0u32, |sum, n| sum.wrapping_add(*n))
+}
+
+/// Runs in the tcpip thread: the checksum errors lwIP counts while
+/// processing the packet are the interface's.
+unsafe extern "C" fn netif_ip_input(p: *mut lwip::pbuf, netif: *mut lwip::netif) -> lwip::err_t {
+    let before = checksum_errors();
+    let ret = if (*netif).flags & NETIF_FLAGS_ETHERNET != 0 {
+        lwip::ethernet_input(p, netif)
+    } else {
+        lwip::ip_input(p, netif)
+    };
+
+    let n = checksum_errors().wrapping_sub(before);
+    let ptr = netif_cstate(netif);
+    if n > 0 && !ptr.is_null() {
+        (*ptr).counters.checksum_errors(n);
+    }
+    ret
+}
+
+unsafe extern "C" fn netif_input(p: *mut lwip::pbuf, netif: *mut lwip::netif) -> lwip::err_t {
+    lwip::tcpip_inpkt(p, netif, Some(netif_ip_input))
+}
+
 fn netif_common_output(netif: *mut lwip::netif, p: *mut lwip::pbuf) -> lwip::err_t {
     unsafe {
-        let state: &mut NetIfCState = &mut *((&mut *netif).state as *mut NetIfCState);
-        let state = state.0.lock().unwrap();
+        let pkt = Bytes::from_pbuf(p);
 
-        // send to channel:
-        state.send(Bytes::from_pbuf(p)).unwrap();
+        // IP_MULTICAST_LOOP: lwIP only loops the packet back itself with
+        // LWIP_NETIF_LOOPBACK, which also short-circuits unicast to our own
+        // addresses.
+        if (*p).flags & PBUF_FLAG_MCASTLOOP != 0 {
+            let q = pkt.clone().into_pbuf();
+            // not queued for the tcpip thread, it is still ours
+            if netif_input(q, netif) != lwip::err_enum_t::ERR_OK {
+                lwip::pbuf_free(q);
+            }
+        }
+
+        netif_queue_output(netif, pkt)
     }
-    lwip::err_enum_t::ERR_OK
 }
 
 extern "C" fn netif_output(
@@ -54,16 +215,213 @@ extern "C" fn netif_output_ip6(
     netif_common_output(netif, p)
 }
 
+// Ethernet frames, from etharp_output and ethip6_output
+extern "C" fn netif_linkoutput(netif: *mut lwip::netif, p: *mut lwip::pbuf) -> lwip::err_t {
+    netif_common_output(netif, p)
+}
+
 unsafe extern "C" fn netif_remove_ballback(netif: *mut lwip::netif) {
-    Box::from_raw((&mut *netif).state as *mut NetIfCState);
+    Box::from_raw(netif_cstate(netif));
 
-    (*netif).state = std::ptr::null_mut();
+    netif_set_cstate(netif, std::ptr::null_mut());
+}
+
+unsafe extern "C" fn netif_ext_callback(
+    netif: *mut lwip::netif,
+    reason: lwip::netif_nsc_reason_t,
+    args: *const lwip::netif_ext_callback_args_t,
+) {
+    let ptr = netif_cstate(netif);
+    if ptr.is_null() {
+        // not one of ours or not fully initialised yet
+        return;
+    }
+
+    let state = &*ptr;
+    let reason = reason as u32;
+
+    if reason & lwip::LWIP_NSC_NETIF_REMOVED != 0 {
+        state.notify(NetIfEvent::Removed);
+        return;
+    }
+
+    if reason & lwip::LWIP_NSC_STATUS_CHANGED != 0 {
+        state.notify(if (*args).status_changed.state != 0 {
+            NetIfEvent::Up
+        } else {
+            NetIfEvent::Down
+        });
+    }
+
+    if reason & lwip::LWIP_NSC_LINK_CHANGED != 0 {
+        state.notify(if (*args).link_changed.state != 0 {
+            NetIfEvent::LinkUp
+        } else {
+            NetIfEvent::LinkDown
+        });
+    }
+
+    if reason & lwip::LWIP_NSC_IPV4_ADDRESS_CHANGED != 0 {
+        let old: Ipv4Addr = (*(*args).ipv4_changed.old_address).u_addr.ip4.into();
+        let new: Ipv4Addr = (*netif).ip_addr.u_addr.ip4.into();
+
+        if !old.is_unspecified() {
+            state.notify(NetIfEvent::AddrRemoved(IpAddr::V4(old)));
+        }
+        if !new.is_unspecified() {
+            state.notify(NetIfEvent::AddrAdded(IpAddr::V4(new)));
+        }
+    }
+
+    if reason & lwip::LWIP_NSC_IPV6_ADDR_STATE_CHANGED != 0 {
+        let changed = &(*args).ipv6_addr_state_changed;
+        let addr: Ipv6Addr = (*changed.address).u_addr.ip6.into();
+        let idx = changed.addr_index as usize;
+
+        let old = Ipv6AddrState::from_value(changed.old_state);
+        let new = Ipv6AddrState::from_value((*netif).ip6_addr_state[idx]);
+        let skip_dad = *state.skip_dad.lock().unwrap() == Some(idx);
+
+        if old == Ipv6AddrState::Invalid {
+            state.notify(NetIfEvent::AddrAdded(IpAddr::V6(addr)));
+        }
+
+        match (old, new) {
+            (_, Ipv6AddrState::Invalid) => {
+                state.notify(NetIfEvent::AddrRemoved(IpAddr::V6(addr)));
+            }
+            (Ipv6AddrState::Tentative, Ipv6AddrState::Tentative) => {}
+            (Ipv6AddrState::Tentative, _) if skip_dad => {}
+            (Ipv6AddrState::Tentative, Ipv6AddrState::Duplicated) => {
+                state.notify(NetIfEvent::DadFinished {
+                    addr,
+                    duplicate: true,
+                });
+            }
+            (Ipv6AddrState::Tentative, _) => {
+                state.notify(NetIfEvent::DadFinished {
+                    addr,
+                    duplicate: false,
+                });
+            }
+            _ => {}
+        }
+    }
+}
+
+unsafe fn notify_mac_filter(netif: *mut lwip::netif, group: IpAddr, action: u32) {
+    let ptr = netif_cstate(netif);
+    if ptr.is_null() {
+        // groups joined by netif_add(), see NetIf::multicast_groups
+        return;
+    }
+
+    let state = &*ptr;
+    state.notify(
+        if action == lwip::netif_mac_filter_action_NETIF_ADD_MAC_FILTER {
+            NetIfEvent::MulticastJoined(group)
+        } else {
+            NetIfEvent::MulticastLeft(group)
+        },
+    );
+}
+
+unsafe extern "C" fn netif_igmp_mac_filter(
+    netif: *mut lwip::netif,
+    group: *const lwip::ip4_addr_t,
+    action: lwip::netif_mac_filter_action,
+) -> lwip::err_t {
+    notify_mac_filter(netif, IpAddr::V4((*group).into()), action);
+    lwip::err_enum_t::ERR_OK
+}
+
+unsafe extern "C" fn netif_mld_mac_filter(
+    netif: *mut lwip::netif,
+    group: *const lwip::ip6_addr_t,
+    action: lwip::netif_mac_filter_action,
+) -> lwip::err_t {
+    notify_mac_filter(netif, IpAddr::V6((*group).into()), action);
+    lwip::err_enum_t::ERR_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn lwip_rs_dhcp_event(
+    netif: *mut lwip::netif,
+    event: lwip::dhcp_client_event,
+    lease: *const lwip::dhcp_client_lease,
+) {
+    let ptr = netif_cstate(netif);
+    if ptr.is_null() {
+        return;
+    }
+
+    let state = &*ptr;
+    let mut dhcp = state.dhcp.lock().unwrap();
+
+    let event = match event {
+        lwip::dhcp_client_event::DHCP_CLIENT_EVENT_BOUND => {
+            let lease = &*lease;
+            let mask: Ipv4Addr = lease.netmask.into();
+            let router: Ipv4Addr = lease.gw.into();
+            let lease = DhcpLease {
+                addr: Ipv4Network::new(lease.addr.into(), ipv4_mask_to_prefix(mask).unwrap_or(0))
+                    .unwrap(),
+                router: if router.is_unspecified() {
+                    None
+                } else {
+                    Some(router)
+                },
+                dns: lease.dns[..lease.dns_count as usize]
+                    .iter()
+                    .map(|&addr| addr.into())
+                    .collect(),
+                lease_time: Duration::from_secs(lease.lease_time as u64),
+                renew_time: Duration::from_secs(lease.renew_time as u64),
+                rebind_time: Duration::from_secs(lease.rebind_time as u64),
+            };
+
+            *dhcp = DhcpState {
+                lease: Some(lease.clone()),
+                ..Default::default()
+            };
+            NetIfEvent::DhcpBound(lease)
+        }
+        // requests are retransmitted, only report the first one
+        lwip::dhcp_client_event::DHCP_CLIENT_EVENT_RENEWING => {
+            if dhcp.renewing {
+                return;
+            }
+            dhcp.renewing = true;
+            NetIfEvent::DhcpRenewing
+        }
+        lwip::dhcp_client_event::DHCP_CLIENT_EVENT_REBINDING => {
+            if dhcp.rebinding {
+                return;
+            }
+            dhcp.rebinding = true;
+            NetIfEvent::DhcpRebinding
+        }
+        // also sent for the first discover, before any lease
+        lwip::dhcp_client_event::DHCP_CLIENT_EVENT_LOST => {
+            if dhcp.lease.is_none() {
+                return;
+            }
+            *dhcp = DhcpState::default();
+            NetIfEvent::DhcpLost
+        }
+    };
+    drop(dhcp);
+
+    state.notify(event);
 }
 
 extern "C" fn netif_init(netif: *mut lwip::netif) -> lwip::err_t {
     unsafe {
         (*netif).output = Some(netif_output);
         (*netif).output_ip6 = Some(netif_output_ip6);
+        (*netif).flags |= (lwip::NETIF_FLAG_IGMP | lwip::NETIF_FLAG_MLD6) as u8;
+        (*netif).igmp_mac_filter = Some(netif_igmp_mac_filter);
+        (*netif).mld_mac_filter = Some(netif_mld_mac_filter);
         lwip::netif_set_remove_callback(netif, Some(netif_remove_ballback));
     }
     lwip::err_enum_t::ERR_OK
@@ -71,14 +429,21 @@ extern "C" fn netif_init(netif: *mut lwip::netif) -> lwip::err_t {
 
 impl NetIf {
     pub fn new<D: Device>(device: &D) -> io::Result<Self> {
-        crate::stack_init();
-        let (tx, rx) = mpsc::unbounded_channel();
+        netif_setup();
+
         let pcb: *mut lwip::netif = Box::into_raw(Box::new(unsafe { mem::zeroed() }));
 
         let addr: lwip::ip4_addr = device.ipv4().ip().into();
         let mask: lwip::ip4_addr = device.ipv4().mask().into();
         let default: lwip::ip4_addr = Ipv4Addr::UNSPECIFIED.into();
 
+        let hwaddr = device.hwaddr().unwrap_or_else(|| {
+            let mut hwaddr: [u8; 6] = rand::random();
+            // unicast, locally administered
+            hwaddr[0] = (hwaddr[0] & 0xfe) | 0x02;
+            hwaddr
+        });
+
         let ret: io::Result<()> = unsafe {
             lwip::netifapi_netif_add(
                 pcb,
@@ -87,50 +452,447 @@ impl NetIf {
                 &default,
                 std::ptr::null_mut(),
                 Some(netif_init),
-                Some(lwip::tcpip_input),
+                Some(netif_input),
             )
         }
         .into();
         ret?; // TODO
 
-        unsafe {
-            lwip::netif_set_link_up(pcb);
-            lwip::netif_set_up(pcb);
-        }
+        lwip::with_core_lock(|| unsafe {
+            (*pcb).mtu = device.mtu();
+            (*pcb).hwaddr_len = hwaddr.len() as u8;
+            (*pcb).hwaddr[..hwaddr.len()].copy_from_slice(&hwaddr);
+            if device.ethernet() {
+                (*pcb).output = Some(lwip::etharp_output);
+                (*pcb).output_ip6 = Some(lwip::ethip6_output);
+                (*pcb).linkoutput = Some(netif_linkoutput);
+                (*pcb).flags |= NETIF_FLAGS_ETHERNET;
+            }
+        });
 
-        // TODO Add mtu !!
+        // once attached, for the netif callback to see the interface as ours
+        let netif = NetIf::attach(pcb);
+        netif.set_link_up()?;
+        netif.set_up()?;
+
+        let autoconfig = device.ipv6_autoconfig();
+        if autoconfig {
+            lwip::with_core_lock(|| unsafe {
+                lwip::netif_create_ip6_linklocal_address(pcb, 1);
+                (*pcb).ip6_autoconfig_enabled = 1;
+            });
+        }
 
         for addr in device.ipv6() {
-            let addr: lwip::ip6_addr = addr.ip().into();
-            unsafe {
-                lwip::netif_add_ip6_address(pcb, &addr, std::ptr::null_mut());
-                lwip::netif_ip6_addr_set_state(pcb, 1, 0x10 /* IP6_ADDR_VALID */);
-            }
+            let state = if autoconfig {
+                Ipv6AddrState::Tentative
+            } else {
+                Ipv6AddrState::Preferred
+            };
+            netif.add_addr(Ipv6AddrInfo::new(addr.ip()).state(state))?;
         }
 
-        let state = Box::into_raw(Box::new(NetIfCState(Arc::new(Mutex::new(tx)))));
-        unsafe {
-            (*pcb).state = state as *mut _;
+        if device.dhcp() {
+            netif.start_dhcp()?;
         }
 
-        let inner = NetIfInner { pcb: pcb, rx: rx };
+        Ok(netif)
+    }
+
+    /// Takes over an interface already added to the stack, such as a PPP
+    /// one. Its driver queues the output with `netif_queue_output`.
+    pub(crate) fn attach(pcb: *mut lwip::netif) -> Self {
+        netif_setup();
 
-        Ok(NetIf {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let counters = Arc::new(NetIfCounters::default());
+        let state = Box::into_raw(Box::new(NetIfCState {
+            tx: Arc::new(Mutex::new(tx)),
+            watchers: Mutex::new(Vec::new()),
+            counters: counters.clone(),
+            dhcp: Mutex::new(DhcpState::default()),
+            skip_dad: Mutex::new(None),
+        }));
+        lwip::with_core_lock(|| unsafe {
+            netif_set_cstate(pcb, state);
+            lwip::netif_set_remove_callback(pcb, Some(netif_remove_ballback));
+        });
+
+        let inner = NetIfInner {
+            pcb: pcb,
+            generation: crate::stack::generation(),
+            rx: rx,
+            counters: counters,
+        };
+
+        NetIf {
             inner: Arc::new(Mutex::new(inner)),
+        }
+    }
+
+    pub fn set_up(&self) -> io::Result<()> {
+        self.netifapi_common(lwip::netif_set_up)
+    }
+
+    pub fn set_down(&self) -> io::Result<()> {
+        self.netifapi_common(lwip::netif_set_down)
+    }
+
+    pub fn set_link_up(&self) -> io::Result<()> {
+        self.netifapi_common(lwip::netif_set_link_up)
+    }
+
+    pub fn set_link_down(&self) -> io::Result<()> {
+        self.netifapi_common(lwip::netif_set_link_down)
+    }
+
+    pub fn is_up(&self) -> bool {
+        self.has_flag(lwip::NETIF_FLAG_UP)
+    }
+
+    pub fn is_link_up(&self) -> bool {
+        self.has_flag(lwip::NETIF_FLAG_LINK_UP)
+    }
+
+    pub fn stats(&self) -> NetIfStats {
+        let inner = self.inner.lock().unwrap();
+        inner.counters.snapshot()
+    }
+
+    pub fn watch(&self) -> NetIfWatch {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let inner = self.inner.lock().unwrap();
+
+        // the watch ends right away if the interface is gone
+        lwip::with_core_lock(|| unsafe {
+            if let Ok(state) = inner.cstate() {
+                state.watchers.lock().unwrap().push(tx);
+            }
+        });
+
+        NetIfWatch(rx)
+    }
+
+    pub fn hwaddr(&self) -> [u8; 6] {
+        let inner = self.inner.lock().unwrap();
+        let mut hwaddr = [0; 6];
+
+        lwip::with_core_lock(|| unsafe {
+            hwaddr.copy_from_slice(&(*inner.pcb).hwaddr[..hwaddr.len()]);
+        });
+        hwaddr
+    }
+
+    /// Starts the DHCP client, the lease is reported through `watch`.
+    pub fn start_dhcp(&self) -> io::Result<()> {
+        let inner = self.inner.lock().unwrap();
+
+        lwip::with_core_lock(|| unsafe {
+            inner.cstate()?;
+            lwip::dhcp_client_start(inner.pcb).into()
+        })
+    }
+
+    /// Renews the lease now instead of at the renewal time.
+    pub fn renew_dhcp(&self) -> io::Result<()> {
+        let inner = self.inner.lock().unwrap();
+
+        lwip::with_core_lock(|| unsafe {
+            inner.cstate()?;
+            lwip::dhcp_renew(inner.pcb).into()
+        })
+    }
+
+    /// Releases the lease, if any, and stops the DHCP client.
+    pub fn stop_dhcp(&self) {
+        let inner = self.inner.lock().unwrap();
+
+        lwip::with_core_lock(|| unsafe {
+            if let Ok(state) = inner.cstate() {
+                lwip::dhcp_release_and_stop(inner.pcb);
+                *state.dhcp.lock().unwrap() = DhcpState::default();
+            }
+        });
+    }
+
+    /// Current DHCP lease.
+    pub fn dhcp_lease(&self) -> Option<DhcpLease> {
+        let inner = self.inner.lock().unwrap();
+
+        lwip::with_core_lock(|| unsafe {
+            let state = inner.cstate().ok()?;
+            let dhcp = state.dhcp.lock().unwrap();
+            dhcp.lease.clone()
+        })
+    }
+
+    fn netifapi_common(&self, f: unsafe extern "C" fn(*mut lwip::netif)) -> io::Result<()> {
+        let inner = self.inner.lock().unwrap();
+
+        lwip::with_core_lock(|| unsafe { inner.cstate().map(|_| ()) })?;
+        unsafe { lwip::netifapi_netif_common(inner.pcb, Some(f), None) }.into()
+    }
+
+    /// Whether the interface exchanges Ethernet frames, see
+    /// `DeviceBuilder::ethernet`.
+    pub fn is_ethernet(&self) -> bool {
+        self.has_flag(NETIF_FLAGS_ETHERNET as u32)
+    }
+
+    fn has_flag(&self, flag: u32) -> bool {
+        let inner = self.inner.lock().unwrap();
+
+        lwip::with_core_lock(|| unsafe { (*inner.pcb).flags as u32 & flag != 0 })
+    }
+
+    pub fn add_addr<A: Into<Ipv6AddrInfo>>(&self, addr: A) -> io::Result<()> {
+        let info = addr.into();
+        let ip: lwip::ip6_addr = info.addr.into();
+        let inner = self.inner.lock().unwrap();
+
+        lwip::with_core_lock(|| unsafe {
+            let state = inner.cstate()?;
+            let mut idx: i8 = -1;
+            let ret: io::Result<()> = lwip::netif_add_ip6_address(inner.pcb, &ip, &mut idx).into();
+            ret.map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::Other,
+                    "no free IPv6 address slot (see LWIP_IPV6_NUM_ADDRESSES)",
+                )
+            })?;
+
+            let idx = idx as usize;
+            (*inner.pcb).ip6_addr_valid_life[idx] = info.valid_life.value();
+            (*inner.pcb).ip6_addr_pref_life[idx] = info.preferred_life.value();
+
+            // lwIP adds the address as tentative, no DAD runs if another
+            // state is asked for
+            *state.skip_dad.lock().unwrap() = Some(idx);
+            lwip::netif_ip6_addr_set_state(inner.pcb, idx as i8, info.state.value());
+            *state.skip_dad.lock().unwrap() = None;
+            Ok(())
+        })
+    }
+
+    pub fn remove_addr(&self, addr: IpAddr) -> io::Result<()> {
+        match addr {
+            IpAddr::V4(addr) => {
+                if self.ipv4().ip() != addr {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AddrNotAvailable,
+                        "address not configured on interface",
+                    ));
+                }
+                self.set_ipv4(Ipv4Network::new(Ipv4Addr::UNSPECIFIED, 0).unwrap())
+            }
+            IpAddr::V6(addr) => {
+                let inner = self.inner.lock().unwrap();
+                lwip::with_core_lock(|| unsafe {
+                    inner.cstate()?;
+                    match inner.ip6_index(addr) {
+                        Some(idx) => {
+                            lwip::netif_ip6_addr_set_state(
+                                inner.pcb,
+                                idx as i8,
+                                Ipv6AddrState::Invalid.value(),
+                            );
+                            Ok(())
+                        }
+                        None => Err(io::Error::new(
+                            io::ErrorKind::AddrNotAvailable,
+                            "address not configured on interface",
+                        )),
+                    }
+                })
+            }
+        }
+    }
+
+    pub fn set_ipv4(&self, net: Ipv4Network) -> io::Result<()> {
+        let addr: lwip::ip4_addr = net.ip().into();
+        let mask: lwip::ip4_addr = net.mask().into();
+        let inner = self.inner.lock().unwrap();
+
+        lwip::with_core_lock(|| unsafe {
+            inner.cstate()?;
+            let gw = (*inner.pcb).gw.u_addr.ip4;
+            lwip::netif_set_addr(inner.pcb, &addr, &mask, &gw);
+            Ok(())
+        })
+    }
+
+    pub fn ipv4(&self) -> Ipv4Network {
+        let inner = self.inner.lock().unwrap();
+        lwip::with_core_lock(|| inner.ipv4())
+    }
+
+    pub fn list_addrs(&self) -> Vec<NetIfAddr> {
+        let inner = self.inner.lock().unwrap();
+
+        lwip::with_core_lock(|| {
+            let mut addrs = Vec::new();
+            if unsafe { inner.cstate() }.is_err() {
+                return addrs;
+            }
+
+            let ipv4 = inner.ipv4();
+            if !ipv4.ip().is_unspecified() {
+                addrs.push(NetIfAddr::V4(ipv4));
+            }
+
+            let pcb = unsafe { &*inner.pcb };
+            addrs.extend(
+                (0..pcb.ip6_addr_state.len())
+                    .filter_map(|idx| inner.ip6_addr_info(idx))
+                    .map(NetIfAddr::V6),
+            );
+
+            addrs
+        })
+    }
+
+    /// What IPv6 autoconfiguration learned so far, if enabled (see
+    /// `DeviceBuilder::ipv6_autoconfig`).
+    pub fn ipv6_autoconf(&self) -> Option<Ipv6Autoconf> {
+        let inner = self.inner.lock().unwrap();
+
+        lwip::with_core_lock(|| unsafe {
+            let pcb = &*inner.pcb;
+            if pcb.ip6_autoconfig_enabled == 0 {
+                return None;
+            }
+
+            let link_local = inner
+                .ip6_addr_info(0)
+                .filter(|info| info.addr.segments()[0] & 0xffc0 == 0xfe80);
+
+            // like lwIP, addresses with a lifetime are the autoconfigured ones
+            let addrs = (1..pcb.ip6_addr_state.len())
+                .filter_map(|idx| inner.ip6_addr_info(idx))
+                .filter(|info| info.valid_life != Lifetime::Static)
+                .collect();
+
+            let prefixes = lwip::prefix_list
+                .iter()
+                .filter(|p| p.netif == inner.pcb && p.invalidation_timer > 0)
+                .map(|p| Ipv6Prefix {
+                    // lwIP only handles /64 prefixes
+                    prefix: Ipv6Network::new(p.prefix.into(), 64).unwrap(),
+                    valid_life: Duration::from_secs(p.invalidation_timer as u64),
+                })
+                .collect();
+
+            let routers = lwip::default_router_list
+                .iter()
+                .filter(|r| !r.neighbor_entry.is_null() && (*r.neighbor_entry).netif == inner.pcb)
+                .map(|r| Ipv6Router {
+                    addr: (*r.neighbor_entry).next_hop_address.into(),
+                    lifetime: Duration::from_secs(r.invalidation_timer as u64),
+                })
+                .collect();
+
+            Some(Ipv6Autoconf {
+                link_local,
+                addrs,
+                prefixes,
+                routers,
+            })
+        })
+    }
+
+    /// Multicast groups joined on the interface, by sockets or by the stack
+    /// itself (all-systems, solicited-node...). Changes are reported
+    /// through `watch`, to keep a device filter in sync.
+    pub fn multicast_groups(&self) -> Vec<IpAddr> {
+        let inner = self.inner.lock().unwrap();
+
+        lwip::with_core_lock(|| unsafe {
+            let pcb = &*inner.pcb;
+            let mut groups = Vec::new();
+
+            let mut group = pcb.client_data
+                [lwip::lwip_internal_netif_client_data_index_LWIP_NETIF_CLIENT_DATA_INDEX_IGMP
+                    as usize] as *const lwip::igmp_group;
+            while !group.is_null() {
+                groups.push(IpAddr::V4((*group).group_address.into()));
+                group = (*group).next;
+            }
+
+            let mut group = pcb.client_data
+                [lwip::lwip_internal_netif_client_data_index_LWIP_NETIF_CLIENT_DATA_INDEX_MLD6
+                    as usize] as *const lwip::mld_group;
+            while !group.is_null() {
+                groups.push(IpAddr::V6((*group).group_address.into()));
+                group = (*group).next;
+            }
+
+            groups
         })
     }
 
-    pub(crate) fn index(&self) -> u8 {
+    /// lwIP interface index, as taken by `UdpSocket::join_multicast_v6`.
+    pub fn index(&self) -> u8 {
         let inner = self.inner.lock().unwrap();
 
         unsafe { (*inner.pcb).num + 1 }
     }
 }
 
+impl NetIfInner {
+    /// Fails once the interface was removed, or its stack shut down. Called
+    /// with the core lock held.
+    unsafe fn cstate(&self) -> io::Result<&NetIfCState> {
+        if self.generation != crate::stack::generation() {
+            return Err(io::ErrorKind::NotConnected.into());
+        }
+        let state = netif_cstate(self.pcb);
+        if state.is_null() {
+            return Err(io::ErrorKind::NotConnected.into());
+        }
+        Ok(&*state)
+    }
+
+    fn ipv4(&self) -> Ipv4Network {
+        let pcb = unsafe { &*self.pcb };
+        let ip: Ipv4Addr = unsafe { pcb.ip_addr.u_addr.ip4 }.into();
+        let mask: Ipv4Addr = unsafe { pcb.netmask.u_addr.ip4 }.into();
+
+        Ipv4Network::new(ip, ipv4_mask_to_prefix(mask).unwrap_or(0)).unwrap()
+    }
+
+    fn ip6_addr_info(&self, idx: usize) -> Option<Ipv6AddrInfo> {
+        let pcb = unsafe { &*self.pcb };
+        let state = Ipv6AddrState::from_value(pcb.ip6_addr_state[idx]);
+        if state == Ipv6AddrState::Invalid {
+            return None;
+        }
+
+        Some(Ipv6AddrInfo {
+            addr: unsafe { pcb.ip6_addr[idx].u_addr.ip6 }.into(),
+            state,
+            valid_life: Lifetime::from_value(pcb.ip6_addr_valid_life[idx]),
+            preferred_life: Lifetime::from_value(pcb.ip6_addr_pref_life[idx]),
+        })
+    }
+
+    fn ip6_index(&self, addr: Ipv6Addr) -> Option<usize> {
+        let pcb = unsafe { &*self.pcb };
+        (0..pcb.ip6_addr_state.len()).find(|&idx| {
+            let state = Ipv6AddrState::from_value(pcb.ip6_addr_state[idx]);
+            let ip: Ipv6Addr = unsafe { pcb.ip6_addr[idx].u_addr.ip6 }.into();
+            state != Ipv6AddrState::Invalid && ip == addr
+        })
+    }
+}
+
 impl Drop for NetIfInner {
     fn drop(&mut self) {
         unsafe {
-            lwip::netifapi_netif_common(self.pcb, Some(lwip::netif_remove), None);
+            // already removed if the stack was shut down
+            if lwip::with_core_lock(|| self.cstate().is_ok()) {
+                lwip::with_core_lock(|| lwip::dhcp_cleanup(self.pcb));
+                lwip::netifapi_netif_common(self.pcb, Some(lwip::netif_remove), None);
+            }
             // free the pointer
             Box::from_raw(self.pcb);
         }
@@ -169,12 +931,19 @@ impl AsyncWrite for NetIf {
         _: &mut Context<'_>,
         buf: &[u8],
     ) -> Poll<Result<usize, io::Error>> {
-        let pbuf = BytesMut::from(buf).freeze().into_pbuf();
-
         let inner = self.inner.lock().unwrap();
+        if let Err(e) = lwip::with_core_lock(|| unsafe { inner.cstate().map(|_| ()) }) {
+            return Poll::Ready(Err(e));
+        }
 
-        let ret: io::Result<()> = unsafe { lwip::tcpip_input(pbuf, inner.pcb) }.into();
-        ret?;
+        let pbuf = BytesMut::from(buf).freeze().into_pbuf();
+        let ret: io::Result<()> = unsafe { netif_input(pbuf, inner.pcb) }.into();
+        if let Err(e) = ret {
+            unsafe { lwip::pbuf_free(pbuf) };
+            inner.counters.input_drop();
+            return Poll::Ready(Err(e));
+        }
+        inner.counters.input(buf.len());
         Poll::Ready(Ok(buf.len()))
     }
 
@@ -209,12 +978,67 @@ impl<D> NetDevice<D>
 where
     D: AsyncRead + AsyncWrite,
 {
-    pub fn drive(self) -> Transfer<NetIf, D> {
-        transfer(self.netif, self.device)
+    pub fn drive(self) -> Drive<D> {
+        Drive {
+            netif: self.netif.clone(),
+            tr: transfer(self.netif, self.device),
+        }
+    }
+}
+
+pin_project_lite::pin_project! {
+    pub struct Drive<D> {
+        #[pin]
+        tr: Transfer<NetIf, D>,
+        netif: NetIf,
+    }
+}
+
+impl<D> Drive<D> {
+    pub fn into_inner(self) -> (NetIf, D) {
+        self.tr.into_inner()
+    }
+}
+
+impl<D> Future for Drive<D>
+where
+    D: AsyncRead + AsyncWrite,
+{
+    type Output = io::Result<(u64, u64)>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+
+        match this.tr.poll(cx) {
+            Poll::Ready(res) => {
+                // the underlying device is gone: take the interface down
+                // instead of leaving a dangling route to it.
+                let _ = this.netif.set_link_down();
+                let _ = this.netif.set_down();
+                Poll::Ready(res)
+            }
+            Poll::Pending => Poll::Pending,
+        }
     }
 }
 
 impl<D> NetDevice<D> {
+    pub fn capture<W>(self, writer: W, format: PcapFormat) -> io::Result<NetDevice<Capture<D>>>
+    where
+        W: std::io::Write + Send + 'static,
+    {
+        let device = if self.netif.is_ethernet() {
+            Capture::ethernet(self.device, writer, format)?
+        } else {
+            Capture::new(self.device, writer, format)?
+        };
+
+        Ok(NetDevice {
+            netif: self.netif,
+            device: device,
+        })
+    }
+
     pub fn netif_as_ref(&self) -> &NetIf {
         &self.netif
     }
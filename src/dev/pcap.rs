@@ -0,0 +1,675 @@
+use std::io::{self, Read, Write};
+use std::pin::Pin;
+use std::sync::mpsc::{self, Sender};
+use std::task::{Context, Poll};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian, NativeEndian, WriteBytesExt};
+use ipnetwork::{Ipv4Network, Ipv6Network};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::Device;
+
+const LINKTYPE_ETHERNET: u16 = 1;
+const LINKTYPE_RAW: u16 = 101;
+const LINKTYPE_IPV4: u16 = 228;
+const LINKTYPE_IPV6: u16 = 229;
+const SNAPLEN: u32 = 65535;
+// larger records or blocks are rejected instead of being allocated
+const MAX_CAPLEN: usize = 256 * 1024;
+const MAX_BLOCK_LEN: usize = 2 * MAX_CAPLEN;
+
+const PCAP_MAGIC: u32 = 0xa1b2_c3d4;
+const PCAP_MAGIC_NSEC: u32 = 0xa1b2_3c4d;
+const PCAPNG_SHB: u32 = 0x0a0d_0d0a;
+const PCAPNG_IDB: u32 = 0x0000_0001;
+const PCAPNG_SPB: u32 = 0x0000_0003;
+const PCAPNG_EPB: u32 = 0x0000_0006;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1a2b_3c4d;
+const PCAPNG_OPT_ENDOFOPT: u16 = 0;
+const PCAPNG_OPT_EPB_FLAGS: u16 = 2;
+const PCAPNG_OPT_IF_TSRESOL: u16 = 9;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PcapFormat {
+    Pcap,
+    PcapNg,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Direction {
+    // from the device to the stack
+    Inbound,
+    // from the stack to the device
+    Outbound,
+}
+
+#[derive(Debug)]
+pub struct PcapWriter<W> {
+    writer: W,
+    format: PcapFormat,
+}
+
+impl<W: Write> PcapWriter<W> {
+    /// Writes IP packets.
+    pub fn new(writer: W, format: PcapFormat) -> io::Result<Self> {
+        Self::with_linktype(writer, format, LINKTYPE_RAW)
+    }
+
+    /// Writes Ethernet frames.
+    pub fn ethernet(writer: W, format: PcapFormat) -> io::Result<Self> {
+        Self::with_linktype(writer, format, LINKTYPE_ETHERNET)
+    }
+
+    fn with_linktype(mut writer: W, format: PcapFormat, linktype: u16) -> io::Result<Self> {
+        match format {
+            PcapFormat::Pcap => {
+                writer.write_u32::<NativeEndian>(PCAP_MAGIC)?;
+                writer.write_u16::<NativeEndian>(2)?;
+                writer.write_u16::<NativeEndian>(4)?;
+                writer.write_i32::<NativeEndian>(0)?; // thiszone
+                writer.write_u32::<NativeEndian>(0)?; // sigfigs
+                writer.write_u32::<NativeEndian>(SNAPLEN)?;
+                writer.write_u32::<NativeEndian>(linktype as u32)?;
+            }
+            PcapFormat::PcapNg => {
+                // section header block
+                writer.write_u32::<NativeEndian>(PCAPNG_SHB)?;
+                writer.write_u32::<NativeEndian>(28)?;
+                writer.write_u32::<NativeEndian>(PCAPNG_BYTE_ORDER_MAGIC)?;
+                writer.write_u16::<NativeEndian>(1)?;
+                writer.write_u16::<NativeEndian>(0)?;
+                writer.write_i64::<NativeEndian>(-1)?; // section length unknown
+                writer.write_u32::<NativeEndian>(28)?;
+
+                // interface description block, default microsecond resolution
+                writer.write_u32::<NativeEndian>(PCAPNG_IDB)?;
+                writer.write_u32::<NativeEndian>(20)?;
+                writer.write_u16::<NativeEndian>(linktype)?;
+                writer.write_u16::<NativeEndian>(0)?;
+                writer.write_u32::<NativeEndian>(SNAPLEN)?;
+                writer.write_u32::<NativeEndian>(20)?;
+            }
+        }
+        writer.flush()?;
+
+        Ok(PcapWriter { writer, format })
+    }
+
+    pub fn write_packet(
+        &mut self,
+        ts: SystemTime,
+        direction: Direction,
+        data: &[u8],
+    ) -> io::Result<()> {
+        let ts = ts
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or(Duration::from_secs(0));
+        let caplen = data.len().min(SNAPLEN as usize);
+
+        match self.format {
+            PcapFormat::Pcap => {
+                // classic pcap cannot record the direction.
+                self.writer.write_u32::<NativeEndian>(ts.as_secs() as u32)?;
+                self.writer.write_u32::<NativeEndian>(ts.subsec_micros())?;
+                self.writer.write_u32::<NativeEndian>(caplen as u32)?;
+                self.writer.write_u32::<NativeEndian>(data.len() as u32)?;
+                self.writer.write_all(&data[..caplen])?;
+            }
+            PcapFormat::PcapNg => {
+                let padding = (4 - caplen % 4) % 4;
+                let len = (44 + caplen + padding) as u32;
+                let micros = ts.as_micros() as u64;
+                let flags: u32 = match direction {
+                    Direction::Inbound => 0x1,
+                    Direction::Outbound => 0x2,
+                };
+
+                self.writer.write_u32::<NativeEndian>(PCAPNG_EPB)?;
+                self.writer.write_u32::<NativeEndian>(len)?;
+                self.writer.write_u32::<NativeEndian>(0)?; // interface id
+                self.writer
+                    .write_u32::<NativeEndian>((micros >> 32) as u32)?;
+                self.writer.write_u32::<NativeEndian>(micros as u32)?;
+                self.writer.write_u32::<NativeEndian>(caplen as u32)?;
+                self.writer.write_u32::<NativeEndian>(data.len() as u32)?;
+                self.writer.write_all(&data[..caplen])?;
+                self.writer.write_all(&[0; 3][..padding])?;
+                self.writer
+                    .write_u16::<NativeEndian>(PCAPNG_OPT_EPB_FLAGS)?;
+                self.writer.write_u16::<NativeEndian>(4)?;
+                self.writer.write_u32::<NativeEndian>(flags)?;
+                self.writer.write_u16::<NativeEndian>(PCAPNG_OPT_ENDOFOPT)?;
+                self.writer.write_u16::<NativeEndian>(0)?;
+                self.writer.write_u32::<NativeEndian>(len)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PcapPacket {
+    // time since the UNIX epoch, as recorded in the file
+    pub ts: Duration,
+    // only known for pcapng files carrying epb_flags
+    pub direction: Option<Direction>,
+    // IP packet, link-layer headers are stripped
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct PcapInterface {
+    linktype: u16,
+    // 0 if unknown
+    snaplen: usize,
+    // timestamp units per second
+    tsresol: u64,
+}
+
+impl PcapInterface {
+    fn valid_caplen(&self, caplen: usize) -> bool {
+        caplen <= MAX_CAPLEN && (self.snaplen == 0 || caplen <= self.snaplen)
+    }
+}
+
+#[derive(Debug)]
+pub struct PcapReader<R> {
+    reader: R,
+    format: PcapFormat,
+    big_endian: bool,
+    interfaces: Vec<PcapInterface>,
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+impl<R: Read> PcapReader<R> {
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+
+        if LittleEndian::read_u32(&magic) == PCAPNG_SHB {
+            let mut pcapng = PcapReader {
+                reader,
+                format: PcapFormat::PcapNg,
+                big_endian: false,
+                interfaces: Vec::new(),
+            };
+            pcapng.read_shb()?;
+            return Ok(pcapng);
+        }
+
+        let (big_endian, tsresol) =
+            match (LittleEndian::read_u32(&magic), BigEndian::read_u32(&magic)) {
+                (PCAP_MAGIC, _) => (false, 1_000_000),
+                (PCAP_MAGIC_NSEC, _) => (false, 1_000_000_000),
+                (_, PCAP_MAGIC) => (true, 1_000_000),
+                (_, PCAP_MAGIC_NSEC) => (true, 1_000_000_000),
+                _ => return Err(invalid_data("not a pcap or pcapng file")),
+            };
+
+        let mut header = [0; 20];
+        reader.read_exact(&mut header)?;
+
+        let mut pcap = PcapReader {
+            reader,
+            format: PcapFormat::Pcap,
+            big_endian,
+            interfaces: Vec::new(),
+        };
+        let snaplen = pcap.u32(&header[12..16]) as usize;
+        let linktype = pcap.u32(&header[16..20]) as u16;
+        pcap.interfaces.push(PcapInterface {
+            linktype,
+            snaplen,
+            tsresol,
+        });
+        Ok(pcap)
+    }
+
+    pub fn format(&self) -> PcapFormat {
+        self.format
+    }
+
+    pub fn next_packet(&mut self) -> io::Result<Option<PcapPacket>> {
+        loop {
+            let packet = match self.format {
+                PcapFormat::Pcap => self.read_record()?,
+                PcapFormat::PcapNg => self.read_block()?,
+            };
+
+            match packet {
+                None => return Ok(None),
+                Some(None) => continue, // not an IP packet
+                Some(Some(packet)) => return Ok(Some(packet)),
+            }
+        }
+    }
+
+    fn u16(&self, buf: &[u8]) -> u16 {
+        if self.big_endian {
+            BigEndian::read_u16(buf)
+        } else {
+            LittleEndian::read_u16(buf)
+        }
+    }
+
+    fn u32(&self, buf: &[u8]) -> u32 {
+        if self.big_endian {
+            BigEndian::read_u32(buf)
+        } else {
+            LittleEndian::read_u32(buf)
+        }
+    }
+
+    // returns false on a clean end of file
+    fn read_or_eof(&mut self, buf: &mut [u8]) -> io::Result<bool> {
+        let mut read = 0;
+        while read < buf.len() {
+            match self.reader.read(&mut buf[read..])? {
+                0 if read == 0 => return Ok(false),
+                0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+                n => read += n,
+            }
+        }
+        Ok(true)
+    }
+
+    fn read_record(&mut self) -> io::Result<Option<Option<PcapPacket>>> {
+        let mut header = [0; 16];
+        if !self.read_or_eof(&mut header)? {
+            return Ok(None);
+        }
+
+        let ticks = (
+            self.u32(&header[0..4]) as u64,
+            self.u32(&header[4..8]) as u64,
+        );
+        let caplen = self.u32(&header[8..12]) as usize;
+        let iface = self.interfaces[0];
+        if !iface.valid_caplen(caplen) {
+            return Err(invalid_data("invalid pcap record length"));
+        }
+
+        let mut data = vec![0; caplen];
+        self.reader.read_exact(&mut data)?;
+
+        let ts = Duration::from_secs(ticks.0)
+            + Duration::from_nanos(ticks.1 * 1_000_000_000 / iface.tsresol);
+
+        Ok(Some(strip_link(iface.linktype, data).map(|data| {
+            PcapPacket {
+                ts,
+                direction: None,
+                data,
+            }
+        })))
+    }
+
+    fn read_shb(&mut self) -> io::Result<()> {
+        // the block type was already consumed.
+        let mut header = [0; 8];
+        self.reader.read_exact(&mut header)?;
+
+        self.big_endian = match (
+            LittleEndian::read_u32(&header[4..8]),
+            BigEndian::read_u32(&header[4..8]),
+        ) {
+            (PCAPNG_BYTE_ORDER_MAGIC, _) => false,
+            (_, PCAPNG_BYTE_ORDER_MAGIC) => true,
+            _ => return Err(invalid_data("invalid pcapng byte-order magic")),
+        };
+
+        let len = self.u32(&header[0..4]) as usize;
+        if len < 28 || len % 4 != 0 || len > MAX_BLOCK_LEN {
+            return Err(invalid_data("invalid pcapng section header"));
+        }
+        let mut rest = vec![0; len - 12];
+        self.reader.read_exact(&mut rest)?;
+
+        // interfaces are numbered per section
+        self.interfaces.clear();
+        Ok(())
+    }
+
+    fn read_block(&mut self) -> io::Result<Option<Option<PcapPacket>>> {
+        let mut header = [0; 8];
+        if !self.read_or_eof(&mut header[..4])? {
+            return Ok(None);
+        }
+
+        if LittleEndian::read_u32(&header[..4]) == PCAPNG_SHB {
+            self.read_shb()?;
+            return Ok(Some(None));
+        }
+
+        self.reader.read_exact(&mut header[4..8])?;
+        let kind = self.u32(&header[0..4]);
+        let len = self.u32(&header[4..8]) as usize;
+        if len < 12 || len % 4 != 0 || len > MAX_BLOCK_LEN {
+            return Err(invalid_data("invalid pcapng block length"));
+        }
+
+        let mut body = vec![0; len - 8];
+        self.reader.read_exact(&mut body)?;
+        let body = &body[..len - 12]; // trailing block length
+
+        match kind {
+            PCAPNG_IDB => {
+                if body.len() < 8 {
+                    return Err(invalid_data("invalid pcapng interface block"));
+                }
+                let mut iface = PcapInterface {
+                    linktype: self.u16(&body[0..2]),
+                    snaplen: self.u32(&body[4..8]) as usize,
+                    tsresol: 1_000_000,
+                };
+                for (code, value) in self.options(&body[8..]) {
+                    if code == PCAPNG_OPT_IF_TSRESOL && !value.is_empty() {
+                        let exp = (value[0] & 0x7f) as u32;
+                        iface.tsresol = if value[0] & 0x80 == 0 {
+                            10u64.pow(exp.min(19))
+                        } else {
+                            2u64.pow(exp.min(63))
+                        };
+                    }
+                }
+                self.interfaces.push(iface);
+                Ok(Some(None))
+            }
+            PCAPNG_EPB => {
+                if body.len() < 20 {
+                    return Err(invalid_data("invalid pcapng packet block"));
+                }
+                let iface = *self
+                    .interfaces
+                    .get(self.u32(&body[0..4]) as usize)
+                    .ok_or_else(|| invalid_data("unknown pcapng interface"))?;
+                let ticks = ((self.u32(&body[4..8]) as u64) << 32) | self.u32(&body[8..12]) as u64;
+                let caplen = self.u32(&body[12..16]) as usize;
+                if !iface.valid_caplen(caplen) {
+                    return Err(invalid_data("invalid pcapng packet block"));
+                }
+                let padded = (caplen + 3) & !3;
+                if body.len() < 20 + padded {
+                    return Err(invalid_data("invalid pcapng packet block"));
+                }
+
+                let mut direction = None;
+                for (code, value) in self.options(&body[20 + padded..]) {
+                    if code == PCAPNG_OPT_EPB_FLAGS && value.len() == 4 {
+                        direction = match self.u32(value) & 0x3 {
+                            0x1 => Some(Direction::Inbound),
+                            0x2 => Some(Direction::Outbound),
+                            _ => None,
+                        };
+                    }
+                }
+
+                let ts = Duration::from_nanos(
+                    (ticks as u128 * 1_000_000_000 / iface.tsresol as u128) as u64,
+                );
+                let data = body[20..20 + caplen].to_vec();
+
+                Ok(Some(strip_link(iface.linktype, data).map(|data| {
+                    PcapPacket {
+                        ts,
+                        direction,
+                        data,
+                    }
+                })))
+            }
+            PCAPNG_SPB => {
+                if body.len() < 4 {
+                    return Err(invalid_data("invalid pcapng packet block"));
+                }
+                let iface = *self
+                    .interfaces
+                    .get(0)
+                    .ok_or_else(|| invalid_data("unknown pcapng interface"))?;
+                let caplen = (self.u32(&body[0..4]) as usize).min(body.len() - 4);
+                if !iface.valid_caplen(caplen) {
+                    return Err(invalid_data("invalid pcapng packet block"));
+                }
+                let data = body[4..4 + caplen].to_vec();
+
+                Ok(Some(strip_link(iface.linktype, data).map(|data| {
+                    PcapPacket {
+                        ts: Duration::from_secs(0),
+                        direction: None,
+                        data,
+                    }
+                })))
+            }
+            _ => Ok(Some(None)), // unsupported blocks are skipped
+        }
+    }
+
+    fn options<'a>(&self, mut buf: &'a [u8]) -> Vec<(u16, &'a [u8])> {
+        let mut options = Vec::new();
+
+        while buf.len() >= 4 {
+            let code = self.u16(&buf[0..2]);
+            let len = self.u16(&buf[2..4]) as usize;
+            if code == PCAPNG_OPT_ENDOFOPT || buf.len() < 4 + len {
+                break;
+            }
+            options.push((code, &buf[4..4 + len]));
+            buf = &buf[(4 + len + 3) & !3..];
+        }
+
+        options
+    }
+}
+
+impl<R: Read> Iterator for PcapReader<R> {
+    type Item = io::Result<PcapPacket>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_packet().transpose()
+    }
+}
+
+fn strip_link(linktype: u16, mut data: Vec<u8>) -> Option<Vec<u8>> {
+    match linktype {
+        LINKTYPE_RAW | LINKTYPE_IPV4 | LINKTYPE_IPV6 => Some(data),
+        LINKTYPE_ETHERNET if data.len() >= 14 => match BigEndian::read_u16(&data[12..14]) {
+            0x0800 | 0x86dd => Some(data.split_off(14)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+type CapturedPacket = (SystemTime, Direction, Vec<u8>);
+
+// Writes the captured packets off the poll path, flushing whenever it has
+// caught up. Dropping it waits for the pending packets to be written.
+struct CaptureThread {
+    tx: Option<Sender<CapturedPacket>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl CaptureThread {
+    fn spawn<W>(mut writer: PcapWriter<W>) -> io::Result<Self>
+    where
+        W: Write + Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel::<CapturedPacket>();
+
+        let handle = thread::Builder::new()
+            .name("lwip-pcap".into())
+            .spawn(move || {
+                while let Ok(mut packet) = rx.recv() {
+                    loop {
+                        let (ts, direction, data) = packet;
+                        // a broken capture must not break the data path.
+                        if let Err(e) = writer.write_packet(ts, direction, &data) {
+                            log::warn!(target: "lwip::pcap", "unable to write packet: {}", e);
+                        }
+                        packet = match rx.try_recv() {
+                            Ok(packet) => packet,
+                            Err(_) => break,
+                        };
+                    }
+                    if let Err(e) = writer.flush() {
+                        log::warn!(target: "lwip::pcap", "unable to flush capture: {}", e);
+                    }
+                }
+            })?;
+
+        Ok(CaptureThread {
+            tx: Some(tx),
+            handle: Some(handle),
+        })
+    }
+
+    fn record(&self, direction: Direction, data: &[u8]) {
+        if let Some(tx) = &self.tx {
+            let _ = tx.send((SystemTime::now(), direction, data.to_vec()));
+        }
+    }
+}
+
+impl Drop for CaptureThread {
+    fn drop(&mut self) {
+        drop(self.tx.take());
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+pin_project_lite::pin_project! {
+    /// Records the packets going through a device. They are written by a
+    /// background thread, all of them once the `Capture` is dropped.
+    pub struct Capture<D> {
+        #[pin]
+        underlying: D,
+        writer: CaptureThread,
+    }
+}
+
+impl<D> Capture<D> {
+    pub fn new<W>(underlying: D, writer: W, format: PcapFormat) -> io::Result<Self>
+    where
+        W: Write + Send + 'static,
+    {
+        Ok(Capture {
+            underlying,
+            writer: CaptureThread::spawn(PcapWriter::new(writer, format)?)?,
+        })
+    }
+
+    /// Records the frames of a device carrying Ethernet.
+    pub fn ethernet<W>(underlying: D, writer: W, format: PcapFormat) -> io::Result<Self>
+    where
+        W: Write + Send + 'static,
+    {
+        Ok(Capture {
+            underlying,
+            writer: CaptureThread::spawn(PcapWriter::ethernet(writer, format)?)?,
+        })
+    }
+
+    pub fn into_inner(self) -> D {
+        self.underlying
+    }
+}
+
+impl<D> AsyncRead for Capture<D>
+where
+    D: AsyncRead,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.project();
+
+        match this.underlying.poll_read(cx, buf) {
+            Poll::Ready(Ok(len)) => {
+                if len > 0 {
+                    this.writer.record(Direction::Inbound, &buf[..len]);
+                }
+                Poll::Ready(Ok(len))
+            }
+            poll => poll,
+        }
+    }
+}
+
+impl<D> AsyncWrite for Capture<D>
+where
+    D: AsyncWrite,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        let this = self.project();
+
+        match this.underlying.poll_write(cx, buf) {
+            Poll::Ready(Ok(len)) => {
+                this.writer.record(Direction::Outbound, &buf[..len]);
+                Poll::Ready(Ok(len))
+            }
+            poll => poll,
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        self.project().underlying.poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        self.project().underlying.poll_shutdown(cx)
+    }
+}
+
+impl<D> Device for Capture<D>
+where
+    D: Device,
+{
+    fn ipv4(&self) -> Ipv4Network {
+        self.underlying.ipv4()
+    }
+
+    fn ipv6(&self) -> Vec<Ipv6Network> {
+        self.underlying.ipv6()
+    }
+
+    fn mtu(&self) -> u16 {
+        self.underlying.mtu()
+    }
+
+    fn hwaddr(&self) -> Option<[u8; 6]> {
+        self.underlying.hwaddr()
+    }
+
+    fn ethernet(&self) -> bool {
+        self.underlying.ethernet()
+    }
+
+    fn dhcp(&self) -> bool {
+        self.underlying.dhcp()
+    }
+
+    fn ipv6_autoconfig(&self) -> bool {
+        self.underlying.ipv6_autoconfig()
+    }
+}
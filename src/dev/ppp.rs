@@ -0,0 +1,475 @@
+use std::ffi::CString;
+use std::future::Future;
+use std::io;
+use std::mem;
+use std::net::Ipv4Addr;
+use std::os::raw::{c_int, c_void};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use bytes::Bytes;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use super::netif::netif_queue_frame;
+use crate::lwip;
+use crate::NetIf;
+
+// output is queued a pbuf at a time
+const BUF_SIZE: usize = std::u16::MAX as usize;
+
+/// How we authenticate to the peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PppAuth {
+    Pap,
+    Chap,
+    /// Whichever the peer asks for.
+    Any,
+}
+
+impl PppAuth {
+    fn value(&self) -> u8 {
+        (match *self {
+            PppAuth::Pap => lwip::PPPAUTHTYPE_PAP,
+            PppAuth::Chap => lwip::PPPAUTHTYPE_CHAP,
+            PppAuth::Any => lwip::PPPAUTHTYPE_ANY,
+        }) as u8
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct PppBuilder {
+    auth: Option<(PppAuth, String, String)>,
+    auth_required: bool,
+    local_addr: Option<Ipv4Addr>,
+    peer_addr: Option<Ipv4Addr>,
+}
+
+impl PppBuilder {
+    /// Credentials sent if the peer asks us to authenticate.
+    pub fn auth(mut self, auth: PppAuth, user: &str, password: &str) -> Self {
+        self.auth = Some((auth, user.to_string(), password.to_string()));
+        self
+    }
+
+    /// Asks the peer to authenticate, with these credentials. lwIP checks
+    /// the peer against the credentials it would send itself, this replaces
+    /// `auth`.
+    pub fn require_auth(mut self, auth: PppAuth, user: &str, password: &str) -> Self {
+        self.auth_required = true;
+        self.auth(auth, user, password)
+    }
+
+    /// Our IPv4 address, asked from the peer if unset.
+    pub fn local_addr(mut self, addr: Ipv4Addr) -> Self {
+        self.local_addr = Some(addr);
+        self
+    }
+
+    /// IPv4 address handed to the peer if it asks for one.
+    pub fn peer_addr(mut self, addr: Ipv4Addr) -> Self {
+        self.peer_addr = Some(addr);
+        self
+    }
+
+    /// Starts negotiating over `stream` once driven.
+    pub fn build<T: AsyncRead + AsyncWrite>(self, stream: T) -> io::Result<PppDevice<T>> {
+        let link = PppLink::new(self, None, |pcb, ctx| unsafe {
+            Ok(lwip::pppos_create(
+                pcb,
+                Some(ppp_output),
+                Some(ppp_status),
+                ctx,
+            ))
+        })?;
+        Ok(PppDevice { link, stream })
+    }
+
+    /// Starts PPPoE (RFC 2516) discovery on `ethernet`, an interface built
+    /// with `DeviceBuilder::ethernet` whose device is driven as usual.
+    pub fn build_pppoe(self, ethernet: &NetIf) -> io::Result<PppoeDevice> {
+        if !ethernet.is_ethernet() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "PPPoE needs an Ethernet interface",
+            ));
+        }
+
+        let index = ethernet.index();
+        let link = PppLink::new(self, Some(ethernet.clone()), |pcb, ctx| unsafe {
+            let ethif = lwip::netif_get_by_index(index);
+            if ethif.is_null() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "no such interface",
+                ));
+            }
+            Ok(lwip::pppoe_create(
+                pcb,
+                ethif,
+                std::ptr::null(),
+                std::ptr::null(),
+                Some(ppp_status),
+                ctx,
+            ))
+        })?;
+        Ok(PppoeDevice { link })
+    }
+}
+
+/// A PPP link (LCP, PAP/CHAP, IPCP and IPv6CP) over a byte stream such as
+/// a serial port, PTY or modem.
+///
+/// The interface counts the IP packets carried by PPP, not the bytes of
+/// the stream.
+///
+/// The link of the interface comes up, with the negotiated addresses, once
+/// IPCP or IPv6CP is done: see `NetIf::watch`. PPP is closed when dropped.
+pub struct PppDevice<T> {
+    link: PppLink,
+    stream: T,
+}
+
+impl<T> PppDevice<T> {
+    pub fn builder() -> PppBuilder {
+        PppBuilder::default()
+    }
+
+    pub fn netif_as_ref(&self) -> &NetIf {
+        &self.link.netif
+    }
+
+    pub fn stream_as_ref(&self) -> &T {
+        &self.stream
+    }
+}
+
+impl<T> PppDevice<T>
+where
+    T: AsyncRead + AsyncWrite,
+{
+    /// Runs the link, until the stream is closed or PPP fails.
+    pub fn drive(self) -> PppDrive<T> {
+        PppDrive {
+            link: self.link,
+            stream: self.stream,
+            read_buf: vec![0; BUF_SIZE],
+            write_buf: vec![0; BUF_SIZE],
+            pending: None,
+        }
+    }
+}
+
+pin_project_lite::pin_project! {
+    pub struct PppDrive<T> {
+        link: PppLink,
+        #[pin]
+        stream: T,
+        read_buf: Vec<u8>,
+        write_buf: Vec<u8>,
+        pending: Option<(usize, usize)>,
+    }
+}
+
+impl<T> Future for PppDrive<T>
+where
+    T: AsyncRead + AsyncWrite,
+{
+    type Output = io::Result<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+
+        this.link.status.lock().unwrap().waker = Some(cx.waker().clone());
+
+        loop {
+            match this.stream.as_mut().poll_read(cx, this.read_buf) {
+                Poll::Ready(Ok(0)) => {
+                    this.link.close();
+                    return Poll::Ready(Ok(()));
+                }
+                Poll::Ready(Ok(len)) => this.link.input(&this.read_buf[..len]),
+                Poll::Ready(Err(e)) => {
+                    this.link.close();
+                    return Poll::Ready(Err(e));
+                }
+                Poll::Pending => break,
+            }
+        }
+
+        loop {
+            if this.pending.is_none() {
+                match Pin::new(&mut this.link.netif).poll_read(cx, this.write_buf) {
+                    Poll::Ready(Ok(len)) => *this.pending = Some((0, len)),
+                    Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                    Poll::Pending => break,
+                }
+            }
+
+            let (start, end) = this.pending.unwrap();
+            match this
+                .stream
+                .as_mut()
+                .poll_write(cx, &this.write_buf[start..end])
+            {
+                Poll::Ready(Ok(len)) if start + len < end => {
+                    *this.pending = Some((start + len, end));
+                }
+                Poll::Ready(Ok(_)) => *this.pending = None,
+                Poll::Ready(Err(e)) => {
+                    this.link.close();
+                    return Poll::Ready(Err(e));
+                }
+                Poll::Pending => break,
+            }
+        }
+        if let Poll::Ready(Err(e)) = this.stream.as_mut().poll_flush(cx) {
+            this.link.close();
+            return Poll::Ready(Err(e));
+        }
+
+        match this.link.status.lock().unwrap().error.take() {
+            Some(e) => Poll::Ready(Err(e)),
+            None => Poll::Pending,
+        }
+    }
+}
+
+/// A PPPoE session, the PPP link runs as for `PppDevice` over the frames
+/// of an Ethernet interface.
+///
+/// lwIP only implements the client side of the discovery. PPP is closed
+/// when dropped.
+pub struct PppoeDevice {
+    link: PppLink,
+}
+
+impl PppoeDevice {
+    pub fn netif_as_ref(&self) -> &NetIf {
+        &self.link.netif
+    }
+
+    /// Runs the link, until PPP fails.
+    pub fn drive(self) -> PppoeDrive {
+        PppoeDrive { link: self.link }
+    }
+}
+
+pub struct PppoeDrive {
+    link: PppLink,
+}
+
+impl Future for PppoeDrive {
+    type Output = io::Result<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut status = self.link.status.lock().unwrap();
+
+        match status.error.take() {
+            Some(e) => Poll::Ready(Err(e)),
+            None => {
+                status.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct PppStatus {
+    error: Option<io::Error>,
+    waker: Option<Waker>,
+    // set once dropped while the PCB still terminates
+    orphan: Option<PppOrphan>,
+}
+
+/// What the PCB uses until it is freed.
+#[derive(Debug)]
+struct PppOrphan {
+    _netif: NetIf,
+    _auth: Option<(CString, CString)>,
+    _ethernet: Option<NetIf>,
+}
+
+struct PppLink {
+    ppp: *mut lwip::ppp_pcb,
+    netif: NetIf,
+    status: Arc<Mutex<PppStatus>>,
+    // referenced by the PCB
+    auth: Option<(CString, CString)>,
+    generation: usize,
+    // PPPoE runs over it, it is removed once PPP is freed
+    ethernet: Option<NetIf>,
+}
+
+unsafe impl Send for PppLink {}
+
+impl PppLink {
+    /// `create` makes the PCB for the new interface, with the core lock
+    /// held.
+    fn new<F>(config: PppBuilder, ethernet: Option<NetIf>, create: F) -> io::Result<Self>
+    where
+        F: FnOnce(*mut lwip::netif, *mut c_void) -> io::Result<*mut lwip::ppp_pcb>,
+    {
+        crate::stack::stack_init();
+
+        let auth = match config.auth {
+            Some((auth, user, password)) => {
+                let invalid =
+                    |_| io::Error::new(io::ErrorKind::InvalidInput, "invalid credentials");
+                Some((
+                    auth,
+                    CString::new(user).map_err(invalid)?,
+                    CString::new(password).map_err(invalid)?,
+                ))
+            }
+            None => None,
+        };
+
+        let status = Arc::new(Mutex::new(PppStatus::default()));
+        let ctx = Arc::into_raw(status.clone()) as *mut c_void;
+        let pcb: *mut lwip::netif = Box::into_raw(Box::new(unsafe { mem::zeroed() }));
+
+        let ppp = lwip::with_core_lock(|| create(pcb, ctx)).and_then(|ppp| {
+            if ppp.is_null() {
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    "no free PPP PCB (see MEMP_NUM_PPP_PCB)",
+                ));
+            }
+            Ok(ppp)
+        });
+        let ppp = match ppp {
+            Ok(ppp) => ppp,
+            Err(e) => {
+                unsafe {
+                    Arc::from_raw(ctx as *const Mutex<PppStatus>);
+                    Box::from_raw(pcb);
+                }
+                return Err(e);
+            }
+        };
+        let netif = NetIf::attach(pcb);
+
+        lwip::with_core_lock(|| unsafe {
+            // PPP only takes the link up and down
+            lwip::netif_set_up(pcb);
+            if let Some((auth, user, password)) = auth.as_ref() {
+                lwip::ppp_set_auth(ppp, auth.value(), user.as_ptr(), password.as_ptr());
+            }
+            if config.auth_required {
+                (*ppp).settings.set_auth_required(1);
+            }
+            if let Some(addr) = config.local_addr {
+                let addr: lwip::ip4_addr_t = addr.into();
+                (*ppp).ipcp_wantoptions.ouraddr = addr.addr;
+                (*ppp).set_ask_for_local(1);
+            }
+            if let Some(addr) = config.peer_addr {
+                let addr: lwip::ip4_addr_t = addr.into();
+                (*ppp).ipcp_wantoptions.hisaddr = addr.addr;
+            }
+            lwip::ppp_connect(ppp, 0)
+        });
+
+        Ok(PppLink {
+            ppp,
+            netif,
+            status,
+            auth: auth.map(|(_, user, password)| (user, password)),
+            generation: crate::stack::generation(),
+            ethernet,
+        })
+    }
+
+    fn input(&self, buf: &[u8]) {
+        lwip::with_core_lock(|| unsafe {
+            lwip::pppos_input(self.ppp, buf.as_ptr() as *mut u8, buf.len() as c_int)
+        });
+    }
+
+    /// Takes the link down right away, as when the carrier is lost.
+    fn close(&self) {
+        lwip::with_core_lock(|| unsafe { lwip::ppp_close(self.ppp, 1) });
+    }
+}
+
+impl Drop for PppLink {
+    fn drop(&mut self) {
+        if self.generation != crate::stack::generation() {
+            // the PCB went away with its stack
+            return;
+        }
+
+        let mut orphan = Some(PppOrphan {
+            _netif: self.netif.clone(),
+            _auth: self.auth.take(),
+            _ethernet: self.ethernet.take(),
+        });
+        lwip::with_core_lock(|| unsafe {
+            let ctx = (*self.ppp).ctx_cb;
+            // only stops right away once LCP is up or before it started,
+            // otherwise PPP terminates the link first
+            lwip::ppp_close(self.ppp, 1);
+            // also removes the interface
+            if lwip::ppp_free(self.ppp) == lwip::err_enum_t::ERR_OK {
+                Arc::from_raw(ctx as *const Mutex<PppStatus>);
+            } else {
+                // freed by ppp_status once dead
+                self.status.lock().unwrap().orphan = orphan.take();
+            }
+        });
+    }
+}
+
+unsafe extern "C" fn ppp_output(
+    ppp: *mut lwip::ppp_pcb,
+    data: *mut u8,
+    len: u32,
+    _: *mut c_void,
+) -> u32 {
+    let data = Bytes::copy_from_slice(std::slice::from_raw_parts(data, len as usize));
+    match netif_queue_frame((*ppp).netif, data) {
+        lwip::err_enum_t::ERR_OK => len,
+        _ => 0,
+    }
+}
+
+fn ppp_error(code: u32) -> Option<io::Error> {
+    let (kind, msg) = match code {
+        // up, or closed by us
+        lwip::PPPERR_NONE | lwip::PPPERR_USER => return None,
+        lwip::PPPERR_AUTHFAIL => (io::ErrorKind::PermissionDenied, "PPP authentication failed"),
+        lwip::PPPERR_PEERDEAD => (io::ErrorKind::TimedOut, "PPP peer not responding"),
+        lwip::PPPERR_CONNECT => (io::ErrorKind::ConnectionAborted, "PPP link terminated"),
+        lwip::PPPERR_PROTOCOL => (io::ErrorKind::InvalidData, "PPP negotiation failed"),
+        lwip::PPPERR_LOOPBACK => (io::ErrorKind::Other, "PPP link looped back"),
+        _ => (io::ErrorKind::Other, "PPP error"),
+    };
+    Some(io::Error::new(kind, msg))
+}
+
+unsafe extern "C" fn ppp_status(ppp: *mut lwip::ppp_pcb, code: c_int, ctx: *mut c_void) {
+    let status = &*(ctx as *const Mutex<PppStatus>);
+    let mut status = status.lock().unwrap();
+
+    if status.orphan.is_some() {
+        if (*ppp).phase as u32 == lwip::PPP_PHASE_DEAD {
+            let orphan = status.orphan.take();
+            drop(status);
+            // also removes the interface
+            lwip::ppp_free(ppp);
+            Arc::from_raw(ctx as *const Mutex<PppStatus>);
+            // the interfaces take the core lock, held here, when dropped
+            std::thread::spawn(move || drop(orphan));
+        }
+        return;
+    }
+
+    if let Some(e) = ppp_error(code as u32) {
+        status.error = Some(e);
+        if let Some(waker) = status.waker.take() {
+            waker.wake();
+        }
+    }
+}
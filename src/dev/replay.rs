@@ -0,0 +1,195 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::future::Future;
+use std::io::{self, BufReader, Read};
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use ipnetwork::{Ipv4Network, Ipv6Network};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::time::{delay_until, Delay, Instant};
+
+use crate::{Device, DeviceBuilder, Direction, PcapPacket, PcapReader};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Timing {
+    // keep the gaps recorded in the capture
+    Original,
+    // divide the recorded gaps by the given factor
+    Accelerated(f64),
+    // as fast as the stack reads
+    Immediate,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SentPackets(Arc<Mutex<Vec<Vec<u8>>>>);
+
+impl SentPackets {
+    pub fn packets(&self) -> Vec<Vec<u8>> {
+        self.0.lock().unwrap().clone()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[derive(Debug)]
+pub struct PcapDevice {
+    packets: VecDeque<PcapPacket>,
+    timing: Timing,
+    // replay start and timestamp of the first packet
+    start: Option<(Instant, Duration)>,
+    delay: Option<Delay>,
+    sent: SentPackets,
+    config: DeviceBuilder,
+}
+
+impl PcapDevice {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    pub fn from_reader<R: Read>(reader: R) -> io::Result<Self> {
+        // packets recorded on their way out of the stack are what we expect
+        // the stack to send again, do not feed them back.
+        let packets = PcapReader::new(reader)?
+            .filter(|p| match p {
+                Ok(p) => p.direction != Some(Direction::Outbound),
+                Err(_) => true,
+            })
+            .collect::<io::Result<VecDeque<_>>>()?;
+
+        Ok(PcapDevice {
+            packets,
+            timing: Timing::Original,
+            start: None,
+            delay: None,
+            sent: SentPackets::default(),
+            config: DeviceBuilder::default(),
+        })
+    }
+
+    /// Fails unless an acceleration factor is finite and positive.
+    pub fn timing(mut self, timing: Timing) -> io::Result<Self> {
+        if let Timing::Accelerated(factor) = timing {
+            if !factor.is_finite() || factor <= 0.0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "invalid acceleration factor",
+                ));
+            }
+        }
+        self.timing = timing;
+        Ok(self)
+    }
+
+    pub fn mtu(mut self, mtu: u16) -> Self {
+        self.config = self.config.mtu(mtu);
+        self
+    }
+
+    pub fn ipv4(mut self, addr: Ipv4Addr, prefix: u8) -> Self {
+        self.config = self.config.ipv4(addr, prefix);
+        self
+    }
+
+    pub fn ipv6(mut self, addr: Ipv6Addr, prefix: u8) -> Self {
+        self.config = self.config.ipv6(addr, prefix);
+        self
+    }
+
+    pub fn sent(&self) -> SentPackets {
+        self.sent.clone()
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.packets.len()
+    }
+
+    fn deadline(&mut self, ts: Duration) -> Option<Instant> {
+        let factor = match self.timing {
+            Timing::Immediate => return None,
+            Timing::Original => 1.0,
+            Timing::Accelerated(factor) => factor,
+        };
+
+        let (start, first) = *self.start.get_or_insert_with(|| (Instant::now(), ts));
+        let offset = ts.checked_sub(first).unwrap_or(Duration::from_secs(0));
+
+        Some(start + Duration::from_secs_f64(offset.as_secs_f64() / factor))
+    }
+}
+
+impl AsyncRead for PcapDevice {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        let ts = match this.packets.front() {
+            Some(pkt) => pkt.ts,
+            None => return Poll::Ready(Ok(0)), // EOF
+        };
+
+        if let Some(deadline) = this.deadline(ts) {
+            let delay = this.delay.get_or_insert_with(|| delay_until(deadline));
+            if Pin::new(delay).poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+            this.delay = None;
+        }
+
+        let pkt = this.packets.pop_front().unwrap();
+        let len = pkt.data.len().min(buf.len());
+        buf[..len].copy_from_slice(&pkt.data[..len]);
+        Poll::Ready(Ok(len))
+    }
+}
+
+impl AsyncWrite for PcapDevice {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        self.sent.0.lock().unwrap().push(buf.to_vec());
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Device for PcapDevice {
+    fn ipv4(&self) -> Ipv4Network {
+        Device::ipv4(&self.config)
+    }
+
+    fn ipv6(&self) -> Vec<Ipv6Network> {
+        Device::ipv6(&self.config)
+    }
+
+    fn mtu(&self) -> u16 {
+        Device::mtu(&self.config)
+    }
+
+    fn hwaddr(&self) -> Option<[u8; 6]> {
+        Device::hwaddr(&self.config)
+    }
+}
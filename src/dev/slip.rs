@@ -0,0 +1,163 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use super::frame::FrameWriter;
+
+const END: u8 = 0xc0;
+const ESC: u8 = 0xdb;
+const ESC_END: u8 = 0xdc;
+const ESC_ESC: u8 = 0xdd;
+
+const READ_SIZE: usize = 4096;
+// larger frames are line noise or a lost END, they are dropped
+const MAX_FRAME: usize = std::u16::MAX as usize;
+
+pin_project_lite::pin_project! {
+    /// SLIP (RFC 1055) framing over a byte stream such as a serial port:
+    /// each read returns one IP packet and each write sends one.
+    ///
+    /// A write takes the packet once the frame of the previous one was
+    /// written, its own frame goes out with the next writes and flushes.
+    #[derive(Debug)]
+    pub struct SlipDevice<T> {
+        #[pin]
+        underlying: T,
+        read_buf: Vec<u8>,
+        read_pos: usize,
+        read_len: usize,
+        frame: Vec<u8>,
+        escaped: bool,
+        overflow: bool,
+        writer: FrameWriter,
+    }
+}
+
+impl<T> SlipDevice<T> {
+    pub fn new(underlying: T) -> Self {
+        SlipDevice {
+            underlying,
+            read_buf: vec![0; READ_SIZE],
+            read_pos: 0,
+            read_len: 0,
+            frame: Vec::new(),
+            escaped: false,
+            overflow: false,
+            writer: FrameWriter::default(),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.underlying
+    }
+}
+
+fn encode(pkt: &[u8], buf: &mut Vec<u8>) {
+    // flushes any line noise received by the peer
+    buf.push(END);
+    for &b in pkt {
+        match b {
+            END => buf.extend_from_slice(&[ESC, ESC_END]),
+            ESC => buf.extend_from_slice(&[ESC, ESC_ESC]),
+            b => buf.push(b),
+        }
+    }
+    buf.push(END);
+}
+
+impl<T> AsyncRead for SlipDevice<T>
+where
+    T: AsyncRead,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut this = self.project();
+
+        loop {
+            while *this.read_pos < *this.read_len {
+                let b = this.read_buf[*this.read_pos];
+                *this.read_pos += 1;
+
+                let b = match (b, *this.escaped) {
+                    (END, _) => {
+                        *this.escaped = false;
+                        if this.frame.is_empty() {
+                            // back to back ENDs
+                            continue;
+                        }
+                        if *this.overflow {
+                            log::debug!(target: "lwip::slip", "dropping oversized frame");
+                            *this.overflow = false;
+                            this.frame.clear();
+                            continue;
+                        }
+
+                        let len = this.frame.len().min(buf.len());
+                        buf[..len].copy_from_slice(&this.frame[..len]);
+                        this.frame.clear();
+                        return Poll::Ready(Ok(len));
+                    }
+                    (ESC, false) => {
+                        *this.escaped = true;
+                        continue;
+                    }
+                    (ESC_END, true) => END,
+                    (ESC_ESC, true) => ESC,
+                    // protocol violation, RFC 1055 keeps the byte as is
+                    (b, _) => b,
+                };
+                *this.escaped = false;
+
+                if this.frame.len() < MAX_FRAME {
+                    this.frame.push(b);
+                } else {
+                    *this.overflow = true;
+                }
+            }
+
+            match this.underlying.as_mut().poll_read(cx, this.read_buf) {
+                Poll::Ready(Ok(0)) => {
+                    // a partial frame is lost
+                    this.frame.clear();
+                    return Poll::Ready(Ok(0));
+                }
+                Poll::Ready(Ok(len)) => {
+                    *this.read_pos = 0;
+                    *this.read_len = len;
+                }
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl<T> AsyncWrite for SlipDevice<T>
+where
+    T: AsyncWrite,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        let this = self.project();
+        this.writer
+            .poll_write(this.underlying, cx, buf.len(), |frame| encode(buf, frame))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        let this = self.project();
+        this.writer.poll_flush(this.underlying, cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        let this = self.project();
+        this.writer.poll_shutdown(this.underlying, cx)
+    }
+}
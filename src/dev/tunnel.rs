@@ -0,0 +1,196 @@
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::UdpSocket;
+
+use super::frame::FrameWriter;
+
+const HEADER_LEN: usize = 2;
+const MAX_PACKET: usize = std::u16::MAX as usize;
+
+/// Carries IP packets over a host UDP socket, one datagram per packet, to
+/// link two stacks without a TUN device. Datagrams from others than the
+/// peer are dropped.
+#[derive(Debug)]
+pub struct UdpTunnel {
+    socket: UdpSocket,
+    peer: SocketAddr,
+}
+
+impl UdpTunnel {
+    pub fn new(socket: UdpSocket, peer: SocketAddr) -> Self {
+        UdpTunnel { socket, peer }
+    }
+
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    pub fn into_inner(self) -> UdpSocket {
+        self.socket
+    }
+}
+
+impl AsyncRead for UdpTunnel {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        loop {
+            match this.socket.poll_recv_from(cx, buf) {
+                Poll::Ready(Ok((len, from))) if from == this.peer => return Poll::Ready(Ok(len)),
+                Poll::Ready(Ok((_, from))) => {
+                    log::debug!(target: "lwip::tunnel", "dropping datagram from {}", from);
+                }
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl AsyncWrite for UdpTunnel {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        let this = self.get_mut();
+        this.socket.poll_send_to(cx, buf, &this.peer)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+pin_project_lite::pin_project! {
+    /// Carries IP packets over a byte stream such as a Unix socket or a TLS
+    /// connection, each prefixed by its length as a big-endian `u16`.
+    ///
+    /// A write takes the packet once the previous one was written, it goes
+    /// out with the next writes and flushes.
+    #[derive(Debug)]
+    pub struct StreamTunnel<T> {
+        #[pin]
+        underlying: T,
+        read_buf: Vec<u8>,
+        read_len: usize,
+        writer: FrameWriter,
+    }
+}
+
+impl<T> StreamTunnel<T> {
+    pub fn new(underlying: T) -> Self {
+        StreamTunnel {
+            underlying,
+            read_buf: vec![0; HEADER_LEN + MAX_PACKET],
+            read_len: 0,
+            writer: FrameWriter::default(),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.underlying
+    }
+}
+
+impl<T> AsyncRead for StreamTunnel<T>
+where
+    T: AsyncRead,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut this = self.project();
+
+        loop {
+            if *this.read_len >= HEADER_LEN {
+                let len = u16::from_be_bytes([this.read_buf[0], this.read_buf[1]]) as usize;
+                let end = HEADER_LEN + len;
+
+                if *this.read_len >= end {
+                    let copied = len.min(buf.len());
+                    buf[..copied].copy_from_slice(&this.read_buf[HEADER_LEN..HEADER_LEN + copied]);
+                    this.read_buf.copy_within(end..*this.read_len, 0);
+                    *this.read_len -= end;
+
+                    // an empty read would be taken for the end of the stream
+                    if len == 0 {
+                        continue;
+                    }
+                    return Poll::Ready(Ok(copied));
+                }
+            }
+
+            let read_len = *this.read_len;
+            match this
+                .underlying
+                .as_mut()
+                .poll_read(cx, &mut this.read_buf[read_len..])
+            {
+                Poll::Ready(Ok(0)) if read_len == 0 => return Poll::Ready(Ok(0)),
+                Poll::Ready(Ok(0)) => {
+                    return Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "stream closed within a packet",
+                    )))
+                }
+                Poll::Ready(Ok(len)) => *this.read_len += len,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl<T> AsyncWrite for StreamTunnel<T>
+where
+    T: AsyncWrite,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        if buf.len() > MAX_PACKET {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "packet too large",
+            )));
+        }
+
+        let this = self.project();
+        this.writer
+            .poll_write(this.underlying, cx, buf.len(), |frame| {
+                frame.extend_from_slice(&(buf.len() as u16).to_be_bytes());
+                frame.extend_from_slice(buf);
+            })
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        let this = self.project();
+        this.writer.poll_flush(this.underlying, cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        let this = self.project();
+        this.writer.poll_shutdown(this.underlying, cx)
+    }
+}
@@ -0,0 +1,154 @@
+use std::net::Ipv4Addr;
+
+pub(crate) const BOOTREQUEST: u8 = 1;
+pub(crate) const BOOTREPLY: u8 = 2;
+
+pub(crate) const DHCPDISCOVER: u8 = 1;
+pub(crate) const DHCPOFFER: u8 = 2;
+pub(crate) const DHCPREQUEST: u8 = 3;
+pub(crate) const DHCPDECLINE: u8 = 4;
+pub(crate) const DHCPACK: u8 = 5;
+pub(crate) const DHCPNAK: u8 = 6;
+pub(crate) const DHCPRELEASE: u8 = 7;
+pub(crate) const DHCPINFORM: u8 = 8;
+
+pub(crate) const OPTION_PAD: u8 = 0;
+pub(crate) const OPTION_SUBNET_MASK: u8 = 1;
+pub(crate) const OPTION_ROUTER: u8 = 3;
+pub(crate) const OPTION_DNS_SERVER: u8 = 6;
+pub(crate) const OPTION_REQUESTED_IP: u8 = 50;
+pub(crate) const OPTION_LEASE_TIME: u8 = 51;
+pub(crate) const OPTION_MESSAGE_TYPE: u8 = 53;
+pub(crate) const OPTION_SERVER_ID: u8 = 54;
+pub(crate) const OPTION_RENEWAL_TIME: u8 = 58;
+pub(crate) const OPTION_REBINDING_TIME: u8 = 59;
+pub(crate) const OPTION_END: u8 = 255;
+
+const MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+const OPTIONS_OFFSET: usize = 240;
+
+/// A BOOTP message with its DHCP options (RFC 2131).
+#[derive(Debug, Clone)]
+pub(crate) struct Message {
+    pub op: u8,
+    pub htype: u8,
+    pub hlen: u8,
+    pub xid: u32,
+    pub flags: u16,
+    pub ciaddr: Ipv4Addr,
+    pub yiaddr: Ipv4Addr,
+    pub giaddr: Ipv4Addr,
+    pub chaddr: [u8; 16],
+    pub options: Vec<(u8, Vec<u8>)>,
+}
+
+fn ipv4(buf: &[u8]) -> Ipv4Addr {
+    Ipv4Addr::new(buf[0], buf[1], buf[2], buf[3])
+}
+
+impl Message {
+    pub fn parse(buf: &[u8]) -> Option<Self> {
+        if buf.len() < OPTIONS_OFFSET || buf[236..240] != MAGIC_COOKIE {
+            return None;
+        }
+
+        let mut chaddr = [0; 16];
+        chaddr.copy_from_slice(&buf[28..44]);
+
+        let mut options = Vec::new();
+        let mut opts = &buf[OPTIONS_OFFSET..];
+        while let Some(&code) = opts.first() {
+            match code {
+                OPTION_PAD => opts = &opts[1..],
+                OPTION_END => break,
+                _ => {
+                    let len = *opts.get(1)? as usize;
+                    let value = opts.get(2..2 + len)?;
+                    options.push((code, value.to_vec()));
+                    opts = &opts[2 + len..];
+                }
+            }
+        }
+
+        Some(Message {
+            op: buf[0],
+            htype: buf[1],
+            hlen: buf[2],
+            xid: u32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]),
+            flags: u16::from_be_bytes([buf[10], buf[11]]),
+            ciaddr: ipv4(&buf[12..16]),
+            yiaddr: ipv4(&buf[16..20]),
+            giaddr: ipv4(&buf[24..28]),
+            chaddr,
+            options,
+        })
+    }
+
+    /// A reply to `self`, without options.
+    pub fn reply(&self, yiaddr: Ipv4Addr) -> Self {
+        Message {
+            op: BOOTREPLY,
+            htype: self.htype,
+            hlen: self.hlen,
+            xid: self.xid,
+            flags: self.flags,
+            ciaddr: self.ciaddr,
+            yiaddr,
+            giaddr: self.giaddr,
+            chaddr: self.chaddr,
+            options: Vec::new(),
+        }
+    }
+
+    pub fn option(&self, code: u8) -> Option<&[u8]> {
+        self.options
+            .iter()
+            .find(|(c, _)| *c == code)
+            .map(|(_, v)| &v[..])
+    }
+
+    pub fn option_ipv4(&self, code: u8) -> Option<Ipv4Addr> {
+        self.option(code).filter(|v| v.len() == 4).map(ipv4)
+    }
+
+    pub fn message_type(&self) -> Option<u8> {
+        self.option(OPTION_MESSAGE_TYPE)
+            .filter(|v| v.len() == 1)
+            .map(|v| v[0])
+    }
+
+    pub fn hwaddr(&self) -> Option<[u8; 6]> {
+        if self.hlen != 6 {
+            return None;
+        }
+        let mut hwaddr = [0; 6];
+        hwaddr.copy_from_slice(&self.chaddr[..6]);
+        Some(hwaddr)
+    }
+
+    pub fn push_option(&mut self, code: u8, value: &[u8]) {
+        self.options.push((code, value.to_vec()));
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = vec![0; OPTIONS_OFFSET];
+        buf[0] = self.op;
+        buf[1] = self.htype;
+        buf[2] = self.hlen;
+        buf[4..8].copy_from_slice(&self.xid.to_be_bytes());
+        buf[10..12].copy_from_slice(&self.flags.to_be_bytes());
+        buf[12..16].copy_from_slice(&self.ciaddr.octets());
+        buf[16..20].copy_from_slice(&self.yiaddr.octets());
+        buf[24..28].copy_from_slice(&self.giaddr.octets());
+        buf[28..44].copy_from_slice(&self.chaddr);
+        buf[236..240].copy_from_slice(&MAGIC_COOKIE);
+
+        for (code, value) in self.options.iter() {
+            buf.push(*code);
+            buf.push(value.len() as u8);
+            buf.extend_from_slice(value);
+        }
+        buf.push(OPTION_END);
+        buf
+    }
+}
@@ -0,0 +1,4 @@
+mod message;
+
+mod server;
+pub use self::server::*;
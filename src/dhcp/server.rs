@@ -0,0 +1,541 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::Ipv4Addr;
+use std::os::raw::c_void;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use bytes::Bytes;
+use ipnetwork::Ipv4Network;
+
+use crate::dhcp::message::*;
+use crate::lwip::{self, FromPbuf};
+use crate::NetIf;
+
+const DHCP_SERVER_PORT: u16 = 67;
+const DHCP_CLIENT_PORT: u16 = 68;
+
+const SOF_BROADCAST: u8 = 0x20; /* TODO: bindgen skips casted defines */
+
+// how long an offered address is held for the client to request it
+const OFFER_TIME: Duration = Duration::from_secs(60);
+
+/// An address handed out by a `DhcpServer`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LeaseRecord {
+    pub hwaddr: [u8; 6],
+    pub addr: Ipv4Addr,
+    /// Wall-clock time, for the store. The server itself expires the lease
+    /// on the stack clock, see `lwip::time`.
+    pub expires: SystemTime,
+}
+
+/// Persistence of the leases of a `DhcpServer`.
+///
+/// `save` and `remove` are called from the stack thread with the core lock
+/// held, every packet of every interface waits for them: they must not
+/// block, e.g. hand the lease over to a thread writing it out. Should they
+/// panic, the request is dropped. `load` is called once, by
+/// `DhcpServerBuilder::bind`.
+pub trait LeaseStore: Send {
+    fn load(&mut self) -> io::Result<Vec<LeaseRecord>>;
+
+    /// Adds the lease, or updates the one with the same `hwaddr`. The
+    /// client is not acknowledged if it fails.
+    fn save(&mut self, lease: &LeaseRecord) -> io::Result<()>;
+
+    fn remove(&mut self, lease: &LeaseRecord) -> io::Result<()>;
+}
+
+/// Keeps the leases in memory, clones share the same leases.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryLeaseStore(Arc<Mutex<Vec<LeaseRecord>>>);
+
+impl MemoryLeaseStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn leases(&self) -> Vec<LeaseRecord> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+impl LeaseStore for MemoryLeaseStore {
+    fn load(&mut self) -> io::Result<Vec<LeaseRecord>> {
+        Ok(self.leases())
+    }
+
+    fn save(&mut self, lease: &LeaseRecord) -> io::Result<()> {
+        let mut leases = self.0.lock().unwrap();
+        leases.retain(|l| l.hwaddr != lease.hwaddr);
+        leases.push(lease.clone());
+        Ok(())
+    }
+
+    fn remove(&mut self, lease: &LeaseRecord) -> io::Result<()> {
+        self.0.lock().unwrap().retain(|l| l.hwaddr != lease.hwaddr);
+        Ok(())
+    }
+}
+
+pub struct DhcpServerBuilder {
+    pools: Vec<(Ipv4Addr, Ipv4Addr)>,
+    static_leases: HashMap<[u8; 6], Ipv4Addr>,
+    router: Option<Ipv4Addr>,
+    dns: Vec<Ipv4Addr>,
+    lease_time: Duration,
+    store: Box<dyn LeaseStore>,
+}
+
+impl Default for DhcpServerBuilder {
+    fn default() -> Self {
+        DhcpServerBuilder {
+            pools: Vec::new(),
+            static_leases: HashMap::new(),
+            router: None,
+            dns: Vec::new(),
+            lease_time: Duration::from_secs(3600),
+            store: Box::new(MemoryLeaseStore::new()),
+        }
+    }
+}
+
+impl DhcpServerBuilder {
+    /// Adds the addresses from `start` to `end`, inclusive.
+    pub fn pool(mut self, start: Ipv4Addr, end: Ipv4Addr) -> Self {
+        self.pools.push((start, end));
+        self
+    }
+
+    pub fn static_lease(mut self, hwaddr: [u8; 6], addr: Ipv4Addr) -> Self {
+        self.static_leases.insert(hwaddr, addr);
+        self
+    }
+
+    pub fn router(mut self, router: Ipv4Addr) -> Self {
+        self.router = Some(router);
+        self
+    }
+
+    pub fn dns(mut self, server: Ipv4Addr) -> Self {
+        self.dns.push(server);
+        self
+    }
+
+    pub fn lease_time(mut self, lease_time: Duration) -> Self {
+        self.lease_time = lease_time;
+        self
+    }
+
+    pub fn store<S: LeaseStore + 'static>(mut self, store: S) -> Self {
+        self.store = Box::new(store);
+        self
+    }
+
+    /// Serves the clients of `netif`, from its IPv4 address.
+    pub fn bind(mut self, netif: &NetIf) -> io::Result<DhcpServer> {
+        let net = netif.ipv4();
+        if net.ip().is_unspecified() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrNotAvailable,
+                "interface has no IPv4 address",
+            ));
+        }
+
+        let in_subnet = |addr: &Ipv4Addr| net.contains(*addr) && *addr != net.ip();
+        let pools_valid = self
+            .pools
+            .iter()
+            .all(|(start, end)| start <= end && in_subnet(start) && in_subnet(end));
+        if !pools_valid || !self.static_leases.values().all(in_subnet) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "addresses must be in the interface subnet",
+            ));
+        }
+
+        let mut clock = StackClock::new();
+        let now = clock.now();
+        let wall = SystemTime::now();
+        let leases = self
+            .store
+            .load()?
+            .into_iter()
+            .filter(|l| in_subnet(&l.addr))
+            .filter_map(|l| {
+                let left = l.expires.duration_since(wall).ok()?;
+                Some((
+                    l.hwaddr,
+                    Binding {
+                        addr: l.addr,
+                        expires: now + left,
+                        record: Some(l),
+                    },
+                ))
+            })
+            .collect();
+
+        let state = Arc::new(Mutex::new(ServerState {
+            clock,
+            net,
+            pools: self.pools,
+            static_leases: self.static_leases,
+            router: self.router,
+            dns: self.dns,
+            lease_time: self.lease_time,
+            leases,
+            offers: HashMap::new(),
+            declined: HashMap::new(),
+            store: self.store,
+        }));
+
+        let ip: lwip::ip_addr_t = net.ip().into();
+        let index = netif.index();
+        let arg = Arc::into_raw(state.clone()) as *mut c_void;
+
+        let pcb = lwip::with_core_lock(|| unsafe {
+            let pcb = lwip::udp_new_ip_type(lwip::lwip_ip_addr_type_IPADDR_TYPE_V4 as u8);
+            if pcb.is_null() {
+                return Err(io::Error::from(io::ErrorKind::Other));
+            }
+
+            let ret: io::Result<()> = lwip::udp_bind(pcb, &ip, DHCP_SERVER_PORT).into();
+            if let Err(e) = ret {
+                lwip::udp_remove(pcb);
+                return Err(e);
+            }
+            (*pcb).netif_idx = index;
+            // replies to clients without an address are broadcast
+            (*pcb).so_options |= SOF_BROADCAST;
+            lwip::udp_recv(pcb, Some(server_recv), arg);
+            Ok(pcb)
+        });
+
+        match pcb {
+            Ok(pcb) => Ok(DhcpServer {
+                pcb,
+                state,
+                generation: crate::stack::generation(),
+            }),
+            Err(e) => {
+                unsafe { Arc::from_raw(arg as *const Mutex<ServerState>) };
+                Err(e)
+            }
+        }
+    }
+}
+
+/// DHCPv4 server running in the stack, on one interface.
+///
+/// Stops serving when dropped.
+pub struct DhcpServer {
+    pcb: *mut lwip::udp_pcb,
+    state: Arc<Mutex<ServerState>>,
+    generation: usize,
+}
+
+impl DhcpServer {
+    pub fn builder() -> DhcpServerBuilder {
+        DhcpServerBuilder::default()
+    }
+
+    /// Leases not expired yet.
+    pub fn leases(&self) -> Vec<LeaseRecord> {
+        let mut state = self.state.lock().unwrap();
+        let now = state.clock.now();
+
+        state
+            .leases
+            .values()
+            .filter(|l| l.expires > now)
+            .filter_map(|l| l.record.clone())
+            .collect()
+    }
+}
+
+impl Drop for DhcpServer {
+    fn drop(&mut self) {
+        if self.generation != crate::stack::generation() {
+            // the PCB went away with its stack
+            return;
+        }
+
+        lwip::with_core_lock(|| unsafe {
+            let arg = (*self.pcb).recv_arg;
+            lwip::udp_remove(self.pcb);
+            Arc::from_raw(arg as *const Mutex<ServerState>);
+        });
+    }
+}
+
+unsafe impl Send for DhcpServer {}
+unsafe impl Sync for DhcpServer {}
+
+// lwIP time, which wraps around, as the time elapsed since the server
+// started. Follows the virtual clock of the tests, see `lwip::time`.
+struct StackClock {
+    last: u32,
+    elapsed: Duration,
+}
+
+impl StackClock {
+    fn new() -> Self {
+        StackClock {
+            last: crate::time::now(),
+            elapsed: Duration::from_secs(0),
+        }
+    }
+
+    fn now(&mut self) -> Duration {
+        let now = crate::time::now();
+        self.elapsed += Duration::from_millis(now.wrapping_sub(self.last) as u64);
+        self.last = now;
+        self.elapsed
+    }
+}
+
+// an address held for a client, on the stack clock
+struct Binding {
+    addr: Ipv4Addr,
+    expires: Duration,
+    // what the store knows of it, none for offers
+    record: Option<LeaseRecord>,
+}
+
+struct ServerState {
+    clock: StackClock,
+    net: Ipv4Network,
+    pools: Vec<(Ipv4Addr, Ipv4Addr)>,
+    static_leases: HashMap<[u8; 6], Ipv4Addr>,
+    router: Option<Ipv4Addr>,
+    dns: Vec<Ipv4Addr>,
+    lease_time: Duration,
+    leases: HashMap<[u8; 6], Binding>,
+    // addresses offered and not requested yet
+    offers: HashMap<[u8; 6], Binding>,
+    declined: HashMap<Ipv4Addr, Duration>,
+    store: Box<dyn LeaseStore>,
+}
+
+impl ServerState {
+    fn in_pool(&self, addr: Ipv4Addr) -> bool {
+        self.pools
+            .iter()
+            .any(|&(start, end)| start <= addr && addr <= end)
+    }
+
+    fn available(&self, hwaddr: &[u8; 6], addr: Ipv4Addr, now: Duration) -> bool {
+        match self.static_leases.get(hwaddr) {
+            Some(&fixed) => return addr == fixed,
+            None if self.static_leases.values().any(|&a| a == addr) => return false,
+            None => {}
+        }
+
+        let taken = self
+            .leases
+            .iter()
+            .chain(self.offers.iter())
+            .any(|(h, l)| l.addr == addr && h != hwaddr && l.expires > now);
+        let declined = self.declined.get(&addr).map_or(false, |&t| t > now);
+
+        !taken && !declined && self.in_pool(addr)
+    }
+
+    fn select(
+        &self,
+        hwaddr: &[u8; 6],
+        requested: Option<Ipv4Addr>,
+        now: Duration,
+    ) -> Option<Ipv4Addr> {
+        if let Some(&fixed) = self.static_leases.get(hwaddr) {
+            return Some(fixed);
+        }
+
+        let previous = self.leases.get(hwaddr).map(|l| l.addr);
+        let offered = self.offers.get(hwaddr).map(|l| l.addr);
+        previous
+            .into_iter()
+            .chain(offered)
+            .chain(requested)
+            .find(|&addr| self.available(hwaddr, addr, now))
+            .or_else(|| {
+                self.pools
+                    .iter()
+                    .flat_map(|&(start, end)| {
+                        (u32::from(start)..=u32::from(end)).map(Ipv4Addr::from)
+                    })
+                    .find(|&addr| self.available(hwaddr, addr, now))
+            })
+    }
+
+    // holds the address for the client until it requests it
+    fn offer(&mut self, hwaddr: [u8; 6], addr: Ipv4Addr, now: Duration) {
+        let offer = Binding {
+            addr,
+            expires: now + OFFER_TIME,
+            record: None,
+        };
+        self.offers.insert(hwaddr, offer);
+    }
+
+    fn commit(&mut self, hwaddr: [u8; 6], addr: Ipv4Addr, now: Duration) -> io::Result<()> {
+        let record = LeaseRecord {
+            hwaddr,
+            addr,
+            expires: SystemTime::now() + self.lease_time,
+        };
+        self.store.save(&record)?;
+        self.offers.remove(&hwaddr);
+        self.leases.insert(
+            hwaddr,
+            Binding {
+                addr,
+                expires: now + self.lease_time,
+                record: Some(record),
+            },
+        );
+        Ok(())
+    }
+
+    fn release(&mut self, hwaddr: &[u8; 6]) {
+        self.offers.remove(hwaddr);
+        if let Some(record) = self.leases.remove(hwaddr).and_then(|l| l.record) {
+            if let Err(e) = self.store.remove(&record) {
+                log::warn!(target: "lwip::dhcp", "unable to remove lease: {}", e);
+            }
+        }
+    }
+
+    fn reply(&self, msg: &Message, msg_type: u8, yiaddr: Ipv4Addr) -> Message {
+        let mut reply = msg.reply(yiaddr);
+        reply.push_option(OPTION_MESSAGE_TYPE, &[msg_type]);
+        reply.push_option(OPTION_SERVER_ID, &self.net.ip().octets());
+        if msg_type == DHCPNAK {
+            return reply;
+        }
+
+        if msg_type != DHCPACK || msg.message_type() != Some(DHCPINFORM) {
+            let secs = self.lease_time.as_secs().min(u32::max_value() as u64) as u32;
+            reply.push_option(OPTION_LEASE_TIME, &secs.to_be_bytes());
+            reply.push_option(OPTION_RENEWAL_TIME, &(secs / 2).to_be_bytes());
+            reply.push_option(
+                OPTION_REBINDING_TIME,
+                &((secs as u64 * 7 / 8) as u32).to_be_bytes(),
+            );
+        }
+        reply.push_option(OPTION_SUBNET_MASK, &self.net.mask().octets());
+        if let Some(router) = self.router {
+            reply.push_option(OPTION_ROUTER, &router.octets());
+        }
+        if !self.dns.is_empty() {
+            let dns: Vec<u8> = self.dns.iter().flat_map(|a| a.octets().to_vec()).collect();
+            reply.push_option(OPTION_DNS_SERVER, &dns);
+        }
+        reply
+    }
+
+    /// Returns the reply and where to send it.
+    fn handle(&mut self, msg: &Message) -> Option<(Ipv4Addr, Message)> {
+        if msg.op != BOOTREQUEST {
+            return None;
+        }
+        let hwaddr = msg.hwaddr()?;
+        let now = self.clock.now();
+        self.offers.retain(|_, o| o.expires > now);
+        let server_id = msg.option_ipv4(OPTION_SERVER_ID);
+        let ours = server_id.map_or(true, |id| id == self.net.ip());
+
+        let (msg_type, yiaddr) = match msg.message_type()? {
+            DHCPDISCOVER => {
+                let requested = msg.option_ipv4(OPTION_REQUESTED_IP);
+                let addr = self.select(&hwaddr, requested, now)?;
+                self.offer(hwaddr, addr, now);
+                (DHCPOFFER, addr)
+            }
+            DHCPREQUEST if !ours => {
+                // the client picked another server
+                self.offers.remove(&hwaddr);
+                return None;
+            }
+            DHCPREQUEST => {
+                let addr = msg.option_ipv4(OPTION_REQUESTED_IP).unwrap_or(msg.ciaddr);
+                if addr.is_unspecified() || !self.available(&hwaddr, addr, now) {
+                    let nak = self.reply(msg, DHCPNAK, Ipv4Addr::UNSPECIFIED);
+                    return Some((Ipv4Addr::BROADCAST, nak));
+                }
+                if let Err(e) = self.commit(hwaddr, addr, now) {
+                    log::warn!(target: "lwip::dhcp", "unable to save lease: {}", e);
+                    return None;
+                }
+                (DHCPACK, addr)
+            }
+            DHCPDECLINE if ours => {
+                if let Some(addr) = msg.option_ipv4(OPTION_REQUESTED_IP) {
+                    self.declined.insert(addr, now + self.lease_time);
+                }
+                self.release(&hwaddr);
+                return None;
+            }
+            DHCPRELEASE if ours => {
+                if self.leases.get(&hwaddr).map(|l| l.addr) == Some(msg.ciaddr) {
+                    self.release(&hwaddr);
+                }
+                return None;
+            }
+            DHCPINFORM => (DHCPACK, Ipv4Addr::UNSPECIFIED),
+            _ => return None,
+        };
+
+        let dst = if msg.ciaddr.is_unspecified() {
+            Ipv4Addr::BROADCAST
+        } else {
+            msg.ciaddr
+        };
+        Some((dst, self.reply(msg, msg_type, yiaddr)))
+    }
+}
+
+unsafe extern "C" fn server_recv(
+    arg: *mut c_void,
+    pcb: *mut lwip::udp_pcb,
+    p: *mut lwip::pbuf,
+    _: *const lwip::ip_addr_t,
+    _: u16,
+) {
+    let state = &*(arg as *const Mutex<ServerState>);
+    let buf = Bytes::from_pbuf(p);
+    lwip::pbuf_free(p);
+
+    let reply = match Message::parse(&buf) {
+        Some(msg) => {
+            let mut state = state.lock().unwrap();
+            // must not unwind into lwIP, nor poison the state with the guard
+            match panic::catch_unwind(AssertUnwindSafe(|| state.handle(&msg))) {
+                Ok(reply) => reply,
+                Err(_) => {
+                    log::warn!(target: "lwip::dhcp", "request dropped, the server panicked");
+                    None
+                }
+            }
+        }
+        None => None,
+    };
+
+    if let Some((dst, reply)) = reply {
+        let buf = reply.encode();
+        let dst: lwip::ip_addr_t = dst.into();
+        let p = lwip::pbuf_alloc(
+            lwip::pbuf_layer::PBUF_TRANSPORT,
+            buf.len() as u16,
+            lwip::pbuf_type::PBUF_RAM,
+        );
+        if p.is_null() {
+            return;
+        }
+        lwip::pbuf_take(p, buf.as_ptr() as *const c_void, buf.len() as u16);
+        lwip::udp_sendto(pcb, p, &dst, DHCP_CLIENT_PORT);
+        lwip::pbuf_free(p);
+    }
+}
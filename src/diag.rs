@@ -0,0 +1,65 @@
+use std::os::raw::{c_char, c_int, c_uint};
+
+use log::{log, log_enabled, Level};
+
+use crate::lwip;
+
+// Indexed by the module encoded in the *_DEBUG flags (see lwipopts.h).
+const TARGETS: [&str; 26] = [
+    "lwip",
+    "lwip::raw",
+    "lwip::ppp",
+    "lwip::mem",
+    "lwip::memp",
+    "lwip::pbuf",
+    "lwip::api_lib",
+    "lwip::api_msg",
+    "lwip::tcpip",
+    "lwip::netif",
+    "lwip::sockets",
+    "lwip::dns",
+    "lwip::autoip",
+    "lwip::dhcp",
+    "lwip::ip",
+    "lwip::ip_reass",
+    "lwip::ip6",
+    "lwip::icmp",
+    "lwip::igmp",
+    "lwip::udp",
+    "lwip::tcp",
+    "lwip::tcp::input",
+    "lwip::tcp::output",
+    "lwip::tcp::rst",
+    "lwip::tcp::rto",
+    "lwip::tcp::cwnd",
+];
+
+fn target(debug: c_uint) -> &'static str {
+    TARGETS.get((debug >> 8) as usize).unwrap_or(&TARGETS[0])
+}
+
+fn level(debug: c_uint) -> Level {
+    match debug & lwip::LWIP_DBG_MASK_LEVEL {
+        lwip::LWIP_DBG_LEVEL_ALL if debug & lwip::LWIP_DBG_TRACE != 0 => Level::Trace,
+        lwip::LWIP_DBG_LEVEL_ALL => Level::Debug,
+        lwip::LWIP_DBG_LEVEL_WARNING => Level::Warn,
+        _ => Level::Error,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn lwip_rs_log_enabled(debug: c_uint) -> c_int {
+    log_enabled!(target: target(debug), level(debug)) as c_int
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn lwip_rs_log(debug: c_uint, msg: *const c_char, len: usize) {
+    let msg = std::slice::from_raw_parts(msg as *const u8, len);
+
+    log!(
+        target: target(debug),
+        level(debug),
+        "{}",
+        String::from_utf8_lossy(msg).trim_end()
+    );
+}
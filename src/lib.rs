@@ -1,27 +1,35 @@
 mod lwip;
 
+mod diag;
+
 mod raw;
 pub use raw::*;
 
+mod udp;
+pub use udp::*;
+
 pub mod tcp;
 pub use tcp::*;
 
 mod netconn;
 pub use netconn::*;
 
-mod dev;
+pub mod dev;
 pub use dev::*;
 
-use std::io;
-use std::sync::Once;
+mod dhcp;
+pub use dhcp::*;
+
+mod mdns;
+pub use mdns::*;
+
+mod ra;
+pub use ra::*;
+
+mod stack;
+pub use stack::*;
 
-static mut BUILDER_INIT: bool = false;
-static BUILDER_INIT_ONCE: Once = Once::new();
+mod stats;
+pub use stats::*;
 
-pub(crate) fn stack_init() {
-    BUILDER_INIT_ONCE.call_once(|| unsafe {
-        let err: io::Result<()> = lwip::tcpip_init_block().into();
-        err.expect("unable to initialise the TCP/IP stack");
-        BUILDER_INIT = true;
-    });
-}
+pub mod time;
@@ -30,6 +30,16 @@ impl Into<io::Error> for err_t {
     }
 }
 
+pub(crate) fn with_core_lock<F, R>(f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    unsafe { sys_lock_tcpip_core() };
+    let ret = f();
+    unsafe { sys_unlock_tcpip_core() };
+    ret
+}
+
 pub trait IntoPbuf {
     fn into_pbuf(self) -> *mut pbuf;
 }
@@ -144,7 +154,7 @@ impl Into<Ipv6Addr> for ip6_addr {
     fn into(self) -> Ipv6Addr {
         let mut buf = [0; 16];
         for n in 0..=3 {
-            NativeEndian::write_u32(&mut buf[(n + 0)..(n + 4)], self.addr[n]);
+            NativeEndian::write_u32(&mut buf[(n * 4)..(n * 4 + 4)], self.addr[n]);
         }
         Ipv6Addr::from(buf)
     }
@@ -0,0 +1,238 @@
+use std::fmt;
+
+pub(crate) const TYPE_A: u16 = 1;
+pub(crate) const TYPE_PTR: u16 = 12;
+pub(crate) const TYPE_TXT: u16 = 16;
+pub(crate) const TYPE_AAAA: u16 = 28;
+pub(crate) const TYPE_SRV: u16 = 33;
+pub(crate) const TYPE_ANY: u16 = 255;
+
+pub(crate) const CLASS_IN: u16 = 1;
+/// In questions, asks for a unicast response (RFC 6762, section 5.4).
+pub(crate) const CLASS_UNICAST_RESPONSE: u16 = 0x8000;
+/// In records, replaces the cached ones (RFC 6762, section 10.2).
+pub(crate) const CLASS_CACHE_FLUSH: u16 = 0x8000;
+
+pub(crate) const FLAG_RESPONSE: u16 = 0x8000;
+pub(crate) const FLAG_AUTHORITATIVE: u16 = 0x0400;
+
+const HEADER_LEN: usize = 12;
+const POINTER: u8 = 0xc0;
+
+/// A domain name, as its labels.
+#[derive(Clone, PartialEq, Eq)]
+pub(crate) struct Name(Vec<String>);
+
+impl Name {
+    pub fn new(name: &str) -> Self {
+        Name(
+            name.split('.')
+                .filter(|l| !l.is_empty())
+                .map(String::from)
+                .collect(),
+        )
+    }
+
+    /// `label` followed by `self`, labels may contain dots.
+    pub fn child(&self, label: &str) -> Self {
+        let mut labels = vec![label.to_string()];
+        labels.extend(self.0.iter().cloned());
+        Name(labels)
+    }
+
+    /// Case-insensitive, as DNS names compare.
+    pub fn matches(&self, other: &Name) -> bool {
+        self.0.len() == other.0.len()
+            && self
+                .0
+                .iter()
+                .zip(other.0.iter())
+                .all(|(a, b)| a.eq_ignore_ascii_case(b))
+    }
+
+    fn parse(buf: &[u8], mut pos: usize) -> Option<(Self, usize)> {
+        let mut labels = Vec::new();
+        let mut end = None;
+
+        // bounded, to stop on pointer loops
+        for _ in 0..128 {
+            let len = *buf.get(pos)? as usize;
+            if len == 0 {
+                return Some((Name(labels), end.unwrap_or(pos + 1)));
+            }
+
+            if len as u8 & POINTER == POINTER {
+                let low = *buf.get(pos + 1)? as usize;
+                end.get_or_insert(pos + 2);
+                pos = (len & 0x3f) << 8 | low;
+                continue;
+            }
+
+            let label = buf.get(pos + 1..pos + 1 + len)?;
+            labels.push(String::from_utf8_lossy(label).into_owned());
+            pos += 1 + len;
+        }
+        None
+    }
+
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        for label in self.0.iter() {
+            let label = &label.as_bytes()[..label.len().min(63)];
+            buf.push(label.len() as u8);
+            buf.extend_from_slice(label);
+        }
+        buf.push(0);
+    }
+}
+
+impl fmt::Debug for Name {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.", self.0.join("."))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Question {
+    pub name: Name,
+    pub qtype: u16,
+    pub unicast_response: bool,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Record {
+    pub name: Name,
+    pub rtype: u16,
+    pub cache_flush: bool,
+    pub ttl: u32,
+    pub data: Vec<u8>,
+}
+
+impl Record {
+    fn parse(buf: &[u8], pos: usize) -> Option<(Self, usize)> {
+        let (name, next) = Name::parse(buf, pos)?;
+        let fixed = buf.get(next..next + 10)?;
+        let word = |pos: usize| u16::from_be_bytes([fixed[pos], fixed[pos + 1]]);
+        let len = word(8) as usize;
+        let data = buf.get(next + 10..next + 10 + len)?;
+
+        let record = Record {
+            name,
+            rtype: word(0),
+            cache_flush: word(2) & CLASS_CACHE_FLUSH != 0,
+            ttl: u32::from_be_bytes([fixed[4], fixed[5], fixed[6], fixed[7]]),
+            data: data.to_vec(),
+        };
+        Some((record, next + 10 + len))
+    }
+
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.name.encode(buf);
+        buf.extend_from_slice(&self.rtype.to_be_bytes());
+        let class = if self.cache_flush {
+            CLASS_IN | CLASS_CACHE_FLUSH
+        } else {
+            CLASS_IN
+        };
+        buf.extend_from_slice(&class.to_be_bytes());
+        buf.extend_from_slice(&self.ttl.to_be_bytes());
+        buf.extend_from_slice(&(self.data.len() as u16).to_be_bytes());
+        buf.extend_from_slice(&self.data);
+    }
+}
+
+/// A DNS message (RFC 1035), names in the data of received records are
+/// left compressed.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Message {
+    pub id: u16,
+    pub flags: u16,
+    pub questions: Vec<Question>,
+    pub answers: Vec<Record>,
+    /// The records a probe is for (RFC 6762, section 8.2).
+    pub authority: Vec<Record>,
+    pub additional: Vec<Record>,
+}
+
+impl Message {
+    pub fn parse(buf: &[u8]) -> Option<Self> {
+        if buf.len() < HEADER_LEN {
+            return None;
+        }
+        let word = |pos: usize| u16::from_be_bytes([buf[pos], buf[pos + 1]]);
+
+        let mut questions = Vec::new();
+        let mut pos = HEADER_LEN;
+        for _ in 0..word(4) {
+            let (name, next) = Name::parse(buf, pos)?;
+            let fixed = buf.get(next..next + 4)?;
+            let class = u16::from_be_bytes([fixed[2], fixed[3]]);
+            questions.push(Question {
+                name,
+                qtype: u16::from_be_bytes([fixed[0], fixed[1]]),
+                unicast_response: class & CLASS_UNICAST_RESPONSE != 0,
+            });
+            pos = next + 4;
+        }
+
+        // records are only looked at for conflicts, a truncated section
+        // keeps what was parsed
+        let mut sections = vec![Vec::new(), Vec::new(), Vec::new()];
+        'parse: for (idx, records) in sections.iter_mut().enumerate() {
+            for _ in 0..word(6 + idx * 2) {
+                match Record::parse(buf, pos) {
+                    Some((record, next)) => {
+                        records.push(record);
+                        pos = next;
+                    }
+                    None => break 'parse,
+                }
+            }
+        }
+        let additional = sections.pop().unwrap();
+        let authority = sections.pop().unwrap();
+        let answers = sections.pop().unwrap();
+
+        Some(Message {
+            id: word(0),
+            flags: word(2),
+            questions,
+            answers,
+            authority,
+            additional,
+        })
+    }
+
+    pub fn is_response(&self) -> bool {
+        self.flags & FLAG_RESPONSE != 0
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(512);
+        buf.extend_from_slice(&self.id.to_be_bytes());
+        buf.extend_from_slice(&self.flags.to_be_bytes());
+        buf.extend_from_slice(&(self.questions.len() as u16).to_be_bytes());
+        buf.extend_from_slice(&(self.answers.len() as u16).to_be_bytes());
+        buf.extend_from_slice(&(self.authority.len() as u16).to_be_bytes());
+        buf.extend_from_slice(&(self.additional.len() as u16).to_be_bytes());
+
+        for question in self.questions.iter() {
+            question.name.encode(&mut buf);
+            buf.extend_from_slice(&question.qtype.to_be_bytes());
+            let class = if question.unicast_response {
+                CLASS_IN | CLASS_UNICAST_RESPONSE
+            } else {
+                CLASS_IN
+            };
+            buf.extend_from_slice(&class.to_be_bytes());
+        }
+        for record in self
+            .answers
+            .iter()
+            .chain(self.authority.iter())
+            .chain(self.additional.iter())
+        {
+            record.encode(&mut buf);
+        }
+        buf
+    }
+}
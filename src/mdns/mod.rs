@@ -0,0 +1,4 @@
+mod message;
+
+mod responder;
+pub use self::responder::*;
@@ -0,0 +1,871 @@
+use std::convert::TryInto;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::os::raw::c_void;
+use std::sync::{Arc, Mutex, Weak};
+
+use bytes::Bytes;
+use futures::future::{self, AbortHandle};
+use futures::StreamExt;
+use rand::Rng;
+
+use super::message::*;
+use crate::lwip::{self, FromPbuf};
+use crate::{Ipv6AddrState, NetIf, NetIfEvent, NetIfWatch};
+
+const MDNS_PORT: u16 = 5353;
+const MDNS_GROUP_V4: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+
+// RFC 6762, section 10
+const HOST_TTL: u32 = 120;
+const SERVICE_TTL: u32 = 4500;
+const LEGACY_UNICAST_TTL: u32 = 10;
+
+// RFC 6762, section 8.1
+const PROBE_INTERVAL_MS: u32 = 250;
+const PROBES: usize = 3;
+const MAX_CONFLICTS: usize = 15;
+const CONFLICT_WINDOW_MS: u32 = 10_000;
+const CONFLICT_DELAY_MS: u32 = 5000;
+
+// RFC 6762, section 8.2
+const PROBE_DEFER_MS: u32 = 1000;
+
+// RFC 6762, section 8.3
+const ANNOUNCE_INTERVAL_MS: u32 = 1000;
+const ANNOUNCEMENTS: usize = 2;
+
+const MDNS_HOPLIM: u8 = 255;
+const SOF_REUSEADDR: u8 = 0x04; /* TODO: bindgen skips casted defines */
+
+fn mdns_group_v6() -> Ipv6Addr {
+    Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 0xfb)
+}
+
+fn services_name() -> Name {
+    Name::new("_services._dns-sd._udp.local")
+}
+
+/// A DNS-SD service instance, such as `Printer._ipp._tcp.local`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MdnsService {
+    instance: String,
+    service_type: String,
+    port: u16,
+    txt: Vec<String>,
+}
+
+impl MdnsService {
+    /// `service_type` is the service and protocol, as in `_http._tcp`.
+    pub fn new(instance: &str, service_type: &str, port: u16) -> Self {
+        MdnsService {
+            instance: instance.to_string(),
+            service_type: service_type.trim_end_matches('.').to_string(),
+            port,
+            txt: Vec::new(),
+        }
+    }
+
+    /// Adds a `key=value` entry to the TXT record.
+    pub fn txt(mut self, entry: &str) -> Self {
+        self.txt.push(entry.to_string());
+        self
+    }
+
+    fn type_name(&self) -> Name {
+        Name::new(&format!("{}.local", self.service_type))
+    }
+
+    fn instance_name(&self) -> Name {
+        self.type_name().child(&self.instance)
+    }
+
+    fn is(&self, instance: &str, service_type: &str) -> bool {
+        self.instance_name()
+            .matches(&MdnsService::new(instance, service_type, 0).instance_name())
+    }
+}
+
+#[derive(Default)]
+pub struct MdnsResponderBuilder {
+    hostname: Option<String>,
+    services: Vec<MdnsService>,
+}
+
+impl MdnsResponderBuilder {
+    /// Answers for `hostname.local`, with the addresses of the interface,
+    /// or for `hostname-2.local` and so on if it is in use.
+    pub fn hostname(mut self, hostname: &str) -> Self {
+        let hostname = hostname.trim_end_matches('.').trim_end_matches(".local");
+        self.hostname = Some(hostname.to_string());
+        self
+    }
+
+    pub fn service(mut self, service: MdnsService) -> Self {
+        self.services.push(service);
+        self
+    }
+
+    /// Joins the mDNS groups on `netif`, probes for the hostname and then
+    /// announces it and the services.
+    ///
+    /// The addresses of `netif` are followed by a task, this must be called
+    /// from within a Tokio runtime.
+    pub fn bind(self, netif: &NetIf) -> io::Result<MdnsResponder> {
+        let hostname = self
+            .hostname
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no hostname"))?;
+
+        let state = Arc::new(Mutex::new(ResponderState {
+            pcb: std::ptr::null_mut(),
+            index: netif.index(),
+            base: hostname.clone(),
+            hostname,
+            renames: 0,
+            conflicts: Vec::new(),
+            services: self.services,
+            probes: 0,
+            probed: false,
+            announced: 0,
+            addrs: Vec::new(),
+        }));
+        let arg = Arc::into_raw(state.clone()) as *mut c_void;
+        let events = netif.watch();
+        let generation = crate::stack::generation();
+
+        let pcb = lwip::with_core_lock(|| unsafe {
+            let mut state = state.lock().unwrap();
+            let netif = state.netif();
+            if netif.is_null() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "no such interface",
+                ));
+            }
+
+            let pcb = lwip::udp_new_ip_type(lwip::lwip_ip_addr_type_IPADDR_TYPE_ANY as u8);
+            if pcb.is_null() {
+                return Err(io::Error::from(io::ErrorKind::Other));
+            }
+
+            // responders on other interfaces are bound to the same port
+            (*pcb).so_options |= SOF_REUSEADDR;
+            let ret: io::Result<()> =
+                lwip::udp_bind(pcb, &lwip::ip_addr::unspecified(), MDNS_PORT).into();
+            if let Err(e) = ret {
+                lwip::udp_remove(pcb);
+                return Err(e);
+            }
+            (*pcb).netif_idx = state.index;
+            (*pcb).ttl = MDNS_HOPLIM;
+            (*pcb).mcast_ttl = MDNS_HOPLIM;
+
+            if let Err(e) = join_leave(netif, true) {
+                lwip::udp_remove(pcb);
+                return Err(e);
+            }
+            lwip::udp_recv(pcb, Some(responder_recv), arg);
+            state.pcb = pcb;
+
+            // the first probe after a random delay, responders started
+            // together then rarely probe at the same time
+            let delay = rand::thread_rng().gen_range(0, PROBE_INTERVAL_MS + 1);
+            lwip::sys_timeout(delay, Some(probe_timer), arg);
+            Ok(pcb)
+        });
+
+        match pcb {
+            Ok(pcb) => {
+                let (task, watch) =
+                    future::abortable(watch_addrs(events, Arc::downgrade(&state), generation));
+                tokio::spawn(task);
+                Ok(MdnsResponder {
+                    pcb,
+                    state,
+                    watch,
+                    generation,
+                })
+            }
+            Err(e) => {
+                unsafe { Arc::from_raw(arg as *const Mutex<ResponderState>) };
+                Err(e)
+            }
+        }
+    }
+}
+
+/// mDNS responder (RFC 6762) for a hostname and DNS-SD services (RFC 6763)
+/// on one interface.
+///
+/// The hostname is probed for before being answered, it becomes
+/// `name-2.local`, `name-3.local` and so on while others use it. Service
+/// instance names are assumed unique on the link. Addresses added to the
+/// interface are announced and removed ones withdrawn with a goodbye, as
+/// are all the records when dropped.
+pub struct MdnsResponder {
+    pcb: *mut lwip::udp_pcb,
+    state: Arc<Mutex<ResponderState>>,
+    watch: AbortHandle,
+    generation: usize,
+}
+
+impl MdnsResponder {
+    pub fn builder() -> MdnsResponderBuilder {
+        MdnsResponderBuilder::default()
+    }
+
+    /// The name answered for, as in `name.local`, it changes when the
+    /// one asked for is in use.
+    pub fn hostname(&self) -> String {
+        format!("{}.local", self.state.lock().unwrap().hostname)
+    }
+
+    /// Starts announcing `service`, its instance name must not be in use.
+    pub fn add_service(&self, service: MdnsService) -> io::Result<()> {
+        lwip::with_core_lock(|| unsafe {
+            let mut state = self.state.lock().unwrap();
+            if state
+                .services
+                .iter()
+                .any(|s| s.instance_name().matches(&service.instance_name()))
+            {
+                return Err(io::ErrorKind::AddrInUse.into());
+            }
+
+            // announced with the hostname once probed
+            if state.probed {
+                let records = state.service_records(&service, true);
+                state.send_unsolicited(records, false);
+            }
+            state.services.push(service);
+            Ok(())
+        })
+    }
+
+    /// Withdraws the service with a goodbye.
+    pub fn remove_service(&self, instance: &str, service_type: &str) -> io::Result<()> {
+        lwip::with_core_lock(|| unsafe {
+            let mut state = self.state.lock().unwrap();
+            let idx = state
+                .services
+                .iter()
+                .position(|s| s.is(instance, service_type))
+                .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+
+            let service = state.services.remove(idx);
+            // the service type stays listed while other instances have it
+            let last = !state
+                .services
+                .iter()
+                .any(|s| s.type_name().matches(&service.type_name()));
+            if state.probed {
+                let records = state.service_records(&service, last);
+                state.send_unsolicited(records, true);
+            }
+            Ok(())
+        })
+    }
+}
+
+impl Drop for MdnsResponder {
+    fn drop(&mut self) {
+        self.watch.abort();
+        if self.generation != crate::stack::generation() {
+            // the PCB, timers and groups went away with their stack
+            return;
+        }
+
+        lwip::with_core_lock(|| unsafe {
+            let arg = (*self.pcb).recv_arg;
+            lwip::sys_untimeout(Some(probe_timer), arg);
+            lwip::sys_untimeout(Some(announce_timer), arg);
+
+            let mut state = self.state.lock().unwrap();
+            if state.probed {
+                let records = state.all_records();
+                state.send_unsolicited(records, true);
+            }
+
+            let netif = state.netif();
+            if !netif.is_null() {
+                let _ = join_leave(netif, false);
+            }
+            // for the address watch, which may still hold the state
+            state.pcb = std::ptr::null_mut();
+            drop(state);
+
+            lwip::udp_remove(self.pcb);
+            Arc::from_raw(arg as *const Mutex<ResponderState>);
+        });
+    }
+}
+
+unsafe impl Send for MdnsResponder {}
+unsafe impl Sync for MdnsResponder {}
+
+unsafe fn join_leave(netif: *mut lwip::netif, join: bool) -> io::Result<()> {
+    let group4: lwip::ip4_addr_t = MDNS_GROUP_V4.into();
+    let group6: lwip::ip6_addr_t = mdns_group_v6().into();
+
+    if join {
+        let ret: io::Result<()> = lwip::igmp_joingroup_netif(netif, &group4).into();
+        ret?;
+        let ret: io::Result<()> = lwip::mld6_joingroup_netif(netif, &group6).into();
+        if ret.is_err() {
+            lwip::igmp_leavegroup_netif(netif, &group4);
+        }
+        ret
+    } else {
+        lwip::igmp_leavegroup_netif(netif, &group4);
+        lwip::mld6_leavegroup_netif(netif, &group6).into()
+    }
+}
+
+struct ResponderState {
+    pcb: *mut lwip::udp_pcb,
+    index: u8,
+    /// The hostname asked for, without `.local`.
+    base: String,
+    /// The hostname probed for or answered, `base` until renamed.
+    hostname: String,
+    renames: usize,
+    /// When the last conflicts happened, in stack milliseconds.
+    conflicts: Vec<u32>,
+    services: Vec<MdnsService>,
+    probes: usize,
+    probed: bool,
+    announced: usize,
+    /// The address records last announced.
+    addrs: Vec<Record>,
+}
+
+unsafe impl Send for ResponderState {}
+
+fn same_record(a: &Record, b: &Record) -> bool {
+    a.rtype == b.rtype && a.name.matches(&b.name) && a.data == b.data
+}
+
+/// Orders the records of two probes for the same name, the host with the
+/// greater ones keeps probing (RFC 6762, section 8.2).
+fn tiebreak(ours: &[Record], theirs: &[&Record]) -> std::cmp::Ordering {
+    // the class is always IN
+    let mut ours: Vec<_> = ours.iter().map(|r| (r.rtype, &r.data)).collect();
+    let mut theirs: Vec<_> = theirs.iter().map(|r| (r.rtype, &r.data)).collect();
+    ours.sort();
+    theirs.sort();
+    ours.cmp(&theirs)
+}
+
+impl ResponderState {
+    fn name(&self) -> Name {
+        Name::new(&format!("{}.local", self.hostname))
+    }
+
+    /// With the core lock held, null once the interface is removed.
+    unsafe fn netif(&self) -> *mut lwip::netif {
+        lwip::netif_get_by_index(self.index)
+    }
+
+    /// A and AAAA records of the interface, only the usable IPv6 addresses.
+    unsafe fn addr_records(&self) -> Vec<Record> {
+        let netif = self.netif();
+        if netif.is_null() {
+            return Vec::new();
+        }
+        let netif = &*netif;
+
+        let mut addrs = Vec::new();
+        let ipv4: Ipv4Addr = netif.ip_addr.u_addr.ip4.into();
+        if !ipv4.is_unspecified() {
+            addrs.push(IpAddr::V4(ipv4));
+        }
+        for idx in 0..netif.ip6_addr_state.len() {
+            match Ipv6AddrState::from_value(netif.ip6_addr_state[idx]) {
+                Ipv6AddrState::Preferred | Ipv6AddrState::Deprecated => {
+                    let addr: Ipv6Addr = netif.ip6_addr[idx].u_addr.ip6.into();
+                    addrs.push(IpAddr::V6(addr));
+                }
+                _ => {}
+            }
+        }
+
+        addrs
+            .into_iter()
+            .map(|addr| {
+                let (rtype, data) = match addr {
+                    IpAddr::V4(addr) => (TYPE_A, addr.octets().to_vec()),
+                    IpAddr::V6(addr) => (TYPE_AAAA, addr.octets().to_vec()),
+                };
+                Record {
+                    name: self.name(),
+                    rtype,
+                    cache_flush: true,
+                    ttl: HOST_TTL,
+                    data,
+                }
+            })
+            .collect()
+    }
+
+    /// The PTR record listing the service type (RFC 6763, section 9).
+    fn type_record(&self, service: &MdnsService) -> Record {
+        let mut data = Vec::new();
+        service.type_name().encode(&mut data);
+        Record {
+            name: services_name(),
+            rtype: TYPE_PTR,
+            cache_flush: false,
+            ttl: SERVICE_TTL,
+            data,
+        }
+    }
+
+    fn ptr_record(&self, service: &MdnsService) -> Record {
+        let mut data = Vec::new();
+        service.instance_name().encode(&mut data);
+        Record {
+            name: service.type_name(),
+            rtype: TYPE_PTR,
+            cache_flush: false,
+            ttl: SERVICE_TTL,
+            data,
+        }
+    }
+
+    fn srv_record(&self, service: &MdnsService) -> Record {
+        // priority and weight
+        let mut data = vec![0, 0, 0, 0];
+        data.extend_from_slice(&service.port.to_be_bytes());
+        self.name().encode(&mut data);
+        Record {
+            name: service.instance_name(),
+            rtype: TYPE_SRV,
+            cache_flush: true,
+            ttl: HOST_TTL,
+            data,
+        }
+    }
+
+    fn txt_record(&self, service: &MdnsService) -> Record {
+        let mut data = Vec::new();
+        for entry in service.txt.iter() {
+            let entry = &entry.as_bytes()[..entry.len().min(255)];
+            data.push(entry.len() as u8);
+            data.extend_from_slice(entry);
+        }
+        if data.is_empty() {
+            // RFC 6763, section 6.1
+            data.push(0);
+        }
+        Record {
+            name: service.instance_name(),
+            rtype: TYPE_TXT,
+            cache_flush: true,
+            ttl: SERVICE_TTL,
+            data,
+        }
+    }
+
+    fn service_records(&self, service: &MdnsService, with_type: bool) -> Vec<Record> {
+        let mut records = vec![
+            self.ptr_record(service),
+            self.srv_record(service),
+            self.txt_record(service),
+        ];
+        if with_type {
+            records.push(self.type_record(service));
+        }
+        records
+    }
+
+    unsafe fn all_records(&self) -> Vec<Record> {
+        let mut records = self.addr_records();
+        for service in self.services.iter() {
+            for record in self.service_records(service, true) {
+                if !records.iter().any(|r| same_record(r, &record)) {
+                    records.push(record);
+                }
+            }
+        }
+        records
+    }
+
+    /// Answers and additional records for `question`.
+    unsafe fn answer(&self, question: &Question) -> (Vec<Record>, Vec<Record>) {
+        let wants = |rtype| question.qtype == rtype || question.qtype == TYPE_ANY;
+        let mut answers = Vec::new();
+        let mut additional = Vec::new();
+
+        if question.name.matches(&self.name()) {
+            answers.extend(self.addr_records().into_iter().filter(|r| wants(r.rtype)));
+        }
+
+        if question.name.matches(&services_name()) && wants(TYPE_PTR) {
+            for service in self.services.iter() {
+                let record = self.type_record(service);
+                if !answers.iter().any(|r| same_record(r, &record)) {
+                    answers.push(record);
+                }
+            }
+        }
+
+        for service in self.services.iter() {
+            if question.name.matches(&service.type_name()) && wants(TYPE_PTR) {
+                answers.push(self.ptr_record(service));
+                // RFC 6763, section 12.1
+                additional.push(self.srv_record(service));
+                additional.push(self.txt_record(service));
+                additional.extend(self.addr_records());
+            }
+
+            if question.name.matches(&service.instance_name()) {
+                if wants(TYPE_SRV) {
+                    answers.push(self.srv_record(service));
+                    additional.extend(self.addr_records());
+                }
+                if wants(TYPE_TXT) {
+                    answers.push(self.txt_record(service));
+                }
+            }
+        }
+
+        (answers, additional)
+    }
+
+    /// Response to `query`, `None` if nothing is ours or the hostname is
+    /// still being probed for.
+    unsafe fn handle(&self, query: &Message, legacy: bool) -> Option<Message> {
+        if query.is_response() || !self.probed {
+            return None;
+        }
+
+        let mut answers: Vec<Record> = Vec::new();
+        let mut additional: Vec<Record> = Vec::new();
+        for question in query.questions.iter() {
+            let (a, b) = self.answer(question);
+            for record in a {
+                if !answers.iter().any(|r| same_record(r, &record)) {
+                    answers.push(record);
+                }
+            }
+            additional.extend(b);
+        }
+        if answers.is_empty() {
+            return None;
+        }
+
+        let mut dedup: Vec<Record> = Vec::new();
+        for record in additional {
+            if !answers
+                .iter()
+                .chain(dedup.iter())
+                .any(|r| same_record(r, &record))
+            {
+                dedup.push(record);
+            }
+        }
+
+        let mut response = Message {
+            flags: FLAG_RESPONSE | FLAG_AUTHORITATIVE,
+            answers,
+            additional: dedup,
+            ..Default::default()
+        };
+
+        // RFC 6762, section 6.7
+        if legacy {
+            response.id = query.id;
+            response.questions = query.questions.clone();
+            for question in response.questions.iter_mut() {
+                question.unicast_response = false;
+            }
+            for record in response
+                .answers
+                .iter_mut()
+                .chain(response.additional.iter_mut())
+            {
+                record.cache_flush = false;
+                record.ttl = record.ttl.min(LEGACY_UNICAST_TTL);
+            }
+        }
+        Some(response)
+    }
+
+    /// Whether `msg` is from another host answering for the hostname.
+    unsafe fn is_conflict(&self, msg: &Message) -> bool {
+        if !msg.is_response() {
+            return false;
+        }
+
+        let name = self.name();
+        let ours = self.addr_records();
+        msg.answers
+            .iter()
+            .chain(msg.additional.iter())
+            // goodbyes, even for addresses that were ours, do not conflict
+            .filter(|r| r.name.matches(&name) && r.ttl != 0)
+            .any(|r| {
+                !ours
+                    .iter()
+                    .chain(self.addrs.iter())
+                    .any(|o| o.rtype == r.rtype && o.data == r.data)
+            })
+    }
+
+    /// Probes for the hostname again in `delay` milliseconds, the records
+    /// are not answered for meanwhile.
+    unsafe fn restart_probing(&mut self, delay: u32) {
+        let arg = (*self.pcb).recv_arg;
+        lwip::sys_untimeout(Some(probe_timer), arg);
+        lwip::sys_untimeout(Some(announce_timer), arg);
+
+        self.probes = 0;
+        self.probed = false;
+        self.announced = 0;
+        lwip::sys_timeout(delay, Some(probe_timer), arg);
+    }
+
+    /// Probes for the next name after a conflict (RFC 6762, section 9).
+    unsafe fn rename(&mut self) {
+        let now = crate::time::now();
+        self.conflicts
+            .retain(|t| now.wrapping_sub(*t) < CONFLICT_WINDOW_MS);
+        self.conflicts.push(now);
+
+        self.renames += 1;
+        self.hostname = format!("{}-{}", self.base, self.renames + 1);
+
+        let delay = if self.conflicts.len() >= MAX_CONFLICTS {
+            CONFLICT_DELAY_MS
+        } else {
+            rand::thread_rng().gen_range(0, PROBE_INTERVAL_MS + 1)
+        };
+        self.restart_probing(delay);
+    }
+
+    /// Sends the next probe, or starts announcing once they all went
+    /// unanswered.
+    unsafe fn probe(&mut self) {
+        let arg = (*self.pcb).recv_arg;
+        if self.probes == PROBES {
+            self.probed = true;
+            self.announced = 0;
+            self.announce();
+            return;
+        }
+
+        // RFC 6762, section 10.2
+        let mut authority = self.addr_records();
+        for record in authority.iter_mut() {
+            record.cache_flush = false;
+        }
+        let msg = Message {
+            questions: vec![Question {
+                name: self.name(),
+                qtype: TYPE_ANY,
+                // RFC 6762, section 8.1
+                unicast_response: self.probes == 0,
+            }],
+            authority,
+            ..Default::default()
+        };
+        self.send(&msg, IpAddr::V4(MDNS_GROUP_V4), MDNS_PORT);
+        self.send(&msg, IpAddr::V6(mdns_group_v6()), MDNS_PORT);
+
+        self.probes += 1;
+        lwip::sys_timeout(PROBE_INTERVAL_MS, Some(probe_timer), arg);
+    }
+
+    /// Sends the next announcement of all the records.
+    unsafe fn announce(&mut self) {
+        let records = self.all_records();
+        self.send_unsolicited(records, false);
+        self.addrs = self.addr_records();
+        self.announced += 1;
+
+        if self.announced < ANNOUNCEMENTS {
+            let arg = (*self.pcb).recv_arg;
+            lwip::sys_timeout(ANNOUNCE_INTERVAL_MS, Some(announce_timer), arg);
+        }
+    }
+
+    /// Withdraws the removed addresses and announces the records again if
+    /// some were added.
+    unsafe fn addrs_changed(&mut self) {
+        if !self.probed {
+            // all announced once probed
+            return;
+        }
+
+        let addrs = self.addr_records();
+        let removed: Vec<Record> = self
+            .addrs
+            .iter()
+            .filter(|r| !addrs.iter().any(|a| same_record(a, r)))
+            .cloned()
+            .collect();
+        let added = addrs
+            .iter()
+            .any(|a| !self.addrs.iter().any(|r| same_record(r, a)));
+
+        self.send_unsolicited(removed, true);
+        if added {
+            let arg = (*self.pcb).recv_arg;
+            lwip::sys_untimeout(Some(announce_timer), arg);
+            self.announced = 0;
+            self.announce();
+        } else {
+            self.addrs = addrs;
+        }
+    }
+
+    /// Sends `msg`, with the core lock held.
+    unsafe fn send(&self, msg: &Message, dst: IpAddr, port: u16) {
+        let netif = self.netif();
+        if netif.is_null() {
+            return;
+        }
+
+        let buf = msg.encode();
+        let p = lwip::pbuf_alloc(
+            lwip::pbuf_layer::PBUF_TRANSPORT,
+            buf.len() as u16,
+            lwip::pbuf_type::PBUF_RAM,
+        );
+        if p.is_null() {
+            return;
+        }
+        lwip::pbuf_take(p, buf.as_ptr() as *const c_void, buf.len() as u16);
+
+        let dst: lwip::ip_addr_t = dst.into();
+        lwip::udp_sendto_if(self.pcb, p, &dst, port, netif);
+        lwip::pbuf_free(p);
+    }
+
+    /// Multicasts `records` on both families, with a TTL of 0 to withdraw
+    /// them.
+    unsafe fn send_unsolicited(&self, mut records: Vec<Record>, goodbye: bool) {
+        if records.is_empty() {
+            return;
+        }
+        if goodbye {
+            for record in records.iter_mut() {
+                record.ttl = 0;
+            }
+        }
+
+        let msg = Message {
+            flags: FLAG_RESPONSE | FLAG_AUTHORITATIVE,
+            answers: records,
+            ..Default::default()
+        };
+        self.send(&msg, IpAddr::V4(MDNS_GROUP_V4), MDNS_PORT);
+        self.send(&msg, IpAddr::V6(mdns_group_v6()), MDNS_PORT);
+    }
+}
+
+unsafe extern "C" fn probe_timer(arg: *mut c_void) {
+    let state = &*(arg as *const Mutex<ResponderState>);
+    state.lock().unwrap().probe();
+}
+
+unsafe extern "C" fn announce_timer(arg: *mut c_void) {
+    let state = &*(arg as *const Mutex<ResponderState>);
+    state.lock().unwrap().announce();
+}
+
+/// Follows the addresses of the interface until it is removed or the
+/// responder dropped.
+async fn watch_addrs(
+    mut events: NetIfWatch,
+    state: Weak<Mutex<ResponderState>>,
+    generation: usize,
+) {
+    while let Some(event) = events.next().await {
+        match event {
+            NetIfEvent::AddrAdded(_)
+            | NetIfEvent::AddrRemoved(_)
+            | NetIfEvent::DadFinished { .. }
+            | NetIfEvent::DhcpBound(_)
+            | NetIfEvent::DhcpLost => {}
+            _ => continue,
+        }
+
+        let state = match state.upgrade() {
+            Some(state) => state,
+            None => return,
+        };
+        lwip::with_core_lock(|| unsafe {
+            let mut state = state.lock().unwrap();
+            if generation == crate::stack::generation() && !state.pcb.is_null() {
+                state.addrs_changed();
+            }
+        });
+    }
+}
+
+unsafe extern "C" fn responder_recv(
+    arg: *mut c_void,
+    _: *mut lwip::udp_pcb,
+    p: *mut lwip::pbuf,
+    addr: *const lwip::ip_addr_t,
+    port: u16,
+) {
+    let state = &*(arg as *const Mutex<ResponderState>);
+    let buf = Bytes::from_pbuf(p);
+    lwip::pbuf_free(p);
+
+    let src: IpAddr = match (*addr).try_into() {
+        Ok(src) => src,
+        Err(_) => return,
+    };
+    let query = match Message::parse(&buf) {
+        Some(query) => query,
+        None => return,
+    };
+
+    // queries not from port 5353 are from plain DNS resolvers
+    let legacy = port != MDNS_PORT;
+    let mut state = state.lock().unwrap();
+
+    if !legacy && state.is_conflict(&query) {
+        if state.probed {
+            // the hostname is defended by probing again, the other host
+            // answers if it owns it (RFC 6762, section 9)
+            state.restart_probing(0);
+        } else {
+            state.rename();
+        }
+        return;
+    }
+
+    if !legacy && !state.probed && !query.is_response() {
+        let name = state.name();
+        let theirs: Vec<&Record> = query
+            .authority
+            .iter()
+            .filter(|r| r.name.matches(&name))
+            .collect();
+        // our own probes loop back, they tie
+        if !theirs.is_empty()
+            && tiebreak(&state.addr_records(), &theirs) == std::cmp::Ordering::Less
+        {
+            state.restart_probing(PROBE_DEFER_MS);
+            return;
+        }
+    }
+
+    if let Some(response) = state.handle(&query, legacy) {
+        if legacy {
+            state.send(&response, src, port);
+        } else if query.questions.iter().all(|q| q.unicast_response) {
+            state.send(&response, src, MDNS_PORT);
+        } else {
+            let group = match src {
+                IpAddr::V4(_) => IpAddr::V4(MDNS_GROUP_V4),
+                IpAddr::V6(_) => IpAddr::V6(mdns_group_v6()),
+            };
+            state.send(&response, group, MDNS_PORT);
+        }
+    }
+}
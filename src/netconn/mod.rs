@@ -1,7 +1,7 @@
 use std::convert::TryInto;
 use std::io;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, Weak};
 use std::task::{Context, Poll};
 
 use tokio::sync::mpsc;
@@ -22,9 +22,12 @@ struct NetconnCState(
 );
 
 #[derive(Debug)]
-struct NetconnInner {
-    conn: *mut lwip::netconn,
+pub(crate) struct NetconnInner {
+    pub(crate) conn: *mut lwip::netconn,
+    generation: usize,
     ntype: NetconnType,
+    recv_buf_size: Option<u32>,
+    send_buf_size: Option<u32>,
     rxevents: mpsc::UnboundedReceiver<NetconnEvent>,
     txevents: mpsc::UnboundedReceiver<NetconnEvent>,
 }
@@ -42,6 +45,29 @@ enum NetconnEvent {
 
 pub(crate) type NetconnType = lwip::netconn_type;
 
+pub(crate) type NetconnRef = Weak<Mutex<NetconnInner>>;
+
+extern "C" {
+    // TF_WND_SCALE of the lwIP being built, see ffi/src/config.c
+    static lwip_rs_tf_wnd_scale: lwip::tcpflags_t;
+}
+
+/// TCP_WND_MAX() from tcp_priv.h.
+pub(crate) unsafe fn tcp_wnd_max(pcb: *const lwip::tcp_pcb) -> u32 {
+    if (*pcb).flags & lwip_rs_tf_wnd_scale != 0 {
+        crate::stack::tcp_wnd()
+    } else {
+        crate::stack::tcp_wnd().min(0xffff)
+    }
+}
+
+/// TCP_SNDLOWAT from opt.h.
+fn tcp_snd_lowat() -> u32 {
+    let snd_buf = crate::stack::tcp_snd_buf();
+    let mss = crate::stack::tcp_mss() as u32;
+    (snd_buf / 2).max(2 * mss + 1).min(snd_buf - 1)
+}
+
 unsafe extern "C" fn netconn_callback(
     netconn: *mut lwip::netconn,
     evt: lwip::netconn_evt,
@@ -84,6 +110,34 @@ unsafe extern "C" fn netconn_callback(
     }
 }
 
+/// Sends `buf` as one datagram through a netbuf referencing it, the netbuf
+/// is deleted whatever happens.
+unsafe fn send_netbuf<F>(buf: &[u8], send: F) -> io::Result<()>
+where
+    F: FnOnce(*mut lwip::netbuf) -> lwip::err_t,
+{
+    if buf.len() > u16::max_value() as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "datagram too large",
+        ));
+    }
+
+    let netbuf = lwip::netbuf_new();
+    if netbuf.is_null() {
+        return Err(io::Error::from(io::ErrorKind::Other));
+    }
+    let ret: io::Result<()> = lwip::netbuf_ref(
+        netbuf,
+        buf.as_ptr() as *const ::std::os::raw::c_void,
+        buf.len() as u16,
+    )
+    .into();
+    let ret = ret.and_then(|_| send(netbuf).into());
+    lwip::netbuf_delete(netbuf);
+    ret
+}
+
 impl Netconn {
     fn new(conn: *mut lwip::netconn, ntype: NetconnType) -> Self {
         let (txtx, rxtx) = mpsc::unbounded_channel();
@@ -91,6 +145,9 @@ impl Netconn {
 
         let mut inner = NetconnInner {
             conn: conn,
+            generation: crate::stack::generation(),
+            recv_buf_size: None,
+            send_buf_size: None,
             ntype: ntype,
             txevents: rxtx,
             rxevents: rxrx,
@@ -101,12 +158,13 @@ impl Netconn {
             (*inner.conn).callback_ctx = state as *mut _;
         }
 
-        Netconn {
-            inner: Arc::new(Mutex::new(inner)),
-        }
+        let inner = Arc::new(Mutex::new(inner));
+        crate::stack::register_netconn(Arc::downgrade(&inner));
+
+        Netconn { inner }
     }
     fn new_from_type(ntype: NetconnType, proto: u8) -> Self {
-        crate::stack_init();
+        crate::stack::stack_init();
 
         let conn = unsafe {
             lwip::netconn_new_with_proto_and_callback(ntype, proto, Some(netconn_callback))
@@ -118,6 +176,10 @@ impl Netconn {
         Self::new_from_type(NetconnType::NETCONN_TCP, 0)
     }
 
+    pub(crate) fn new_udp() -> Self {
+        Self::new_from_type(NetconnType::NETCONN_UDP, 0)
+    }
+
     pub(crate) fn new_raw(proto: u8) -> Self {
         Self::new_from_type(NetconnType::NETCONN_RAW_IPV6_HDRINCL, proto)
     }
@@ -173,6 +235,12 @@ impl Netconn {
     }
 
     pub(crate) fn recv(&self) -> io::Result<Bytes> {
+        self.recv_from().map(|(data, _)| data)
+    }
+
+    /// Receives data along with the address it came from, unspecified for
+    /// TCP.
+    pub(crate) fn recv_from(&self) -> io::Result<(Bytes, SocketAddr)> {
         let mut netbuf: *mut lwip::netbuf = std::ptr::null_mut();
         let inner = self.inner.lock().unwrap();
 
@@ -199,10 +267,15 @@ impl Netconn {
             /* do-while(netbuf_next() >= 0) */
             {}
 
+            let ip = (*netbuf)
+                .addr
+                .try_into()
+                .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+            let addr = SocketAddr::new(ip, (*netbuf).port);
             lwip::netbuf_delete(netbuf);
-        }
 
-        Ok(buffer.freeze())
+            Ok((buffer.freeze(), addr))
+        }
     }
 
     pub(crate) fn send(&self, buf: &[u8]) -> io::Result<usize> {
@@ -228,22 +301,27 @@ impl Netconn {
                 Ok(len)
             }
             _ => unsafe {
-                let netbuf = lwip::netbuf_new();
-                let ret: io::Result<()> = lwip::netbuf_ref(
-                    netbuf,
-                    buf.as_ptr() as *const ::std::os::raw::c_void,
-                    buf.len() as u16,
-                )
-                .into();
-                ret?;
-
-                let ret: io::Result<()> = lwip::netconn_send(inner.conn, netbuf).into();
-                ret?;
+                send_netbuf(buf, |netbuf| lwip::netconn_send(inner.conn, netbuf))?;
                 Ok(buf.len())
             },
         }
     }
 
+    /// Sends a datagram to `addr`, on an unconnected UDP or RAW netconn.
+    pub(crate) fn send_to(&self, buf: &[u8], addr: SocketAddr) -> io::Result<usize> {
+        let ip: lwip::ip_addr_t = addr.ip().into();
+        let inner = self.inner.lock().unwrap();
+
+        inner.set_nonblocking();
+
+        unsafe {
+            send_netbuf(buf, |netbuf| {
+                lwip::netconn_sendto(inner.conn, netbuf, &ip, addr.port())
+            })?;
+        }
+        Ok(buf.len())
+    }
+
     pub(crate) fn shutdown_tx(&self) -> io::Result<()> {
         let inner = self.inner.lock().unwrap();
 
@@ -267,6 +345,147 @@ impl Netconn {
         Ok(SocketAddr::new(ip.try_into()?, port))
     }
 
+    /// Runs `f` on the PCB of an established TCP connection, with the core
+    /// lock held.
+    pub(crate) fn with_tcp_pcb<F, R>(&self, f: F) -> io::Result<R>
+    where
+        F: FnOnce(*mut lwip::tcp_pcb) -> R,
+    {
+        let inner = self.inner.lock().unwrap();
+
+        lwip::with_core_lock(|| unsafe { inner.tcp_pcb().map(f) })
+    }
+
+    /// Like `with_tcp_pcb`, whatever the state of the connection.
+    pub(crate) fn with_any_tcp_pcb<F, R>(&self, f: F) -> io::Result<R>
+    where
+        F: FnOnce(*mut lwip::tcp_pcb) -> R,
+    {
+        let inner = self.inner.lock().unwrap();
+
+        lwip::with_core_lock(|| unsafe { inner.any_tcp_pcb().map(f) })
+    }
+
+    /// Runs `f` on the PCB of a UDP netconn, with the core lock held.
+    pub(crate) fn with_udp_pcb<F, R>(&self, f: F) -> io::Result<R>
+    where
+        F: FnOnce(*mut lwip::udp_pcb) -> R,
+    {
+        let inner = self.inner.lock().unwrap();
+
+        if inner.ntype != NetconnType::NETCONN_UDP {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "not a UDP socket",
+            ));
+        }
+        Ok(lwip::with_core_lock(|| unsafe { f((*inner.conn).pcb.udp) }))
+    }
+
+    pub(crate) fn recv_buffer_size(&self) -> io::Result<usize> {
+        let inner = self.inner.lock().unwrap();
+
+        lwip::with_core_lock(|| unsafe {
+            let pcb = inner.tcp_pcb()?;
+            Ok(inner.recv_buf_size.unwrap_or_else(|| tcp_wnd_max(pcb)) as usize)
+        })
+    }
+
+    /// Resizes the receive window of an established TCP connection, up to
+    /// the stack window (`StackConfig::tcp_wnd`).
+    pub(crate) fn set_recv_buffer_size(&self, size: usize) -> io::Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+
+        lwip::with_core_lock(|| unsafe {
+            let pcb = inner.tcp_pcb()?;
+            let max = tcp_wnd_max(pcb);
+            let current = inner.recv_buf_size.unwrap_or(max);
+
+            if size == 0 || size > max as usize {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "receive buffer size exceeds the stack window",
+                ));
+            }
+
+            let size = size as u32;
+            if size > current {
+                // tcp_recved() opens the window and sends an update if needed
+                let mut grow = size - current;
+                while grow > 0 {
+                    let n = grow.min(0xffff);
+                    lwip::tcp_recved(pcb, n as u16);
+                    grow -= n;
+                }
+            } else {
+                let shrink = current - size;
+                if (*pcb).rcv_wnd < shrink {
+                    return Err(io::Error::new(
+                        io::ErrorKind::WouldBlock,
+                        "receive buffer in use",
+                    ));
+                }
+                (*pcb).rcv_wnd -= shrink;
+            }
+
+            inner.recv_buf_size = Some(size);
+            Ok(())
+        })
+    }
+
+    pub(crate) fn send_buffer_size(&self) -> io::Result<usize> {
+        let inner = self.inner.lock().unwrap();
+
+        lwip::with_core_lock(|| unsafe {
+            inner.tcp_pcb()?;
+            Ok(inner
+                .send_buf_size
+                .unwrap_or_else(crate::stack::tcp_snd_buf) as usize)
+        })
+    }
+
+    /// Resizes the send buffer of an established TCP connection.
+    ///
+    /// lwIP only signals writability once more than TCP_SNDLOWAT bytes are
+    /// free, so the buffer cannot go below that mark.
+    pub(crate) fn set_send_buffer_size(&self, size: usize) -> io::Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+
+        lwip::with_core_lock(|| unsafe {
+            let pcb = inner.tcp_pcb()?;
+            let current = inner
+                .send_buf_size
+                .unwrap_or_else(crate::stack::tcp_snd_buf);
+
+            if size <= tcp_snd_lowat() as usize || size > u32::max_value() as usize {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "send buffer size below the stack low watermark",
+                ));
+            }
+
+            let size = size as u32;
+            if size > current {
+                (*pcb).snd_buf += size - current;
+                if let Some(callback) = (*inner.conn).callback {
+                    callback(inner.conn, lwip::netconn_evt::NETCONN_EVT_SENDPLUS, 0);
+                }
+            } else {
+                let shrink = current - size;
+                if (*pcb).snd_buf < shrink {
+                    return Err(io::Error::new(
+                        io::ErrorKind::WouldBlock,
+                        "send buffer in use",
+                    ));
+                }
+                (*pcb).snd_buf -= shrink;
+            }
+
+            inner.send_buf_size = Some(size);
+            Ok(())
+        })
+    }
+
     pub(crate) fn poll_rx(&self, cx: &mut Context<'_>) -> Poll<io::Result<usize>> {
         let mut inner = self.inner.lock().unwrap();
 
@@ -292,6 +511,31 @@ impl Netconn {
 }
 
 impl NetconnInner {
+    /// TCP PCB in any state, with the core lock held.
+    unsafe fn any_tcp_pcb(&self) -> io::Result<*mut lwip::tcp_pcb> {
+        if self.ntype != NetconnType::NETCONN_TCP {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "not a TCP socket",
+            ));
+        }
+
+        let pcb = (*self.conn).pcb.tcp;
+        if pcb.is_null() {
+            return Err(io::ErrorKind::NotConnected.into());
+        }
+        Ok(pcb)
+    }
+
+    /// Established TCP PCB, with the core lock held.
+    unsafe fn tcp_pcb(&self) -> io::Result<*mut lwip::tcp_pcb> {
+        let pcb = self.any_tcp_pcb()?;
+        if (*pcb).state < lwip::tcp_state_ESTABLISHED || (*pcb).state == lwip::tcp_state_TIME_WAIT {
+            return Err(io::ErrorKind::NotConnected.into());
+        }
+        Ok(pcb)
+    }
+
     fn set_nonblocking(&self) {
         unsafe {
             (*self.conn).flags |= 0x2 /* TODO NETCONN_FLAG_NON_BLOCKING */ | 0x4 /* TODO NETCONN_FLAG_IN_NONBLOCKING_CONNECT */;
@@ -303,8 +547,17 @@ impl NetconnInner {
     }
 }
 
+unsafe impl Send for NetconnInner {}
+
 impl Drop for NetconnInner {
     fn drop(&mut self) {
+        if self.generation != crate::stack::generation() {
+            // its stack was shut down: the pcb was aborted but the netconn
+            // and its mailboxes are leaked, deleting them needs the tcpip
+            // thread that owned them
+            return;
+        }
+
         unsafe {
             self.set_nonblocking();
             lwip::netconn_delete(self.conn);
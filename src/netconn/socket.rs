@@ -3,13 +3,16 @@ use std::net::SocketAddr;
 use std::pin::Pin;
 use std::sync::{Arc, Mutex};
 
+use bytes::{Buf, Bytes};
 use futures::task::{Context, Poll};
 use tokio::io::{AsyncRead, AsyncWrite};
 
 use crate::Netconn;
 
+/// The netconn and the part of the last received buffer that did not fit in
+/// the caller's buffer.
 #[derive(Debug)]
-struct NetconnSocketInner(Netconn);
+struct NetconnSocketInner(Netconn, Bytes);
 
 #[derive(Debug)]
 pub struct NetconnSocket {
@@ -18,7 +21,7 @@ pub struct NetconnSocket {
 
 impl NetconnSocket {
     pub(crate) fn new(conn: Netconn) -> Self {
-        let inner = NetconnSocketInner(conn);
+        let inner = NetconnSocketInner(conn, Bytes::new());
 
         Self {
             inner: Arc::new(Mutex::new(inner)),
@@ -31,6 +34,15 @@ impl NetconnSocket {
         inner.0.local()
     }
 
+    pub(crate) fn with_netconn<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&Netconn) -> R,
+    {
+        let inner = self.inner.lock().unwrap();
+
+        f(&inner.0)
+    }
+
     pub fn close(self) {
         let inner = self.inner.lock().unwrap();
         drop(inner)
@@ -43,14 +55,20 @@ impl AsyncRead for NetconnSocket {
         cx: &mut Context<'_>,
         buf: &mut [u8],
     ) -> Poll<io::Result<usize>> {
-        let inner = self.inner.lock().unwrap();
+        let mut inner = self.inner.lock().unwrap();
 
         loop {
+            if !inner.1.is_empty() {
+                let len = inner.1.len().min(buf.len());
+                buf[..len].clone_from_slice(&inner.1[..len]);
+                inner.1.advance(len);
+                return Poll::Ready(Ok(len));
+            }
+
             return match inner.0.recv() {
                 Ok(data) => {
-                    let len = data.len();
-                    buf[..len].clone_from_slice(&data);
-                    Poll::Ready(Ok(len))
+                    inner.1 = data;
+                    continue;
                 }
                 Err(ref e) if e.kind() == io::ErrorKind::NotConnected => {
                     /* EOF */
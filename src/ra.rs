@@ -0,0 +1,409 @@
+use std::convert::TryInto;
+use std::io;
+use std::net::{IpAddr, Ipv6Addr};
+use std::os::raw::c_void;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use ipnetwork::Ipv6Network;
+use rand::Rng;
+
+use crate::lwip;
+use crate::NetIf;
+
+const ICMP6_TYPE_RS: u8 = 133;
+const ICMP6_TYPE_RA: u8 = 134;
+
+const ND6_OPTION_SOURCE_LLADDR: u8 = 1;
+const ND6_OPTION_PREFIX_INFO: u8 = 3;
+const ND6_OPTION_MTU: u8 = 5;
+const ND6_OPTION_RDNSS: u8 = 25;
+
+const ND6_PREFIX_FLAG_ON_LINK: u8 = 0x80;
+const ND6_PREFIX_FLAG_AUTONOMOUS: u8 = 0x40;
+
+// RFC 4861, section 10
+const MAX_INITIAL_RTR_ADVERT_INTERVAL: Duration = Duration::from_secs(16);
+const MAX_INITIAL_RTR_ADVERTISEMENTS: usize = 3;
+const MIN_DELAY_BETWEEN_RAS: Duration = Duration::from_secs(3);
+const MAX_RA_DELAY_TIME: Duration = Duration::from_millis(500);
+
+const IP6_NEXTH_ICMP6: u8 = 58;
+const ND6_HOPLIM: u8 = 255;
+
+pub struct RouterAdvertiserBuilder {
+    interval: Duration,
+    router_lifetime: Duration,
+    hop_limit: u8,
+    prefixes: Vec<Ipv6Network>,
+    valid_life: Duration,
+    preferred_life: Duration,
+    rdnss: Vec<Ipv6Addr>,
+    mtu: Option<u32>,
+}
+
+impl Default for RouterAdvertiserBuilder {
+    fn default() -> Self {
+        RouterAdvertiserBuilder {
+            interval: Duration::from_secs(600),
+            router_lifetime: Duration::from_secs(1800),
+            hop_limit: 64,
+            prefixes: Vec::new(),
+            valid_life: Duration::from_secs(86400),
+            preferred_life: Duration::from_secs(14400),
+            rdnss: Vec::new(),
+            mtu: None,
+        }
+    }
+}
+
+impl RouterAdvertiserBuilder {
+    /// Maximum time between unsolicited advertisements, they are sent at a
+    /// random time between a third of it and it.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// 0 to not be used as a default router.
+    pub fn router_lifetime(mut self, lifetime: Duration) -> Self {
+        self.router_lifetime = lifetime;
+        self
+    }
+
+    pub fn hop_limit(mut self, hop_limit: u8) -> Self {
+        self.hop_limit = hop_limit;
+        self
+    }
+
+    /// Advertises an on-link prefix, hosts configure their address from
+    /// /64 ones.
+    pub fn prefix(mut self, prefix: Ipv6Network) -> Self {
+        self.prefixes.push(prefix);
+        self
+    }
+
+    /// Lifetimes of the advertised prefixes.
+    pub fn prefix_lifetimes(mut self, valid: Duration, preferred: Duration) -> Self {
+        self.valid_life = valid;
+        self.preferred_life = preferred;
+        self
+    }
+
+    /// Advertises a recursive DNS server (RFC 8106).
+    pub fn rdnss(mut self, server: Ipv6Addr) -> Self {
+        self.rdnss.push(server);
+        self
+    }
+
+    pub fn mtu(mut self, mtu: u32) -> Self {
+        self.mtu = Some(mtu);
+        self
+    }
+
+    /// Starts advertising on `netif`, from its link-local address.
+    pub fn bind(self, netif: &NetIf) -> io::Result<RouterAdvertiser> {
+        let src = netif
+            .list_addrs()
+            .iter()
+            .filter_map(|a| match a.ip() {
+                IpAddr::V6(addr) if addr.segments()[0] & 0xffc0 == 0xfe80 => Some(addr),
+                _ => None,
+            })
+            .next()
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::AddrNotAvailable,
+                    "interface has no link-local address",
+                )
+            })?;
+
+        let state = Arc::new(Mutex::new(AdvertiserState {
+            config: self,
+            pcb: std::ptr::null_mut(),
+            index: netif.index(),
+            src,
+            hwaddr: netif.hwaddr(),
+            sent: 0,
+            unsolicited: 0,
+            last_sent: None,
+            solicited: None,
+        }));
+        let arg = Arc::into_raw(state.clone()) as *mut c_void;
+
+        let pcb = lwip::with_core_lock(|| unsafe {
+            // solicitations are sent to the routers
+            let index = state.lock().unwrap().index;
+            let group: lwip::ip6_addr_t = all_routers().into();
+            let ret: io::Result<()> =
+                lwip::mld6_joingroup_netif(lwip::netif_get_by_index(index), &group).into();
+            ret?;
+
+            let pcb = lwip::raw_new_ip6(IP6_NEXTH_ICMP6);
+            if pcb.is_null() {
+                lwip::mld6_leavegroup_netif(lwip::netif_get_by_index(index), &group);
+                return Err(io::Error::from(io::ErrorKind::Other));
+            }
+
+            (*pcb).netif_idx = state.lock().unwrap().index;
+            (*pcb).ttl = ND6_HOPLIM;
+            (*pcb).mcast_ttl = ND6_HOPLIM;
+            (*pcb).chksum_reqd = 1;
+            (*pcb).chksum_offset = 2;
+            lwip::raw_recv(pcb, Some(ra_recv), arg);
+            state.lock().unwrap().pcb = pcb;
+
+            // first advertisement right away
+            ra_timer(arg);
+            Ok(pcb)
+        });
+
+        match pcb {
+            Ok(pcb) => Ok(RouterAdvertiser {
+                pcb,
+                state,
+                generation: crate::stack::generation(),
+            }),
+            Err(e) => {
+                unsafe { Arc::from_raw(arg as *const Mutex<AdvertiserState>) };
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Sends IPv6 router advertisements on an interface, periodically and in
+/// answer to router solicitations.
+///
+/// When dropped, a last advertisement tells hosts to stop using the router.
+pub struct RouterAdvertiser {
+    pcb: *mut lwip::raw_pcb,
+    state: Arc<Mutex<AdvertiserState>>,
+    generation: usize,
+}
+
+impl RouterAdvertiser {
+    pub fn builder() -> RouterAdvertiserBuilder {
+        RouterAdvertiserBuilder::default()
+    }
+
+    /// Number of advertisements sent.
+    pub fn sent(&self) -> usize {
+        self.state.lock().unwrap().sent
+    }
+}
+
+impl Drop for RouterAdvertiser {
+    fn drop(&mut self) {
+        if self.generation != crate::stack::generation() {
+            // the PCB and timer went away with their stack
+            return;
+        }
+
+        lwip::with_core_lock(|| unsafe {
+            let arg = (*self.pcb).recv_arg;
+            lwip::sys_untimeout(Some(ra_timer), arg);
+            lwip::sys_untimeout(Some(ra_solicited), arg);
+
+            let mut state = self.state.lock().unwrap();
+            state.config.router_lifetime = Duration::from_secs(0);
+            state.send(all_nodes());
+
+            let netif = lwip::netif_get_by_index(state.index);
+            if !netif.is_null() {
+                let group: lwip::ip6_addr_t = all_routers().into();
+                lwip::mld6_leavegroup_netif(netif, &group);
+            }
+            drop(state);
+
+            lwip::raw_remove(self.pcb);
+            Arc::from_raw(arg as *const Mutex<AdvertiserState>);
+        });
+    }
+}
+
+unsafe impl Send for RouterAdvertiser {}
+unsafe impl Sync for RouterAdvertiser {}
+
+struct AdvertiserState {
+    config: RouterAdvertiserBuilder,
+    pcb: *mut lwip::raw_pcb,
+    index: u8,
+    src: Ipv6Addr,
+    hwaddr: [u8; 6],
+    sent: usize,
+    // sent by the timer, see next_interval
+    unsolicited: usize,
+    // lwIP time of the last advertisement sent
+    last_sent: Option<u32>,
+    // destination of the answer to the pending solicitations
+    solicited: Option<Ipv6Addr>,
+}
+
+unsafe impl Send for AdvertiserState {}
+
+fn all_nodes() -> Ipv6Addr {
+    Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 1)
+}
+
+fn all_routers() -> Ipv6Addr {
+    Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 2)
+}
+
+fn secs(d: Duration) -> u32 {
+    d.as_secs().min(u32::max_value() as u64) as u32
+}
+
+impl AdvertiserState {
+    fn advertisement(&self) -> Vec<u8> {
+        let config = &self.config;
+
+        // checksum filled in by lwIP
+        let mut ra = vec![ICMP6_TYPE_RA, 0, 0, 0, config.hop_limit, 0];
+        ra.extend_from_slice(&(secs(config.router_lifetime).min(0xffff) as u16).to_be_bytes());
+        // reachable time and retransmission timer, unspecified
+        ra.extend_from_slice(&[0; 8]);
+
+        ra.extend_from_slice(&[ND6_OPTION_SOURCE_LLADDR, 1]);
+        ra.extend_from_slice(&self.hwaddr);
+
+        if let Some(mtu) = config.mtu {
+            ra.extend_from_slice(&[ND6_OPTION_MTU, 1, 0, 0]);
+            ra.extend_from_slice(&mtu.to_be_bytes());
+        }
+
+        for prefix in config.prefixes.iter() {
+            let mut flags = ND6_PREFIX_FLAG_ON_LINK;
+            if prefix.prefix() == 64 {
+                flags |= ND6_PREFIX_FLAG_AUTONOMOUS;
+            }
+            ra.extend_from_slice(&[ND6_OPTION_PREFIX_INFO, 4, prefix.prefix(), flags]);
+            ra.extend_from_slice(&secs(config.valid_life).to_be_bytes());
+            ra.extend_from_slice(&secs(config.preferred_life).to_be_bytes());
+            ra.extend_from_slice(&[0; 4]);
+            ra.extend_from_slice(&prefix.network().octets());
+        }
+
+        if !config.rdnss.is_empty() {
+            ra.extend_from_slice(&[ND6_OPTION_RDNSS, 1 + 2 * config.rdnss.len() as u8, 0, 0]);
+            // RFC 8106 recommends at least 3 times the maximum interval
+            ra.extend_from_slice(&secs(config.interval * 3).to_be_bytes());
+            for server in config.rdnss.iter() {
+                ra.extend_from_slice(&server.octets());
+            }
+        }
+
+        ra
+    }
+
+    /// Sends an advertisement, with the core lock held.
+    unsafe fn send(&mut self, dst: Ipv6Addr) {
+        let netif = lwip::netif_get_by_index(self.index);
+        if netif.is_null() {
+            return;
+        }
+
+        let ra = self.advertisement();
+        let p = lwip::pbuf_alloc(
+            lwip::pbuf_layer::PBUF_IP,
+            ra.len() as u16,
+            lwip::pbuf_type::PBUF_RAM,
+        );
+        if p.is_null() {
+            return;
+        }
+        lwip::pbuf_take(p, ra.as_ptr() as *const c_void, ra.len() as u16);
+
+        let src: lwip::ip_addr_t = IpAddr::V6(self.src).into();
+        let dst: lwip::ip_addr_t = IpAddr::V6(dst).into();
+        let ret: io::Result<()> = lwip::raw_sendto_if_src(self.pcb, p, &dst, netif, &src).into();
+        lwip::pbuf_free(p);
+
+        if ret.is_ok() {
+            self.sent += 1;
+            self.last_sent = Some(crate::time::now());
+        }
+    }
+
+    /// Delay before the next unsolicited advertisement (RFC 4861, 6.2.4).
+    fn next_interval(&self) -> Duration {
+        let max = self.config.interval.as_millis() as u64;
+        let mut next = Duration::from_millis(rand::thread_rng().gen_range(max / 3, max + 1));
+        if self.unsolicited < MAX_INITIAL_RTR_ADVERTISEMENTS {
+            next = next.min(MAX_INITIAL_RTR_ADVERT_INTERVAL);
+        }
+        next
+    }
+
+    /// Delay before answering a solicitation (RFC 4861, 6.2.6): a random
+    /// one, and at least MIN_DELAY_BETWEEN_RAS after the last advertisement.
+    fn solicited_delay(&self) -> Duration {
+        let max = MAX_RA_DELAY_TIME.as_millis() as u64;
+        let delay = Duration::from_millis(rand::thread_rng().gen_range(0, max + 1));
+
+        let since = match self.last_sent {
+            Some(last) => Duration::from_millis(crate::time::now().wrapping_sub(last) as u64),
+            None => return delay,
+        };
+        delay.max(MIN_DELAY_BETWEEN_RAS.checked_sub(since).unwrap_or_default())
+    }
+}
+
+unsafe extern "C" fn ra_timer(arg: *mut c_void) {
+    let state = &*(arg as *const Mutex<AdvertiserState>);
+    let mut state = state.lock().unwrap();
+    state.send(all_nodes());
+    state.unsolicited += 1;
+
+    let next = state.next_interval();
+    lwip::sys_timeout(next.as_millis() as u32, Some(ra_timer), arg);
+}
+
+unsafe extern "C" fn ra_solicited(arg: *mut c_void) {
+    let state = &*(arg as *const Mutex<AdvertiserState>);
+    let mut state = state.lock().unwrap();
+    let dst = match state.solicited.take() {
+        Some(dst) => dst,
+        None => return,
+    };
+    state.send(dst);
+
+    // a multicast answer counts as the next unsolicited advertisement
+    if dst == all_nodes() {
+        let next = state.next_interval();
+        lwip::sys_untimeout(Some(ra_timer), arg);
+        lwip::sys_timeout(next.as_millis() as u32, Some(ra_timer), arg);
+    }
+}
+
+unsafe extern "C" fn ra_recv(
+    arg: *mut c_void,
+    _: *mut lwip::raw_pcb,
+    p: *mut lwip::pbuf,
+    addr: *const lwip::ip_addr_t,
+) -> u8 {
+    // p starts at the IPv6 header, solicitations carry no extension header
+    if lwip::pbuf_get_at(p, 6) == IP6_NEXTH_ICMP6 && lwip::pbuf_get_at(p, 40) == ICMP6_TYPE_RS {
+        let state = &*(arg as *const Mutex<AdvertiserState>);
+        let mut state = state.lock().unwrap();
+
+        // RFC 4861 6.2.6 allows a unicast answer to a known source
+        let dst = match (*addr).try_into() {
+            Ok(IpAddr::V6(src)) if !src.is_unspecified() => src,
+            _ => all_nodes(),
+        };
+        match state.solicited {
+            // solicitations from several hosts get a single answer
+            Some(pending) if pending != dst => state.solicited = Some(all_nodes()),
+            Some(_) => {}
+            None => {
+                state.solicited = Some(dst);
+                let delay = state.solicited_delay();
+                lwip::sys_timeout(delay.as_millis() as u32, Some(ra_solicited), arg);
+            }
+        }
+    }
+
+    // let the ICMPv6 layer see it too
+    0
+}
@@ -1,73 +1,263 @@
-use std::future::Future;
 use std::io;
-use std::pin::Pin;
-use std::sync::Once;
-use std::task::{Context, Poll};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 
-use tokio::io::{AsyncRead, AsyncWrite};
-use transfer_async::{transfer, Transfer};
+use parking_lot::{const_mutex, Mutex};
 
 use crate::lwip;
-use crate::NetIf;
-use crate::Device;
-use crate::LoopbackNetIf;
+use crate::netconn::NetconnRef;
 
-static mut BUILDER_INIT: bool = false;
-static BUILDER_INIT_ONCE: Once = Once::new();
+struct StackState {
+    running: bool,
+    netconns: Vec<NetconnRef>,
+}
 
-pin_project_lite::pin_project! {
-    pub struct Stack<S>
-    {
-        #[pin]
-        tr: Transfer<NetIf, S>,
-    }
+static STACK: Mutex<StackState> = const_mutex(StackState {
+    running: false,
+    netconns: Vec::new(),
+});
+
+static GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" {
+    static mut lwip_rs_tcp_mss: u16;
+    static mut lwip_rs_tcp_wnd: u32;
+    static mut lwip_rs_tcp_snd_buf: u32;
+    static mut lwip_rs_tcp_snd_queuelen: u16;
+    static mut lwip_rs_tcp_msl: u32;
+    static mut lwip_rs_tcp_rcv_scale: u8;
+}
+
+fn build_default(value: &str) -> u32 {
+    value.parse().unwrap()
+}
+
+/// Tunables of the stack, applied when it starts.
+///
+/// Defaults come from lwipopts.h and can be overridden at build time with
+/// the `LWIP_TCP_MSS`, `LWIP_TCP_WND`, `LWIP_TCP_SND_BUF`,
+/// `LWIP_TCP_SND_QUEUELEN`, `LWIP_TCP_MSL` and `LWIP_TCP_RCV_SCALE`
+/// environment variables. `LWIP_IPV6_NUM_ADDRESSES` and
+/// `LWIP_TCP_MAX_SACK_NUM` also set the number of IPv6 addresses per netif
+/// and of SACK blocks sent, which are fixed at build time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StackConfig {
+    tcp_mss: u16,
+    tcp_wnd: u32,
+    tcp_snd_buf: u32,
+    tcp_snd_queuelen: u16,
+    time_wait: Duration,
+    tcp_rcv_scale: u8,
 }
 
-impl Default for Stack<LoopbackNetIf> {
+impl Default for StackConfig {
     fn default() -> Self {
-        Self::new(LoopbackNetIf::new()).unwrap()
+        StackConfig {
+            tcp_mss: build_default(env!("LWIP_RS_TCP_MSS")) as u16,
+            tcp_wnd: build_default(env!("LWIP_RS_TCP_WND")),
+            tcp_snd_buf: build_default(env!("LWIP_RS_TCP_SND_BUF")),
+            tcp_snd_queuelen: build_default(env!("LWIP_RS_TCP_SND_QUEUELEN")) as u16,
+            time_wait: Duration::from_millis(2 * build_default(env!("LWIP_RS_TCP_MSL")) as u64),
+            tcp_rcv_scale: build_default(env!("LWIP_RS_TCP_RCV_SCALE")) as u8,
+        }
     }
 }
 
-impl<D> Stack<D>
-where
-    D: AsyncRead + AsyncWrite + Device,
-{
-    pub fn new(device: D) -> io::Result<Self> {
-        if unsafe { BUILDER_INIT } {
-            Err(io::Error::new(
-                io::ErrorKind::AlreadyExists,
-                "Can only instanciate one builder",
-            ))
+impl StackConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn tcp_mss(mut self, mss: u16) -> Self {
+        self.tcp_mss = mss;
+        self
+    }
+
+    pub fn tcp_wnd(mut self, wnd: u32) -> Self {
+        self.tcp_wnd = wnd;
+        self
+    }
+
+    pub fn tcp_snd_buf(mut self, size: u32) -> Self {
+        self.tcp_snd_buf = size;
+        self
+    }
+
+    pub fn tcp_snd_queuelen(mut self, len: u16) -> Self {
+        self.tcp_snd_queuelen = len;
+        self
+    }
+
+    /// Time spent in TIME_WAIT, i.e. twice the maximum segment lifetime.
+    pub fn time_wait(mut self, time_wait: Duration) -> Self {
+        self.time_wait = time_wait;
+        self
+    }
+
+    /// Window scale announced to peers. Raised automatically when `tcp_wnd`
+    /// does not fit in 16 bits.
+    pub fn tcp_rcv_scale(mut self, scale: u8) -> Self {
+        self.tcp_rcv_scale = scale;
+        self
+    }
+
+    fn rcv_scale(&self) -> u8 {
+        let mut scale = self.tcp_rcv_scale;
+        while scale < 14 && self.tcp_wnd > 0xffff << scale {
+            scale += 1;
+        }
+        scale
+    }
+
+    /// Number of IPv6 addresses per netif, only settable at build time
+    /// with the `LWIP_IPV6_NUM_ADDRESSES` environment variable.
+    pub fn ipv6_num_addresses() -> usize {
+        build_default(env!("LWIP_IPV6_NUM_ADDRESSES")) as usize
+    }
+
+    /// Whether selective acknowledgements are sent, set with the `sack`
+    /// cargo feature.
+    pub fn sack() -> bool {
+        cfg!(feature = "sack")
+    }
+
+    fn validate(&self) -> io::Result<()> {
+        let mss = self.tcp_mss as u32;
+        let invalid = |msg| Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+
+        if mss == 0 {
+            invalid("tcp_mss must not be 0")
+        } else if self.tcp_wnd < 2 * mss {
+            invalid("tcp_wnd must be at least 2 * tcp_mss")
+        } else if self.tcp_rcv_scale > 14 {
+            invalid("tcp_rcv_scale must be at most 14")
+        } else if self.tcp_wnd > 0xffff << self.rcv_scale() {
+            invalid("tcp_wnd is too large")
+        } else if self.tcp_snd_buf < 2 * mss {
+            invalid("tcp_snd_buf must be at least 2 * tcp_mss")
+        } else if (self.tcp_snd_queuelen as u32) < 2 * self.tcp_snd_buf / mss {
+            invalid("tcp_snd_queuelen must be at least 2 * tcp_snd_buf / tcp_mss")
         } else {
-            Self::init(device)
+            Ok(())
         }
     }
 
-    fn init(device: D) -> io::Result<Self> {
-        BUILDER_INIT_ONCE.call_once(|| unsafe {
-            let err: io::Result<()> = lwip::tcpip_init_block().into();
-            err.expect("unable to initialise the TCP/IP stack");
-            BUILDER_INIT = true;
-        });
-        Ok(Stack {
-            tr: transfer(NetIf::new(&device)?, device),
-        })
+    /// Starts the stack with this configuration.
+    ///
+    /// Fails if the stack is already running; use `Stack::shutdown` first to
+    /// change the configuration of a running stack.
+    pub fn start(self) -> io::Result<()> {
+        self.validate()?;
+
+        let mut state = STACK.lock();
+        if state.running {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "the stack is already running",
+            ));
+        }
+
+        unsafe {
+            lwip_rs_tcp_mss = self.tcp_mss;
+            lwip_rs_tcp_wnd = self.tcp_wnd;
+            lwip_rs_tcp_snd_buf = self.tcp_snd_buf;
+            lwip_rs_tcp_snd_queuelen = self.tcp_snd_queuelen;
+            lwip_rs_tcp_msl = (self.time_wait.as_millis() / 2) as u32;
+            lwip_rs_tcp_rcv_scale = self.rcv_scale();
+        }
+
+        start(&mut state);
+        Ok(())
+    }
+}
+
+/// Handle on the global lwIP stack.
+///
+/// The stack is started lazily by the first socket or netif created.
+#[derive(Debug, Copy, Clone)]
+pub struct Stack;
+
+impl Stack {
+    pub fn is_running() -> bool {
+        STACK.lock().running
+    }
+
+    /// Tears the stack down, see [`Stack::shutdown_timeout`].
+    pub async fn shutdown() -> io::Result<()> {
+        Self::shutdown_timeout(Duration::from_secs(1)).await
     }
 
-    pub fn into_inner(self) -> D {
-        let (_, device) = self.tr.into_inner();
-        device
+    /// Stops the tcpip thread and frees the global state.
+    ///
+    /// TCP connections that are already closing get `grace` to complete,
+    /// then all remaining sockets are aborted and return `ECONNABORTED`, the
+    /// PCBs left by closed sockets and by the servers bound to netifs are
+    /// freed, netifs are removed and pending timers are dropped. Sockets,
+    /// servers and netifs from a stopped stack must not be used anymore; the
+    /// next socket or netif created starts a new stack.
+    pub async fn shutdown_timeout(grace: Duration) -> io::Result<()> {
+        if !Self::is_running() {
+            return Ok(());
+        }
+
+        let deadline = Instant::now() + grace;
+        while lwip::with_core_lock(|| unsafe { lwip::tcpip_closing_pcbs() }) > 0
+            && Instant::now() < deadline
+        {
+            tokio::time::delay_for(Duration::from_millis(10)).await;
+        }
+
+        let mut state = STACK.lock();
+        if !state.running {
+            return Ok(());
+        }
+
+        for conn in state.netconns.drain(..).filter_map(|c| c.upgrade()) {
+            let conn = conn.lock().unwrap();
+            lwip::with_core_lock(|| unsafe { lwip::netconn_abort_pcb(conn.conn) });
+        }
+
+        let ret: io::Result<()> = unsafe { lwip::tcpip_shutdown_block() }.into();
+        ret?;
+
+        state.running = false;
+        Ok(())
     }
 }
 
-impl<D> Future for Stack<D>
-where
-    D: AsyncRead + AsyncWrite + Device,
-{
-    type Output = io::Result<(u64, u64)>;
+fn start(state: &mut StackState) {
+    let err: io::Result<()> = unsafe { lwip::tcpip_init_block() }.into();
+    err.expect("unable to initialise the TCP/IP stack");
+    GENERATION.fetch_add(1, Ordering::SeqCst);
+    state.running = true;
+}
 
-    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        self.project().tr.poll(cx)
+pub(crate) fn stack_init() {
+    let mut state = STACK.lock();
+    if !state.running {
+        start(&mut state);
     }
 }
+
+pub(crate) fn tcp_wnd() -> u32 {
+    unsafe { lwip_rs_tcp_wnd }
+}
+
+pub(crate) fn tcp_snd_buf() -> u32 {
+    unsafe { lwip_rs_tcp_snd_buf }
+}
+
+pub(crate) fn tcp_mss() -> u16 {
+    unsafe { lwip_rs_tcp_mss }
+}
+
+/// Identifies the running stack instance.
+pub(crate) fn generation() -> usize {
+    GENERATION.load(Ordering::SeqCst)
+}
+
+pub(crate) fn register_netconn(conn: NetconnRef) {
+    let mut state = STACK.lock();
+    state.netconns.retain(|c| c.strong_count() > 0);
+    state.netconns.push(conn);
+}
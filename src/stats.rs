@@ -0,0 +1,252 @@
+use std::ffi::CStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::lwip;
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct ProtoStats {
+    pub xmit: u64,
+    pub recv: u64,
+    pub fw: u64,
+    pub drop: u64,
+    pub chkerr: u64,
+    pub lenerr: u64,
+    pub memerr: u64,
+    pub rterr: u64,
+    pub proterr: u64,
+    pub opterr: u64,
+    pub err: u64,
+}
+
+impl From<&lwip::stats_proto> for ProtoStats {
+    fn from(s: &lwip::stats_proto) -> Self {
+        ProtoStats {
+            xmit: s.xmit as u64,
+            recv: s.recv as u64,
+            fw: s.fw as u64,
+            drop: s.drop as u64,
+            chkerr: s.chkerr as u64,
+            lenerr: s.lenerr as u64,
+            memerr: s.memerr as u64,
+            rterr: s.rterr as u64,
+            proterr: s.proterr as u64,
+            opterr: s.opterr as u64,
+            err: s.err as u64,
+        }
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct MemStats {
+    pub name: &'static str,
+    pub avail: u64,
+    pub used: u64,
+    pub max: u64,
+    pub err: u64,
+    pub illegal: u64,
+}
+
+impl From<&lwip::stats_mem> for MemStats {
+    fn from(s: &lwip::stats_mem) -> Self {
+        let name = if s.name.is_null() {
+            "unknown"
+        } else {
+            // names are static strings from memp_std.h
+            unsafe { CStr::from_ptr(s.name) }
+                .to_str()
+                .unwrap_or("unknown")
+        };
+
+        MemStats {
+            name,
+            avail: s.avail as u64,
+            used: s.used as u64,
+            max: s.max as u64,
+            err: s.err as u64,
+            illegal: s.illegal as u64,
+        }
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct TcpStats {
+    pub segs_in: u64,
+    pub segs_out: u64,
+    pub retransmits: u64,
+    pub active_opens: u64,
+    pub passive_opens: u64,
+    pub attempt_fails: u64,
+    pub resets_out: u64,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Stats {
+    pub link: ProtoStats,
+    pub ip4: ProtoStats,
+    pub ip4_frag: ProtoStats,
+    pub ip6: ProtoStats,
+    pub ip6_frag: ProtoStats,
+    pub icmp6: ProtoStats,
+    pub nd6: ProtoStats,
+    pub udp: ProtoStats,
+    pub tcp: ProtoStats,
+    pub tcp_mib: TcpStats,
+    pub mem: MemStats,
+    pub memp: Vec<MemStats>,
+}
+
+pub fn stats() -> Stats {
+    lwip::with_core_lock(|| {
+        let s = unsafe { &lwip::lwip_stats };
+
+        Stats {
+            link: (&s.link).into(),
+            ip4: (&s.ip).into(),
+            ip4_frag: (&s.ip_frag).into(),
+            ip6: (&s.ip6).into(),
+            ip6_frag: (&s.ip6_frag).into(),
+            icmp6: (&s.icmp6).into(),
+            nd6: (&s.nd6).into(),
+            udp: (&s.udp).into(),
+            tcp: (&s.tcp).into(),
+            tcp_mib: TcpStats {
+                segs_in: s.mib2.tcpinsegs as u64,
+                segs_out: s.mib2.tcpoutsegs as u64,
+                retransmits: s.mib2.tcpretranssegs as u64,
+                active_opens: s.mib2.tcpactiveopens as u64,
+                passive_opens: s.mib2.tcppassiveopens as u64,
+                attempt_fails: s.mib2.tcpattemptfails as u64,
+                resets_out: s.mib2.tcpoutrsts as u64,
+            },
+            mem: (&s.mem).into(),
+            memp: s
+                .memp
+                .iter()
+                .filter(|p| !p.is_null())
+                .map(|p| unsafe { &**p }.into())
+                .collect(),
+        }
+    })
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct NetIfStats {
+    pub packets_in: u64,
+    pub bytes_in: u64,
+    pub drops_in: u64,
+    pub packets_out: u64,
+    pub bytes_out: u64,
+    pub drops_out: u64,
+    /// Received packets dropped for a bad IP, TCP, UDP or ICMPv6 checksum.
+    pub checksum_errors: u64,
+    /// Retransmissions of the TCP connections sent through the interface.
+    pub retransmits: u64,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct NetIfCounters {
+    packets_in: AtomicU64,
+    bytes_in: AtomicU64,
+    drops_in: AtomicU64,
+    packets_out: AtomicU64,
+    bytes_out: AtomicU64,
+    drops_out: AtomicU64,
+    checksum_errors: AtomicU64,
+    retransmits: AtomicU64,
+}
+
+impl NetIfCounters {
+    pub(crate) fn input(&self, len: usize) {
+        self.packets_in.fetch_add(1, Ordering::Relaxed);
+        self.bytes_in.fetch_add(len as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn input_drop(&self) {
+        self.drops_in.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn output(&self, len: usize) {
+        self.packets_out.fetch_add(1, Ordering::Relaxed);
+        self.bytes_out.fetch_add(len as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn output_drop(&self) {
+        self.drops_out.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn checksum_errors(&self, n: u32) {
+        self.checksum_errors.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn retransmits(&self, n: u32) {
+        self.retransmits.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> NetIfStats {
+        NetIfStats {
+            packets_in: self.packets_in.load(Ordering::Relaxed),
+            bytes_in: self.bytes_in.load(Ordering::Relaxed),
+            drops_in: self.drops_in.load(Ordering::Relaxed),
+            packets_out: self.packets_out.load(Ordering::Relaxed),
+            bytes_out: self.bytes_out.load(Ordering::Relaxed),
+            drops_out: self.drops_out.load(Ordering::Relaxed),
+            checksum_errors: self.checksum_errors.load(Ordering::Relaxed),
+            retransmits: self.retransmits.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(feature = "metrics")]
+mod export {
+    use metrics::gauge;
+
+    use super::{stats, NetIfStats, ProtoStats};
+
+    fn export_proto(proto: &'static str, s: &ProtoStats) {
+        gauge!("lwip.proto.xmit", s.xmit as i64, "proto" => proto);
+        gauge!("lwip.proto.recv", s.recv as i64, "proto" => proto);
+        gauge!("lwip.proto.drop", s.drop as i64, "proto" => proto);
+        gauge!("lwip.proto.chkerr", s.chkerr as i64, "proto" => proto);
+        gauge!("lwip.proto.memerr", s.memerr as i64, "proto" => proto);
+        gauge!("lwip.proto.err", s.err as i64, "proto" => proto);
+    }
+
+    pub fn export_metrics() {
+        let s = stats();
+
+        export_proto("link", &s.link);
+        export_proto("ip4", &s.ip4);
+        export_proto("ip6", &s.ip6);
+        export_proto("icmp6", &s.icmp6);
+        export_proto("udp", &s.udp);
+        export_proto("tcp", &s.tcp);
+
+        gauge!("lwip.tcp.retransmits", s.tcp_mib.retransmits as i64);
+        gauge!("lwip.tcp.segs_in", s.tcp_mib.segs_in as i64);
+        gauge!("lwip.tcp.segs_out", s.tcp_mib.segs_out as i64);
+
+        gauge!("lwip.mem.used", s.mem.used as i64);
+        gauge!("lwip.mem.err", s.mem.err as i64);
+        for pool in s.memp {
+            gauge!("lwip.memp.used", pool.used as i64, "pool" => pool.name);
+            gauge!("lwip.memp.max", pool.max as i64, "pool" => pool.name);
+            gauge!("lwip.memp.err", pool.err as i64, "pool" => pool.name);
+        }
+    }
+
+    impl NetIfStats {
+        pub fn export_metrics(&self, netif: &str) {
+            gauge!("lwip.netif.packets_in", self.packets_in as i64, "netif" => netif.to_owned());
+            gauge!("lwip.netif.bytes_in", self.bytes_in as i64, "netif" => netif.to_owned());
+            gauge!("lwip.netif.drops_in", self.drops_in as i64, "netif" => netif.to_owned());
+            gauge!("lwip.netif.packets_out", self.packets_out as i64, "netif" => netif.to_owned());
+            gauge!("lwip.netif.bytes_out", self.bytes_out as i64, "netif" => netif.to_owned());
+            gauge!("lwip.netif.drops_out", self.drops_out as i64, "netif" => netif.to_owned());
+            gauge!("lwip.netif.checksum_errors", self.checksum_errors as i64, "netif" => netif.to_owned());
+            gauge!("lwip.netif.retransmits", self.retransmits as i64, "netif" => netif.to_owned());
+        }
+    }
+}
+
+#[cfg(feature = "metrics")]
+pub use self::export::*;
@@ -0,0 +1,342 @@
+use std::fmt;
+use std::os::raw::c_void;
+use std::panic::{self, AssertUnwindSafe};
+use std::time::Duration;
+
+use crate::lwip;
+
+/// Congestion state of a connection, in bytes.
+///
+/// Controllers update `cwnd` and `ssthresh`, the other fields are read-only
+/// snapshots of the connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CcState {
+    pub cwnd: u32,
+    pub ssthresh: u32,
+    pub mss: u32,
+    pub bytes_in_flight: u32,
+    /// Latest RTT sample, only known when timestamps are used.
+    pub rtt: Option<Duration>,
+    /// Time of the event, on the stack clock (see `lwip::time`).
+    pub now: Duration,
+    /// Whether lwIP is in fast recovery.
+    pub in_recovery: bool,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Loss {
+    /// Three duplicate ACKs, the first unacked segment was resent.
+    FastRetransmit,
+    /// The retransmission timer expired.
+    Timeout,
+}
+
+/// A congestion control algorithm attached to a TCP connection.
+///
+/// The controller owns `cwnd` and `ssthresh`: lwIP's own NewReno updates
+/// are discarded, except for the window inflation during fast recovery.
+/// Should the controller panic, lwIP's updates are kept from then on.
+pub trait CongestionControl: Send {
+    fn name(&self) -> &'static str;
+
+    /// New data was acknowledged.
+    fn on_ack(&mut self, state: &mut CcState, acked: u32);
+
+    fn on_loss(&mut self, state: &mut CcState, loss: Loss);
+}
+
+impl fmt::Debug for dyn CongestionControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+type BoxedCc = Box<dyn CongestionControl>;
+
+struct CcHandle {
+    cc: BoxedCc,
+    // last window set by the controller, lwIP's own updates are ignored
+    cwnd: Option<u32>,
+    recovering: bool,
+    // the controller panicked, lwIP's own updates are kept
+    failed: bool,
+}
+
+const TF_INFR: u16 = 0x04; /* TODO: bindgen skips casted defines */
+
+unsafe extern "C" fn cc_event(
+    cc: *mut c_void,
+    pcb: *mut lwip::tcp_pcb,
+    event: lwip::tcp_cc_event,
+    acked: u32,
+    rtt: u32,
+) {
+    let handle = &mut *(cc as *mut CcHandle);
+    if handle.failed {
+        return;
+    }
+
+    let pcb = &mut *pcb;
+    let in_recovery = pcb.flags & TF_INFR != 0;
+
+    if handle.recovering && !in_recovery {
+        // lwIP deflated the window when leaving fast recovery
+        handle.recovering = false;
+        handle.cwnd = None;
+    }
+
+    let mut state = CcState {
+        cwnd: handle.cwnd.unwrap_or(pcb.cwnd),
+        ssthresh: pcb.ssthresh,
+        mss: pcb.mss as u32,
+        bytes_in_flight: pcb.snd_nxt.wrapping_sub(pcb.lastack),
+        rtt: if rtt > 0 {
+            Some(Duration::from_millis(rtt as u64))
+        } else {
+            None
+        },
+        now: Duration::from_millis(crate::time::now() as u64),
+        in_recovery,
+    };
+
+    // must not unwind into lwIP
+    let cc = &mut handle.cc;
+    let ret = panic::catch_unwind(AssertUnwindSafe(|| match event {
+        lwip::tcp_cc_event::TCP_CC_EVENT_ACK => cc.on_ack(&mut state, acked),
+        lwip::tcp_cc_event::TCP_CC_EVENT_FAST_RETRANSMIT => {
+            cc.on_loss(&mut state, Loss::FastRetransmit)
+        }
+        lwip::tcp_cc_event::TCP_CC_EVENT_TIMEOUT => cc.on_loss(&mut state, Loss::Timeout),
+    }));
+    if ret.is_err() {
+        log::warn!(target: "lwip::tcp", "{:?} panicked, falling back to lwIP's", handle.cc);
+        handle.failed = true;
+        return;
+    }
+
+    match event {
+        lwip::tcp_cc_event::TCP_CC_EVENT_ACK if in_recovery => {
+            // lwIP inflates the window on duplicate ACKs, leave it be.
+            return;
+        }
+        lwip::tcp_cc_event::TCP_CC_EVENT_FAST_RETRANSMIT => handle.recovering = true,
+        _ => {}
+    }
+
+    let cwnd = state.cwnd.max(pcb.mss as u32);
+    handle.cwnd = Some(cwnd);
+    pcb.cwnd = cwnd;
+    pcb.ssthresh = state.ssthresh;
+}
+
+unsafe extern "C" fn cc_free(cc: *mut c_void) {
+    Box::from_raw(cc as *mut CcHandle);
+}
+
+/// Attaches `cc` to `pcb`, with the core lock held.
+pub(crate) unsafe fn set_congestion_control(pcb: *mut lwip::tcp_pcb, cc: BoxedCc) -> lwip::err_t {
+    let cc = Box::into_raw(Box::new(CcHandle {
+        cc,
+        cwnd: None,
+        recovering: false,
+        failed: false,
+    }));
+    let err = lwip::tcp_cc_set(pcb, cc as *mut c_void, Some(cc_event), Some(cc_free));
+    if err != lwip::err_enum_t::ERR_OK {
+        cc_free(cc as *mut c_void);
+    }
+    err
+}
+
+fn loss_ssthresh(state: &CcState) -> u32 {
+    (state.bytes_in_flight / 2).max(2 * state.mss)
+}
+
+/// NewReno (RFC 5681), as lwIP does it.
+#[derive(Debug, Default)]
+pub struct Reno {
+    bytes_acked: u32,
+}
+
+impl Reno {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl CongestionControl for Reno {
+    fn name(&self) -> &'static str {
+        "reno"
+    }
+
+    fn on_ack(&mut self, state: &mut CcState, acked: u32) {
+        if state.in_recovery {
+            return;
+        }
+
+        if state.cwnd < state.ssthresh {
+            state.cwnd += acked.min(2 * state.mss);
+        } else {
+            self.bytes_acked += acked;
+            if self.bytes_acked >= state.cwnd {
+                self.bytes_acked -= state.cwnd;
+                state.cwnd += state.mss;
+            }
+        }
+    }
+
+    fn on_loss(&mut self, state: &mut CcState, loss: Loss) {
+        self.bytes_acked = 0;
+        state.ssthresh = loss_ssthresh(state);
+        state.cwnd = match loss {
+            Loss::FastRetransmit => state.ssthresh + 3 * state.mss,
+            Loss::Timeout => state.mss,
+        };
+    }
+}
+
+/// CUBIC (RFC 8312).
+#[derive(Debug)]
+pub struct Cubic {
+    w_max: f64,
+    k: f64,
+    epoch_start: Option<Duration>,
+    origin: f64,
+    w_est: f64,
+    min_rtt: Option<Duration>,
+}
+
+const CUBIC_C: f64 = 0.4;
+const CUBIC_BETA: f64 = 0.7;
+
+impl Default for Cubic {
+    fn default() -> Self {
+        Cubic {
+            w_max: 0.0,
+            k: 0.0,
+            epoch_start: None,
+            origin: 0.0,
+            w_est: 0.0,
+            min_rtt: None,
+        }
+    }
+}
+
+impl Cubic {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn congestion_avoidance(&mut self, state: &mut CcState, acked: u32) {
+        let mss = state.mss as f64;
+        let cwnd = state.cwnd as f64 / mss;
+        let rtt = self.min_rtt.map(|d| d.as_secs_f64()).unwrap_or_default();
+
+        let epoch_start = match self.epoch_start {
+            Some(t) if t <= state.now => t,
+            // a new epoch, also when the stack clock went back or wrapped
+            _ => {
+                self.epoch_start = Some(state.now);
+                if cwnd < self.w_max {
+                    self.k = ((self.w_max - cwnd) / CUBIC_C).cbrt();
+                    self.origin = self.w_max;
+                } else {
+                    self.k = 0.0;
+                    self.origin = cwnd;
+                }
+                self.w_est = cwnd;
+                state.now
+            }
+        };
+
+        let t = (state.now - epoch_start).as_secs_f64() + rtt;
+        let target = self.origin + CUBIC_C * (t - self.k).powi(3);
+
+        // TCP-friendly region
+        self.w_est += 3.0 * (1.0 - CUBIC_BETA) / (1.0 + CUBIC_BETA) * (acked as f64 / mss) / cwnd;
+
+        let next = if self.w_est > target {
+            self.w_est
+        } else if target > cwnd {
+            cwnd + (target - cwnd) / cwnd * (acked as f64 / mss)
+        } else {
+            cwnd + 0.01 * (acked as f64 / mss) / cwnd
+        };
+
+        state.cwnd = (next.max(cwnd) * mss) as u32;
+    }
+}
+
+impl CongestionControl for Cubic {
+    fn name(&self) -> &'static str {
+        "cubic"
+    }
+
+    fn on_ack(&mut self, state: &mut CcState, acked: u32) {
+        if let Some(rtt) = state.rtt {
+            self.min_rtt = Some(self.min_rtt.map_or(rtt, |min| min.min(rtt)));
+        }
+        if state.in_recovery {
+            return;
+        }
+
+        if state.cwnd < state.ssthresh {
+            state.cwnd += acked.min(2 * state.mss);
+        } else {
+            self.congestion_avoidance(state, acked);
+        }
+    }
+
+    fn on_loss(&mut self, state: &mut CcState, loss: Loss) {
+        let cwnd = state.cwnd as f64 / state.mss as f64;
+
+        // fast convergence
+        self.w_max = if cwnd < self.w_max {
+            cwnd * (1.0 + CUBIC_BETA) / 2.0
+        } else {
+            cwnd
+        };
+        self.epoch_start = None;
+
+        state.ssthresh = ((state.cwnd as f64 * CUBIC_BETA).round() as u32).max(2 * state.mss);
+        state.cwnd = match loss {
+            Loss::FastRetransmit => state.ssthresh,
+            Loss::Timeout => state.mss,
+        };
+    }
+}
+
+/// Keeps the window at `rate` times the RTT, whatever the losses.
+#[derive(Debug)]
+pub struct FixedRate {
+    // bits per second
+    rate: u64,
+}
+
+impl FixedRate {
+    pub fn new(bits_per_sec: u64) -> Self {
+        FixedRate { rate: bits_per_sec }
+    }
+
+    fn update(&self, state: &mut CcState) {
+        if let Some(rtt) = state.rtt {
+            let bdp = (self.rate as f64 / 8.0 * rtt.as_secs_f64()) as u32;
+            state.cwnd = bdp.max(2 * state.mss);
+        }
+        state.ssthresh = state.cwnd;
+    }
+}
+
+impl CongestionControl for FixedRate {
+    fn name(&self) -> &'static str {
+        "fixed-rate"
+    }
+
+    fn on_ack(&mut self, state: &mut CcState, _: u32) {
+        self.update(state);
+    }
+
+    fn on_loss(&mut self, state: &mut CcState, _: Loss) {
+        self.update(state);
+    }
+}
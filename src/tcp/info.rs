@@ -0,0 +1,107 @@
+use std::time::Duration;
+
+use crate::lwip;
+
+// lwIP measures RTT and RTO in slow timer ticks.
+const TCP_SLOW_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TcpState {
+    Closed,
+    Listen,
+    SynSent,
+    SynRcvd,
+    Established,
+    FinWait1,
+    FinWait2,
+    CloseWait,
+    Closing,
+    LastAck,
+    TimeWait,
+}
+
+impl From<lwip::tcp_state> for TcpState {
+    fn from(state: lwip::tcp_state) -> Self {
+        match state {
+            lwip::tcp_state_LISTEN => TcpState::Listen,
+            lwip::tcp_state_SYN_SENT => TcpState::SynSent,
+            lwip::tcp_state_SYN_RCVD => TcpState::SynRcvd,
+            lwip::tcp_state_ESTABLISHED => TcpState::Established,
+            lwip::tcp_state_FIN_WAIT_1 => TcpState::FinWait1,
+            lwip::tcp_state_FIN_WAIT_2 => TcpState::FinWait2,
+            lwip::tcp_state_CLOSE_WAIT => TcpState::CloseWait,
+            lwip::tcp_state_CLOSING => TcpState::Closing,
+            lwip::tcp_state_LAST_ACK => TcpState::LastAck,
+            lwip::tcp_state_TIME_WAIT => TcpState::TimeWait,
+            _ => TcpState::Closed,
+        }
+    }
+}
+
+/// Snapshot of a TCP connection, in the spirit of Linux's TCP_INFO.
+///
+/// Windows and counters are in bytes unless stated otherwise.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TcpInfo {
+    pub state: TcpState,
+    /// Smoothed RTT, with lwIP's 500ms granularity.
+    pub srtt: Duration,
+    pub rttvar: Duration,
+    pub rto: Duration,
+    pub cwnd: u32,
+    pub ssthresh: u32,
+    /// Window advertised by the peer.
+    pub snd_wnd: u32,
+    pub rcv_wnd: u32,
+    pub mss: u16,
+    pub bytes_in_flight: u32,
+    /// Consecutive retransmissions of the oldest unacked segment.
+    pub retransmits: u8,
+    /// Segments sent but not acknowledged.
+    pub unacked: usize,
+    /// Segments queued but not sent yet.
+    pub unsent: usize,
+    /// Out-of-sequence segments held by the receiver.
+    pub ooseq: usize,
+    /// Send buffer space left.
+    pub snd_buf: u32,
+}
+
+unsafe fn count_segs(mut seg: *mut lwip::tcp_seg) -> usize {
+    let mut n = 0;
+    while !seg.is_null() {
+        n += 1;
+        seg = (*seg).next;
+    }
+    n
+}
+
+fn ticks(n: i32) -> Duration {
+    TCP_SLOW_INTERVAL * n.max(0) as u32
+}
+
+impl TcpInfo {
+    /// Reads `pcb`, with the core lock held.
+    pub(crate) unsafe fn from_pcb(pcb: *const lwip::tcp_pcb) -> Self {
+        let pcb = &*pcb;
+
+        TcpInfo {
+            state: pcb.state.into(),
+            // sa is scaled by 8 and sv by 4, see tcp_receive()
+            srtt: ticks((pcb.sa >> 3) as i32),
+            rttvar: ticks((pcb.sv >> 2) as i32),
+            rto: ticks(pcb.rto as i32),
+            cwnd: pcb.cwnd,
+            ssthresh: pcb.ssthresh,
+            snd_wnd: pcb.snd_wnd,
+            rcv_wnd: pcb.rcv_wnd,
+            mss: pcb.mss,
+            bytes_in_flight: pcb.snd_nxt.wrapping_sub(pcb.lastack),
+            retransmits: pcb.nrtx,
+            unacked: count_segs(pcb.unacked),
+            unsent: count_segs(pcb.unsent),
+            ooseq: count_segs(pcb.ooseq),
+            snd_buf: pcb.snd_buf,
+        }
+    }
+}
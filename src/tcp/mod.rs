@@ -3,3 +3,12 @@ pub use self::listener::*;
 
 mod stream;
 pub use self::stream::*;
+
+mod cc;
+pub use self::cc::*;
+
+mod info;
+pub use self::info::*;
+
+mod netstat;
+pub use self::netstat::*;
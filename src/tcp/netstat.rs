@@ -0,0 +1,156 @@
+use std::convert::TryInto;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+
+use crate::lwip;
+use crate::tcp::TcpState;
+
+const UDP_FLAGS_CONNECTED: u8 = 0x04; /* TODO: bindgen skips casted defines */
+const RAW_FLAGS_CONNECTED: u8 = 0x01; /* TODO: bindgen skips casted defines */
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Protocol {
+    Tcp,
+    Udp,
+    /// Raw PCB bound to an IP protocol number.
+    Raw(u8),
+}
+
+/// An entry of the stack's PCB tables.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Connection {
+    pub proto: Protocol,
+    /// Raw PCBs have no ports, they are reported as 0.
+    pub local: SocketAddr,
+    pub remote: Option<SocketAddr>,
+    /// Only for TCP.
+    pub state: Option<TcpState>,
+    /// Index of the netif the PCB is bound to, if any.
+    pub netif: Option<u8>,
+    /// TCP bytes received but not read yet.
+    pub recv_queue: usize,
+    /// TCP bytes written but not acknowledged yet.
+    pub send_queue: usize,
+}
+
+fn ip(addr: lwip::ip_addr) -> IpAddr {
+    // IPADDR_TYPE_ANY: bound to both families
+    addr.try_into().unwrap_or(IpAddr::V6(Ipv6Addr::UNSPECIFIED))
+}
+
+fn netif(idx: u8) -> Option<u8> {
+    // NETIF_NO_INDEX
+    if idx == 0 {
+        None
+    } else {
+        Some(idx)
+    }
+}
+
+/// Bytes queued for the application: the window is only credited back once
+/// they are read.
+unsafe fn recv_queue(p: &lwip::tcp_pcb) -> usize {
+    let mut len = 0;
+    // the netconn, until it is closed
+    let conn = p.callback_arg as *const lwip::netconn;
+    if !conn.is_null() {
+        len += (*conn).recv_avail.max(0) as usize;
+    }
+    if !p.refused_data.is_null() {
+        len += (*p.refused_data).tot_len as usize;
+    }
+    len
+}
+
+unsafe fn tcp_connections(mut pcb: *mut lwip::tcp_pcb, list: &mut Vec<Connection>) {
+    while !pcb.is_null() {
+        let p = &*pcb;
+        let established =
+            p.state >= lwip::tcp_state_ESTABLISHED && p.state != lwip::tcp_state_TIME_WAIT;
+
+        list.push(Connection {
+            proto: Protocol::Tcp,
+            local: SocketAddr::new(ip(p.local_ip), p.local_port),
+            // bound only
+            remote: if p.state == lwip::tcp_state_CLOSED {
+                None
+            } else {
+                Some(SocketAddr::new(ip(p.remote_ip), p.remote_port))
+            },
+            state: Some(p.state.into()),
+            netif: netif(p.netif_idx),
+            recv_queue: if established { recv_queue(p) } else { 0 },
+            send_queue: if established {
+                p.snd_lbb.wrapping_sub(p.lastack) as usize
+            } else {
+                0
+            },
+        });
+        pcb = p.next;
+    }
+}
+
+/// Lists the TCP, UDP and raw PCBs of the stack, like netstat.
+pub fn connections() -> Vec<Connection> {
+    let mut list = Vec::new();
+
+    lwip::with_core_lock(|| unsafe {
+        let mut lpcb = lwip::tcp_listen_pcbs.listen_pcbs;
+        while !lpcb.is_null() {
+            let p = &*lpcb;
+            list.push(Connection {
+                proto: Protocol::Tcp,
+                local: SocketAddr::new(ip(p.local_ip), p.local_port),
+                remote: None,
+                state: Some(TcpState::Listen),
+                netif: netif(p.netif_idx),
+                recv_queue: 0,
+                send_queue: 0,
+            });
+            lpcb = p.next;
+        }
+
+        tcp_connections(lwip::tcp_bound_pcbs, &mut list);
+        tcp_connections(lwip::tcp_active_pcbs, &mut list);
+        tcp_connections(lwip::tcp_tw_pcbs, &mut list);
+
+        let mut upcb = lwip::udp_pcbs;
+        while !upcb.is_null() {
+            let p = &*upcb;
+            list.push(Connection {
+                proto: Protocol::Udp,
+                local: SocketAddr::new(ip(p.local_ip), p.local_port),
+                remote: if p.flags & UDP_FLAGS_CONNECTED != 0 {
+                    Some(SocketAddr::new(ip(p.remote_ip), p.remote_port))
+                } else {
+                    None
+                },
+                state: None,
+                netif: netif(p.netif_idx),
+                recv_queue: 0,
+                send_queue: 0,
+            });
+            upcb = p.next;
+        }
+
+        let mut rpcb = lwip::raw_get_pcbs();
+        while !rpcb.is_null() {
+            let p = &*rpcb;
+            list.push(Connection {
+                proto: Protocol::Raw(p.protocol),
+                local: SocketAddr::new(ip(p.local_ip), 0),
+                remote: if p.flags & RAW_FLAGS_CONNECTED != 0 {
+                    Some(SocketAddr::new(ip(p.remote_ip), 0))
+                } else {
+                    None
+                },
+                state: None,
+                netif: netif(p.netif_idx),
+                recv_queue: 0,
+                send_queue: 0,
+            });
+            rpcb = p.next;
+        }
+    });
+
+    list
+}
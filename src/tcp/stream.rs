@@ -3,6 +3,8 @@ use std::io;
 use futures::future::poll_fn;
 use tokio::net::ToSocketAddrs;
 
+use crate::tcp::cc::{self, CongestionControl};
+use crate::tcp::TcpInfo;
 use crate::{Netconn, NetconnSocket};
 
 pub type TcpStream = NetconnSocket;
@@ -47,4 +49,36 @@ impl TcpStream {
         netconn.bind_ip_port(src.ip(), src.port())?;
         Self::connect_priv(netconn, host).await
     }
+
+    pub fn recv_buffer_size(&self) -> io::Result<usize> {
+        self.with_netconn(|conn| conn.recv_buffer_size())
+    }
+
+    pub fn set_recv_buffer_size(&self, size: usize) -> io::Result<()> {
+        self.with_netconn(|conn| conn.set_recv_buffer_size(size))
+    }
+
+    pub fn send_buffer_size(&self) -> io::Result<usize> {
+        self.with_netconn(|conn| conn.send_buffer_size())
+    }
+
+    pub fn set_send_buffer_size(&self, size: usize) -> io::Result<()> {
+        self.with_netconn(|conn| conn.set_send_buffer_size(size))
+    }
+
+    /// Replaces lwIP's built-in NewReno for this connection.
+    pub fn set_congestion_control<C>(&self, cc: C) -> io::Result<()>
+    where
+        C: CongestionControl + 'static,
+    {
+        self.with_netconn(|conn| {
+            conn.with_tcp_pcb(|pcb| unsafe { cc::set_congestion_control(pcb, Box::new(cc)) })
+        })?
+        .into()
+    }
+
+    /// Also available while the connection closes, until the PCB is released.
+    pub fn info(&self) -> io::Result<TcpInfo> {
+        self.with_netconn(|conn| conn.with_any_tcp_pcb(|pcb| unsafe { TcpInfo::from_pcb(pcb) }))
+    }
 }
@@ -0,0 +1,150 @@
+use std::os::raw::c_int;
+use std::time::Duration;
+
+#[cfg(feature = "test-util")]
+use futures::future::{self, AbortHandle};
+#[cfg(feature = "test-util")]
+use parking_lot::{const_mutex, Mutex};
+
+use crate::lwip;
+#[cfg(feature = "test-util")]
+use crate::Stack;
+
+extern "C" {
+    fn sys_virtual_clock_enable(enable: c_int);
+    fn sys_virtual_clock_advance(ms: u32);
+    fn sys_now() -> u32;
+}
+
+// expired timers fire in order and interleave with packets in flight
+const STEP: Duration = Duration::from_millis(10);
+
+/// Clock read by the lwIP timers.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Clock {
+    System,
+    /// Only moves forward through `advance_clock`, or with tokio's clock
+    /// once paused.
+    Virtual,
+}
+
+/// Switches the clock of the lwIP timers, starting the stack if needed.
+pub fn set_clock(clock: Clock) {
+    crate::stack::stack_init();
+    unsafe { sys_virtual_clock_enable((clock == Clock::Virtual) as c_int) };
+}
+
+/// lwIP time, in milliseconds.
+pub fn now() -> u32 {
+    unsafe { sys_now() }
+}
+
+fn step(d: Duration) {
+    unsafe { sys_virtual_clock_advance(d.as_millis() as u32) };
+    lwip::with_core_lock(|| unsafe { lwip::sys_check_timeouts() });
+}
+
+/// Moves the virtual clock forward and runs the expired lwIP timeouts, no
+/// effect with the system clock.
+pub fn advance_clock(d: Duration) {
+    let mut left = d;
+    while left > Duration::from_millis(0) {
+        let d = left.min(STEP);
+        step(d);
+        left -= d;
+    }
+}
+
+#[cfg(feature = "test-util")]
+struct Paused {
+    // tokio and lwIP time when paused
+    start: (tokio::time::Instant, u32),
+    driver: AbortHandle,
+}
+
+#[cfg(feature = "test-util")]
+static PAUSED: Mutex<Option<Paused>> = const_mutex(None);
+
+/// Pauses tokio's clock and has it drive the lwIP timers, from a
+/// `basic_scheduler` runtime.
+///
+/// The lwIP timeouts then fire as tokio's clock moves, with
+/// `tokio::time::advance` or when tokio skips ahead to the next timer of an
+/// idle runtime.
+#[cfg(feature = "test-util")]
+pub fn pause() {
+    tokio::time::pause();
+    set_clock(Clock::Virtual);
+
+    let (driver, handle) = future::abortable(drive());
+    let paused = Paused {
+        start: (tokio::time::Instant::now(), now()),
+        driver: handle,
+    };
+    if let Some(previous) = PAUSED.lock().replace(paused) {
+        previous.driver.abort();
+    }
+    tokio::spawn(driver);
+}
+
+/// Resumes both clocks.
+#[cfg(feature = "test-util")]
+pub fn resume() {
+    if let Some(paused) = PAUSED.lock().take() {
+        paused.driver.abort();
+    }
+    tokio::time::resume();
+    set_clock(Clock::System);
+}
+
+/// Advances tokio's clock as `tokio::time::advance` does, a step at a time
+/// so that the packets sent by the expired lwIP timers are handled in
+/// between.
+#[cfg(feature = "test-util")]
+pub async fn advance(d: Duration) {
+    let mut left = d;
+    while left > Duration::from_millis(0) {
+        let d = left.min(STEP);
+        tokio::time::advance(d).await;
+        sync();
+        tokio::task::yield_now().await;
+        left -= d;
+    }
+}
+
+// brings the lwIP clock to tokio's and runs the expired timeouts
+#[cfg(feature = "test-util")]
+fn sync() {
+    if !Stack::is_running() {
+        return;
+    }
+
+    let target = match PAUSED.lock().as_ref() {
+        Some(paused) => {
+            let (instant, ms) = paused.start;
+            ms.wrapping_add((tokio::time::Instant::now() - instant).as_millis() as u32)
+        }
+        None => return,
+    };
+    // unless moved further with advance_clock
+    let behind = target.wrapping_sub(now()) as i32;
+    if behind > 0 {
+        advance_clock(Duration::from_millis(behind as u64));
+    }
+}
+
+// follows tokio's clock, waking up for the next lwIP timeout
+#[cfg(feature = "test-util")]
+async fn drive() {
+    loop {
+        sync();
+
+        let next = if Stack::is_running() {
+            lwip::with_core_lock(|| unsafe { lwip::sys_timeouts_sleeptime() })
+        } else {
+            u32::max_value()
+        };
+        let next = Duration::from_millis(next as u64).min(STEP);
+        tokio::time::delay_for(next.max(Duration::from_millis(1))).await;
+    }
+}
@@ -0,0 +1,286 @@
+use std::convert::TryFrom;
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+
+use futures::future::poll_fn;
+use tokio::net::ToSocketAddrs;
+
+use crate::lwip;
+use crate::Netconn;
+
+const UDP_FLAGS_MULTICAST_LOOP: u8 = 0x08; /* TODO: bindgen skips casted defines */
+const SOF_BROADCAST: u8 = 0x20; /* TODO: bindgen skips casted defines */
+
+/// A multicast group joined by a socket, and the interface it was joined
+/// on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Membership {
+    V4(Ipv4Addr, Ipv4Addr),
+    V6(Ipv6Addr, u32),
+}
+
+impl Membership {
+    fn join_leave(&self, join: bool) -> io::Result<()> {
+        lwip::with_core_lock(|| unsafe {
+            match *self {
+                Membership::V4(multiaddr, interface) => {
+                    let multiaddr: lwip::ip4_addr_t = multiaddr.into();
+                    let interface: lwip::ip4_addr_t = interface.into();
+                    if join {
+                        lwip::igmp_joingroup(&interface, &multiaddr)
+                    } else {
+                        lwip::igmp_leavegroup(&interface, &multiaddr)
+                    }
+                }
+                Membership::V6(multiaddr, 0) => {
+                    let multiaddr: lwip::ip6_addr_t = multiaddr.into();
+                    let any: lwip::ip6_addr_t = Ipv6Addr::UNSPECIFIED.into();
+                    if join {
+                        lwip::mld6_joingroup(&any, &multiaddr)
+                    } else {
+                        lwip::mld6_leavegroup(&any, &multiaddr)
+                    }
+                }
+                Membership::V6(multiaddr, interface) => {
+                    let netif = match u8::try_from(interface) {
+                        Ok(index) => lwip::netif_get_by_index(index),
+                        Err(_) => std::ptr::null_mut(),
+                    };
+                    if netif.is_null() {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            "no such interface",
+                        ));
+                    }
+
+                    let multiaddr: lwip::ip6_addr_t = multiaddr.into();
+                    if join {
+                        lwip::mld6_joingroup_netif(netif, &multiaddr)
+                    } else {
+                        lwip::mld6_leavegroup_netif(netif, &multiaddr)
+                    }
+                }
+            }
+            .into()
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct UdpSocket {
+    conn: Netconn,
+    groups: Mutex<Vec<Membership>>,
+    generation: usize,
+}
+
+async fn resolve<T: ToSocketAddrs>(addr: T) -> io::Result<SocketAddr> {
+    match addr.to_socket_addrs().await?.next() {
+        Some(addr) => Ok(addr),
+        None => Err(io::Error::new(
+            io::ErrorKind::Other,
+            "unable to resolve host",
+        )),
+    }
+}
+
+impl UdpSocket {
+    /// Binds to `addr`, `[::]` receives both IPv4 and IPv6.
+    pub async fn bind<T: ToSocketAddrs>(addr: T) -> io::Result<Self> {
+        let addr = resolve(addr).await?;
+
+        let conn = Netconn::new_udp();
+        conn.bind_ip_port(addr.ip(), addr.port())?;
+        Ok(UdpSocket {
+            conn,
+            groups: Mutex::new(Vec::new()),
+            generation: crate::stack::generation(),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.conn.local()
+    }
+
+    /// Sets the default destination of `send`, and only receives from it.
+    pub async fn connect<T: ToSocketAddrs>(&self, addr: T) -> io::Result<()> {
+        let addr = resolve(addr).await?;
+
+        self.conn.connect(addr.ip(), addr.port())
+    }
+
+    pub async fn send(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.conn.send(buf)
+    }
+
+    pub async fn send_to<T: ToSocketAddrs>(&mut self, buf: &[u8], target: T) -> io::Result<usize> {
+        let target = resolve(target).await?;
+
+        self.conn.send_to(buf, target)
+    }
+
+    pub async fn recv(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let (len, _) = self.recv_from(buf).await?;
+        Ok(len)
+    }
+
+    pub async fn recv_from(&mut self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        poll_fn(|cx| self.poll_recv_from(cx, buf)).await
+    }
+
+    /// Receives a datagram, truncated to the size of `buf`.
+    pub fn poll_recv_from(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<(usize, SocketAddr)>> {
+        loop {
+            return match self.conn.recv_from() {
+                Ok((data, addr)) => {
+                    let len = data.len().min(buf.len());
+                    buf[..len].copy_from_slice(&data[..len]);
+                    Poll::Ready(Ok((len, addr)))
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    match self.conn.poll_rx(cx) {
+                        Poll::Ready(Ok(_)) => continue, /* received since the first try */
+                        Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+                        _ => Poll::Pending,
+                    }
+                }
+                Err(e) => Poll::Ready(Err(e)),
+            };
+        }
+    }
+
+    /// Joins `multiaddr` on the interface with the address `interface`, or on
+    /// all interfaces if unspecified. Groups are left when the socket is
+    /// dropped.
+    pub fn join_multicast_v4(&self, multiaddr: Ipv4Addr, interface: Ipv4Addr) -> io::Result<()> {
+        self.join(Membership::V4(multiaddr, interface))
+    }
+
+    pub fn leave_multicast_v4(&self, multiaddr: Ipv4Addr, interface: Ipv4Addr) -> io::Result<()> {
+        self.leave(Membership::V4(multiaddr, interface))
+    }
+
+    /// Joins `multiaddr` on the interface with the index `interface` (see
+    /// `NetIf::index`), or on all interfaces if 0.
+    pub fn join_multicast_v6(&self, multiaddr: &Ipv6Addr, interface: u32) -> io::Result<()> {
+        self.join(Membership::V6(*multiaddr, interface))
+    }
+
+    pub fn leave_multicast_v6(&self, multiaddr: &Ipv6Addr, interface: u32) -> io::Result<()> {
+        self.leave(Membership::V6(*multiaddr, interface))
+    }
+
+    fn join(&self, membership: Membership) -> io::Result<()> {
+        let mut groups = self.groups.lock().unwrap();
+
+        if groups.contains(&membership) {
+            return Err(io::ErrorKind::AddrInUse.into());
+        }
+        membership.join_leave(true)?;
+        groups.push(membership);
+        Ok(())
+    }
+
+    fn leave(&self, membership: Membership) -> io::Result<()> {
+        let mut groups = self.groups.lock().unwrap();
+
+        match groups.iter().position(|m| *m == membership) {
+            Some(idx) => {
+                groups.remove(idx);
+                membership.join_leave(false)
+            }
+            None => Err(io::ErrorKind::AddrNotAvailable.into()),
+        }
+    }
+
+    /// Interface multicast is sent from, by address. Unspecified to route
+    /// it like unicast.
+    pub fn set_multicast_if_v4(&self, interface: Ipv4Addr) -> io::Result<()> {
+        self.conn
+            .with_udp_pcb(|pcb| unsafe { (*pcb).mcast_ip4 = interface.into() })
+    }
+
+    /// Interface multicast is sent from, by index. 0 to route it like
+    /// unicast.
+    pub fn set_multicast_if_v6(&self, interface: u32) -> io::Result<()> {
+        let index = u8::try_from(interface)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "no such interface"))?;
+        self.conn
+            .with_udp_pcb(|pcb| unsafe { (*pcb).mcast_ifindex = index })
+    }
+
+    /// Time to live of outgoing multicast, also the hop limit of IPv6
+    /// multicast.
+    pub fn set_multicast_ttl_v4(&self, ttl: u32) -> io::Result<()> {
+        let ttl = u8::try_from(ttl)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid TTL"))?;
+        self.conn
+            .with_udp_pcb(|pcb| unsafe { (*pcb).mcast_ttl = ttl })
+    }
+
+    pub fn multicast_ttl_v4(&self) -> io::Result<u32> {
+        self.conn
+            .with_udp_pcb(|pcb| unsafe { (*pcb).mcast_ttl as u32 })
+    }
+
+    /// Whether outgoing multicast is also delivered to the sending
+    /// interface. Off by default, and shared with `set_multicast_loop_v6`.
+    pub fn set_multicast_loop_v4(&self, on: bool) -> io::Result<()> {
+        self.conn.with_udp_pcb(|pcb| unsafe {
+            if on {
+                (*pcb).flags |= UDP_FLAGS_MULTICAST_LOOP;
+            } else {
+                (*pcb).flags &= !UDP_FLAGS_MULTICAST_LOOP;
+            }
+        })
+    }
+
+    pub fn multicast_loop_v4(&self) -> io::Result<bool> {
+        self.conn
+            .with_udp_pcb(|pcb| unsafe { (*pcb).flags & UDP_FLAGS_MULTICAST_LOOP != 0 })
+    }
+
+    pub fn set_multicast_loop_v6(&self, on: bool) -> io::Result<()> {
+        self.set_multicast_loop_v4(on)
+    }
+
+    pub fn multicast_loop_v6(&self) -> io::Result<bool> {
+        self.multicast_loop_v4()
+    }
+
+    /// Whether sending to broadcast addresses is allowed, off by default.
+    /// The limited broadcast address is sent from the interface with the
+    /// address the socket is bound to.
+    pub fn set_broadcast(&self, on: bool) -> io::Result<()> {
+        self.conn.with_udp_pcb(|pcb| unsafe {
+            if on {
+                (*pcb).so_options |= SOF_BROADCAST;
+            } else {
+                (*pcb).so_options &= !SOF_BROADCAST;
+            }
+        })
+    }
+
+    pub fn broadcast(&self) -> io::Result<bool> {
+        self.conn
+            .with_udp_pcb(|pcb| unsafe { (*pcb).so_options & SOF_BROADCAST != 0 })
+    }
+}
+
+impl Drop for UdpSocket {
+    fn drop(&mut self) {
+        if self.generation != crate::stack::generation() {
+            // the groups went away with the interfaces of their stack
+            return;
+        }
+
+        for membership in self.groups.lock().unwrap().drain(..) {
+            let _ = membership.join_leave(false);
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use std::io::{self, Write};
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::sync::{Arc, Mutex};
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::stream::StreamExt;
+use tokio::task::JoinHandle;
+
+/// In-memory writer that can be inspected while it is in use, such as by a
+/// `Capture`.
+#[derive(Clone, Default)]
+pub struct SharedBuf(pub Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedBuf {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Internet checksum (RFC 1071).
+pub fn checksum(data: &[u8]) -> u16 {
+    let mut sum = data
+        .chunks(2)
+        .map(|c| (c[0] as u32) << 8 | *c.get(1).unwrap_or(&0) as u32)
+        .sum::<u32>();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// Checksum of an ICMPv6 message, with its pseudo-header.
+pub fn icmpv6_checksum(src: Ipv6Addr, dst: Ipv6Addr, icmp: &[u8]) -> u16 {
+    let mut data = Vec::new();
+    data.extend_from_slice(&src.octets());
+    data.extend_from_slice(&dst.octets());
+    data.extend_from_slice(&(icmp.len() as u32).to_be_bytes());
+    data.extend_from_slice(&[0, 0, 0, 58]);
+    data.extend_from_slice(icmp);
+    checksum(&data)
+}
+
+/// ICMPv6 over IPv6 with the hop limit of neighbor discovery, the checksum
+/// filled in.
+pub fn ipv6_icmp(src: Ipv6Addr, dst: Ipv6Addr, mut icmp: Vec<u8>) -> Vec<u8> {
+    icmp[2..4].copy_from_slice(&[0, 0]);
+    let sum = icmpv6_checksum(src, dst, &icmp);
+    icmp[2..4].copy_from_slice(&sum.to_be_bytes());
+
+    let mut pkt = vec![0x60, 0, 0, 0];
+    pkt.extend_from_slice(&(icmp.len() as u16).to_be_bytes());
+    pkt.extend_from_slice(&[58, 255]);
+    pkt.extend_from_slice(&src.octets());
+    pkt.extend_from_slice(&dst.octets());
+    pkt.extend_from_slice(&icmp);
+    pkt
+}
+
+/// UDP over IPv4, the UDP checksum left out.
+pub fn ipv4_udp(
+    src: Ipv4Addr,
+    dst: Ipv4Addr,
+    src_port: u16,
+    dst_port: u16,
+    payload: &[u8],
+) -> Vec<u8> {
+    let len = 20 + 8 + payload.len();
+    let mut pkt = vec![0x45, 0];
+    pkt.extend_from_slice(&(len as u16).to_be_bytes());
+    pkt.extend_from_slice(&[0, 0, 0, 0, 64, 17, 0, 0]);
+    pkt.extend_from_slice(&src.octets());
+    pkt.extend_from_slice(&dst.octets());
+    let sum = checksum(&pkt);
+    pkt[10..12].copy_from_slice(&sum.to_be_bytes());
+
+    pkt.extend_from_slice(&src_port.to_be_bytes());
+    pkt.extend_from_slice(&dst_port.to_be_bytes());
+    pkt.extend_from_slice(&(8 + payload.len() as u16).to_be_bytes());
+    pkt.extend_from_slice(&[0, 0]);
+    pkt.extend_from_slice(payload);
+    pkt
+}
+
+pub const ETHERTYPE_IPV4: u16 = 0x0800;
+pub const ETHERTYPE_ARP: u16 = 0x0806;
+
+/// Wraps `payload` in an Ethernet header.
+pub fn ethernet_frame(dst: [u8; 6], src: [u8; 6], ethertype: u16, payload: &[u8]) -> Vec<u8> {
+    let mut frame = dst.to_vec();
+    frame.extend_from_slice(&src);
+    frame.extend_from_slice(&ethertype.to_be_bytes());
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Answers an ARP request for `addr` with `hwaddr`, as an Ethernet frame.
+pub fn arp_reply(frame: &[u8], addr: Ipv4Addr, hwaddr: [u8; 6]) -> Option<Vec<u8>> {
+    let arp = frame.get(14..42)?;
+    if frame[12..14] != ETHERTYPE_ARP.to_be_bytes() || arp[6..8] != [0, 1] {
+        return None;
+    }
+    if arp[24..28] != addr.octets() {
+        return None;
+    }
+
+    let mut reply = arp[..6].to_vec();
+    reply.extend_from_slice(&[0, 2]);
+    reply.extend_from_slice(&hwaddr);
+    reply.extend_from_slice(&addr.octets());
+    reply.extend_from_slice(&arp[8..18]);
+    let mut dst = [0; 6];
+    dst.copy_from_slice(&arp[8..14]);
+    Some(ethernet_frame(dst, hwaddr, ETHERTYPE_ARP, &reply))
+}
+
+/// Drives 10.0.`subnet`.1/24 on `dev0` and 10.0.`subnet`.2/24 on `dev1`,
+/// usually the two ends of a `Link`.
+pub fn link_pair<D0, D1>(subnet: u8, dev0: D0, dev1: D1)
+where
+    D0: AsyncRead + AsyncWrite + Send + 'static,
+    D1: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let dev0 = lwip::DeviceBuilder::default()
+        .ipv4(Ipv4Addr::new(10, 0, subnet, 1), 24)
+        .build(dev0)
+        .unwrap();
+    let dev1 = lwip::DeviceBuilder::default()
+        .ipv4(Ipv4Addr::new(10, 0, subnet, 2), 24)
+        .build(dev1)
+        .unwrap();
+    tokio::spawn(dev0.drive());
+    tokio::spawn(dev1.drive());
+}
+
+/// Reads until the end of the stream, returns the number of bytes read.
+pub async fn read_all(conn: &mut lwip::TcpStream) -> usize {
+    let mut buf = vec![0; 4096];
+    let mut total = 0;
+    loop {
+        let n = conn.read(&mut buf).await.unwrap();
+        if n == 0 {
+            return total;
+        }
+        total += n;
+    }
+}
+
+/// Accepts one connection on `listener` and reads all of it.
+pub fn sink(mut listener: lwip::TcpListener) -> JoinHandle<usize> {
+    tokio::spawn(async move {
+        let mut conn = listener.next().await.unwrap().unwrap();
+        read_all(&mut conn).await
+    })
+}
+
+/// Writes `len` bytes then shuts the connection down.
+pub async fn send_all(conn: &mut lwip::TcpStream, len: usize) {
+    let data = vec![0x42; len];
+    let mut sent = 0;
+    while sent < data.len() {
+        sent += conn.write(&data[sent..]).await.unwrap();
+    }
+    conn.shutdown().await.unwrap();
+}
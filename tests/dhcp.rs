@@ -0,0 +1,261 @@
+#[macro_use]
+extern crate rusty_fork;
+
+use std::net::Ipv4Addr;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::runtime;
+use tokio::stream::StreamExt;
+
+use lwip::dev::{Link, LinkEnd};
+use lwip::{NetIfEvent, NetIfWatch};
+
+#[allow(dead_code)]
+mod common;
+
+const HWADDR: [u8; 6] = [0x02, 0, 0, 0, 0, 0x01];
+const SERVER_HWADDR: [u8; 6] = [0x02, 0, 0, 0, 0, 0xfe];
+const BROADCAST_HWADDR: [u8; 6] = [0xff; 6];
+const SERVER: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+const OFFERED: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 2);
+
+const DISCOVER: u8 = 1;
+const OFFER: u8 = 2;
+const REQUEST: u8 = 3;
+const ACK: u8 = 5;
+
+// what the server leaves unanswered
+const IGNORE_NONE: u8 = 0;
+// requests sent to it to renew the lease
+const IGNORE_RENEW: u8 = 1;
+// requests to renew or rebind the lease, it expires
+const IGNORE_LEASE: u8 = 2;
+
+rusty_fork_test! {
+#[test]
+fn dhcp_lease_renew_rebind() {
+    let mut rt = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_time()
+        .build()
+        .unwrap();
+    rt.block_on(dhcp_lease_renew_rebind_async());
+}
+
+#[test]
+fn dhcp_lease_expiry() {
+    let mut rt = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_time()
+        .build()
+        .unwrap();
+    rt.block_on(dhcp_lease_expiry_async());
+}
+}
+
+fn message_type(msg: &[u8]) -> Option<u8> {
+    let mut opts = &msg[240..];
+    while opts.len() >= 2 && opts[0] != 255 {
+        if opts[0] == 0 {
+            opts = &opts[1..];
+            continue;
+        }
+        let len = opts[1] as usize;
+        if opts[0] == 53 {
+            return opts.get(2).cloned();
+        }
+        opts = &opts[(2 + len).min(opts.len())..];
+    }
+    None
+}
+
+fn reply(request: &[u8], msg_type: u8) -> Vec<u8> {
+    let mut msg = vec![0u8; 240];
+    msg[0] = 2; // BOOTREPLY
+    msg[1] = 1;
+    msg[2] = 6;
+    msg[4..8].copy_from_slice(&request[4..8]); // xid
+    msg[16..20].copy_from_slice(&OFFERED.octets());
+    msg[20..24].copy_from_slice(&SERVER.octets());
+    msg[28..44].copy_from_slice(&request[28..44]); // chaddr
+    msg[236..240].copy_from_slice(&[99, 130, 83, 99]);
+
+    msg.extend_from_slice(&[53, 1, msg_type]);
+    msg.extend_from_slice(&[54, 4]);
+    msg.extend_from_slice(&SERVER.octets());
+    msg.extend_from_slice(&[1, 4, 255, 255, 255, 0]);
+    msg.extend_from_slice(&[3, 4]);
+    msg.extend_from_slice(&SERVER.octets());
+    msg.extend_from_slice(&[6, 8, 10, 0, 0, 53, 10, 0, 0, 54]);
+    msg.extend_from_slice(&[51, 4]);
+    msg.extend_from_slice(&600u32.to_be_bytes());
+    msg.extend_from_slice(&[58, 4]);
+    msg.extend_from_slice(&120u32.to_be_bytes());
+    msg.extend_from_slice(&[59, 4]);
+    msg.extend_from_slice(&240u32.to_be_bytes());
+    msg.push(255);
+    msg
+}
+
+/// Minimal DHCP server on Ethernet: offers OFFERED and acknowledges every
+/// request, except those `ignore` tells to leave unanswered.
+async fn server(mut link: LinkEnd, ignore: Arc<AtomicU8>) {
+    let mut buf = vec![0u8; 2048];
+
+    loop {
+        let len = link.read(&mut buf).await.unwrap();
+        let frame = &buf[..len];
+        if let Some(reply) = common::arp_reply(frame, SERVER, SERVER_HWADDR) {
+            link.write_all(&reply).await.unwrap();
+            continue;
+        }
+        if frame.len() < 14 || frame[12..14] != common::ETHERTYPE_IPV4.to_be_bytes() {
+            continue;
+        }
+        let pkt = &frame[14..];
+        let ihl = (pkt[0] & 0x0f) as usize * 4;
+        if pkt[9] != 17 || pkt[ihl + 2..ihl + 4] != [0, 67] {
+            continue;
+        }
+        let msg = &pkt[ihl + 8..];
+        let unicast = pkt[16..20] == SERVER.octets();
+        // only set to renew or rebind a lease
+        let ciaddr = msg[12..16] != [0; 4];
+
+        let answer = match (message_type(msg), ignore.load(Ordering::SeqCst)) {
+            (Some(DISCOVER), _) => OFFER,
+            (Some(REQUEST), IGNORE_RENEW) if unicast => continue,
+            (Some(REQUEST), IGNORE_LEASE) if ciaddr => continue,
+            (Some(REQUEST), _) => ACK,
+            _ => continue,
+        };
+        let reply = common::ipv4_udp(SERVER, Ipv4Addr::BROADCAST, 67, 68, &reply(msg, answer));
+        link.write_all(&common::ethernet_frame(
+            BROADCAST_HWADDR,
+            SERVER_HWADDR,
+            common::ETHERTYPE_IPV4,
+            &reply,
+        ))
+        .await
+        .unwrap();
+    }
+}
+
+async fn next_dhcp_event(events: &mut NetIfWatch) -> NetIfEvent {
+    loop {
+        match events.next().await.unwrap() {
+            e @ NetIfEvent::DhcpBound(_)
+            | e @ NetIfEvent::DhcpRenewing
+            | e @ NetIfEvent::DhcpRebinding
+            | e @ NetIfEvent::DhcpLost => return e,
+            _ => {}
+        }
+    }
+}
+
+async fn dhcp_lease_renew_rebind_async() {
+    lwip::time::pause();
+
+    let (dev, link) = Link::new();
+    let dev = lwip::DeviceBuilder::default()
+        .hwaddr(HWADDR)
+        .ethernet()
+        .dhcp()
+        .build(dev)
+        .unwrap();
+    let netif = dev.netif_as_ref().clone();
+    let mut events = netif.watch();
+    assert_eq!(netif.hwaddr(), HWADDR);
+    assert!(netif.is_ethernet());
+
+    let ignore = Arc::new(AtomicU8::new(IGNORE_NONE));
+    tokio::spawn(dev.drive());
+    tokio::spawn(server(link, ignore.clone()));
+
+    let lease = match next_dhcp_event(&mut events).await {
+        NetIfEvent::DhcpBound(lease) => lease,
+        e => panic!("unexpected event {:?}", e),
+    };
+    assert_eq!(
+        lease.addr,
+        "10.0.0.2/24".parse::<ipnetwork::Ipv4Network>().unwrap()
+    );
+    assert_eq!(lease.router, Some(SERVER));
+    assert_eq!(
+        lease.dns,
+        vec![Ipv4Addr::new(10, 0, 0, 53), Ipv4Addr::new(10, 0, 0, 54)]
+    );
+    assert_eq!(lease.lease_time, Duration::from_secs(600));
+    assert_eq!(lease.renew_time, Duration::from_secs(120));
+    assert_eq!(lease.rebind_time, Duration::from_secs(240));
+    assert_eq!(netif.ipv4(), lease.addr);
+    assert_eq!(netif.dhcp_lease(), Some(lease.clone()));
+
+    // the server ignores the renewal, the client falls back to rebinding
+    // and gets the lease again from a broadcast request.
+    ignore.store(IGNORE_RENEW, Ordering::SeqCst);
+    lwip::time::advance(Duration::from_secs(250)).await;
+
+    assert_eq!(next_dhcp_event(&mut events).await, NetIfEvent::DhcpRenewing);
+    assert_eq!(
+        next_dhcp_event(&mut events).await,
+        NetIfEvent::DhcpRebinding
+    );
+    assert_eq!(
+        next_dhcp_event(&mut events).await,
+        NetIfEvent::DhcpBound(lease.clone())
+    );
+    assert_eq!(netif.ipv4(), lease.addr);
+
+    netif.stop_dhcp();
+    assert_eq!(netif.dhcp_lease(), None);
+    assert!(netif.ipv4().ip().is_unspecified());
+}
+
+async fn dhcp_lease_expiry_async() {
+    lwip::time::pause();
+
+    let (dev, link) = Link::new();
+    let dev = lwip::DeviceBuilder::default()
+        .hwaddr(HWADDR)
+        .ethernet()
+        .dhcp()
+        .build(dev)
+        .unwrap();
+    let netif = dev.netif_as_ref().clone();
+    let mut events = netif.watch();
+
+    let ignore = Arc::new(AtomicU8::new(IGNORE_LEASE));
+    tokio::spawn(dev.drive());
+    tokio::spawn(server(link, ignore.clone()));
+
+    let lease = match next_dhcp_event(&mut events).await {
+        NetIfEvent::DhcpBound(lease) => lease,
+        e => panic!("unexpected event {:?}", e),
+    };
+
+    // neither the renewal nor the rebinding are answered: the lease expires
+    // and the client starts over with a discover.
+    lwip::time::advance(Duration::from_secs(660)).await;
+
+    assert_eq!(next_dhcp_event(&mut events).await, NetIfEvent::DhcpRenewing);
+    assert_eq!(
+        next_dhcp_event(&mut events).await,
+        NetIfEvent::DhcpRebinding
+    );
+    assert_eq!(next_dhcp_event(&mut events).await, NetIfEvent::DhcpLost);
+    assert_eq!(
+        next_dhcp_event(&mut events).await,
+        NetIfEvent::DhcpBound(lease.clone())
+    );
+    assert_eq!(netif.ipv4(), lease.addr);
+    assert_eq!(netif.dhcp_lease(), Some(lease));
+
+    // an explicit stop gives the lease up as well
+    netif.stop_dhcp();
+    assert_eq!(next_dhcp_event(&mut events).await, NetIfEvent::DhcpLost);
+    assert_eq!(netif.dhcp_lease(), None);
+}
@@ -0,0 +1,344 @@
+#[macro_use]
+extern crate rusty_fork;
+
+use std::net::Ipv4Addr;
+use std::time::{Duration, SystemTime};
+
+use ipnetwork::Ipv4Network;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::runtime;
+use tokio::stream::StreamExt;
+use tokio::time::{delay_for, timeout};
+
+use lwip::dev::{Link, LinkEnd};
+use lwip::{DhcpLease, DhcpServer, DhcpServerBuilder, LeaseRecord, LeaseStore, MemoryLeaseStore};
+use lwip::{NetIf, NetIfEvent, NetIfWatch};
+
+#[allow(dead_code)]
+mod common;
+
+const SERVER: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+const CLIENT: [u8; 6] = [0x02, 0, 0, 0, 0, 0x01];
+const OTHER: [u8; 6] = [0x02, 0, 0, 0, 0, 0x02];
+
+const DISCOVER: u8 = 1;
+const OFFER: u8 = 2;
+const REQUEST: u8 = 3;
+const ACK: u8 = 5;
+const NAK: u8 = 6;
+
+rusty_fork_test! {
+#[test]
+fn dhcp_server_pool() {
+    let mut rt = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_time()
+        .build()
+        .unwrap();
+    rt.block_on(async { timeout(Duration::from_secs(30), dhcp_server_pool_async()).await })
+        .unwrap();
+}
+
+#[test]
+fn dhcp_server_static_lease() {
+    let mut rt = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_time()
+        .build()
+        .unwrap();
+    rt.block_on(async { timeout(Duration::from_secs(30), dhcp_server_static_lease_async()).await })
+        .unwrap();
+}
+
+#[test]
+fn dhcp_server_offer() {
+    let mut rt = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_time()
+        .build()
+        .unwrap();
+    rt.block_on(async { timeout(Duration::from_secs(30), dhcp_server_offer_async()).await })
+        .unwrap();
+}
+
+#[test]
+fn dhcp_server_lease_expiry() {
+    let mut rt = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_time()
+        .build()
+        .unwrap();
+    rt.block_on(dhcp_server_lease_expiry_async());
+}
+
+#[test]
+fn dhcp_server_store_panic() {
+    let mut rt = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_time()
+        .build()
+        .unwrap();
+    rt.block_on(async { timeout(Duration::from_secs(30), dhcp_server_store_panic_async()).await })
+        .unwrap();
+}
+}
+
+/// Panics on the first save.
+#[derive(Default)]
+struct PanickingStore {
+    panicked: bool,
+    inner: MemoryLeaseStore,
+}
+
+impl LeaseStore for PanickingStore {
+    fn load(&mut self) -> std::io::Result<Vec<LeaseRecord>> {
+        self.inner.load()
+    }
+
+    fn save(&mut self, lease: &LeaseRecord) -> std::io::Result<()> {
+        if !self.panicked {
+            self.panicked = true;
+            panic!("save");
+        }
+        self.inner.save(lease)
+    }
+
+    fn remove(&mut self, lease: &LeaseRecord) -> std::io::Result<()> {
+        self.inner.remove(lease)
+    }
+}
+
+/// Links a DHCP client interface to a server interface, and serves it.
+fn setup(builder: DhcpServerBuilder) -> (DhcpServer, NetIf, NetIfWatch) {
+    let (dev0, dev1) = Link::new();
+
+    let server = lwip::DeviceBuilder::default()
+        .ipv4(SERVER, 24)
+        .ethernet()
+        .build(dev0)
+        .unwrap();
+    let dhcp = builder.bind(server.netif_as_ref()).unwrap();
+
+    let client = lwip::DeviceBuilder::default()
+        .hwaddr(CLIENT)
+        .ethernet()
+        .dhcp()
+        .build(dev1)
+        .unwrap();
+    let netif = client.netif_as_ref().clone();
+    let events = netif.watch();
+
+    tokio::spawn(server.drive());
+    tokio::spawn(client.drive());
+
+    (dhcp, netif, events)
+}
+
+/// Serves a client driven by hand through the returned end of the link.
+fn setup_manual(builder: DhcpServerBuilder) -> (DhcpServer, LinkEnd) {
+    let (dev, link) = Link::new();
+
+    let server = lwip::DeviceBuilder::default()
+        .ipv4(SERVER, 24)
+        .ethernet()
+        .build(dev)
+        .unwrap();
+    let dhcp = builder.bind(server.netif_as_ref()).unwrap();
+    tokio::spawn(server.drive());
+
+    (dhcp, link)
+}
+
+/// A broadcast client message, requesting `addr` from the server if any.
+fn client_message(hwaddr: [u8; 6], msg_type: u8, addr: Option<Ipv4Addr>) -> Vec<u8> {
+    let mut msg = vec![0u8; 240];
+    msg[0] = 1; // BOOTREQUEST
+    msg[1] = 1;
+    msg[2] = 6;
+    msg[7] = hwaddr[5]; // xid
+    msg[28..34].copy_from_slice(&hwaddr);
+    msg[236..240].copy_from_slice(&[99, 130, 83, 99]);
+
+    msg.extend_from_slice(&[53, 1, msg_type]);
+    if let Some(addr) = addr {
+        msg.extend_from_slice(&[50, 4]);
+        msg.extend_from_slice(&addr.octets());
+        msg.extend_from_slice(&[54, 4]);
+        msg.extend_from_slice(&SERVER.octets());
+    }
+    msg.push(255);
+
+    let pkt = common::ipv4_udp(Ipv4Addr::UNSPECIFIED, Ipv4Addr::BROADCAST, 68, 67, &msg);
+    common::ethernet_frame([0xff; 6], hwaddr, common::ETHERTYPE_IPV4, &pkt)
+}
+
+/// Sends a client message, returns the type of the reply and the address
+/// in it.
+async fn exchange(
+    link: &mut LinkEnd,
+    hwaddr: [u8; 6],
+    msg_type: u8,
+    addr: Option<Ipv4Addr>,
+) -> (u8, Ipv4Addr) {
+    link.write_all(&client_message(hwaddr, msg_type, addr))
+        .await
+        .unwrap();
+
+    let mut buf = vec![0u8; 2048];
+    loop {
+        let len = link.read(&mut buf).await.unwrap();
+        let frame = &buf[..len];
+        if frame.len() < 14 || frame[12..14] != common::ETHERTYPE_IPV4.to_be_bytes() {
+            continue;
+        }
+        let pkt = &frame[14..];
+        let ihl = (pkt[0] & 0x0f) as usize * 4;
+        if pkt[9] != 17 || pkt[ihl + 2..ihl + 4] != [0, 68] {
+            continue;
+        }
+        // the message type is the first option of the replies
+        let msg = &pkt[ihl + 8..];
+        assert_eq!(msg[240..242], [53, 1]);
+        let yiaddr = Ipv4Addr::new(msg[16], msg[17], msg[18], msg[19]);
+        return (msg[242], yiaddr);
+    }
+}
+
+async fn next_lease(events: &mut NetIfWatch) -> DhcpLease {
+    loop {
+        if let NetIfEvent::DhcpBound(lease) = events.next().await.unwrap() {
+            return lease;
+        }
+    }
+}
+
+async fn dhcp_server_pool_async() {
+    // a lease saved by a previous run of the server
+    let mut store = MemoryLeaseStore::new();
+    let previous = LeaseRecord {
+        hwaddr: OTHER,
+        addr: Ipv4Addr::new(10, 0, 0, 100),
+        expires: SystemTime::now() + Duration::from_secs(3600),
+    };
+    store.save(&previous).unwrap();
+
+    let (server, netif, mut events) = setup(
+        DhcpServer::builder()
+            .pool(Ipv4Addr::new(10, 0, 0, 100), Ipv4Addr::new(10, 0, 0, 110))
+            .router(SERVER)
+            .dns(Ipv4Addr::new(10, 0, 0, 53))
+            .lease_time(Duration::from_secs(600))
+            .store(store.clone()),
+    );
+
+    let lease = next_lease(&mut events).await;
+    let addr = Ipv4Addr::new(10, 0, 0, 101);
+    assert_eq!(lease.addr, Ipv4Network::new(addr, 24).unwrap());
+    assert_eq!(lease.router, Some(SERVER));
+    assert_eq!(lease.dns, vec![Ipv4Addr::new(10, 0, 0, 53)]);
+    assert_eq!(lease.lease_time, Duration::from_secs(600));
+    assert_eq!(lease.renew_time, Duration::from_secs(300));
+    assert_eq!(lease.rebind_time, Duration::from_secs(525));
+
+    let mut leases = server.leases();
+    leases.sort_by_key(|l| l.addr);
+    assert_eq!(leases.len(), 2);
+    assert_eq!(leases[0], previous);
+    assert_eq!((leases[1].hwaddr, leases[1].addr), (CLIENT, addr));
+    assert_eq!(store.leases().len(), 2);
+
+    // renewal is unicast to the server
+    netif.renew_dhcp().unwrap();
+    assert_eq!(next_lease(&mut events).await, lease);
+
+    // the release frees the address
+    netif.stop_dhcp();
+    while server.leases().len() != 1 {
+        delay_for(Duration::from_millis(10)).await;
+    }
+    assert_eq!(store.leases(), vec![previous]);
+}
+
+async fn dhcp_server_static_lease_async() {
+    let addr = Ipv4Addr::new(10, 0, 0, 50);
+    let (server, netif, mut events) = setup(
+        DhcpServer::builder()
+            .pool(Ipv4Addr::new(10, 0, 0, 100), Ipv4Addr::new(10, 0, 0, 110))
+            .static_lease(CLIENT, addr),
+    );
+
+    let lease = next_lease(&mut events).await;
+    assert_eq!(lease.addr, Ipv4Network::new(addr, 24).unwrap());
+    assert_eq!(lease.router, None);
+    assert!(lease.dns.is_empty());
+    assert_eq!(netif.ipv4(), lease.addr);
+    assert_eq!(server.leases()[0].addr, addr);
+}
+
+async fn dhcp_server_offer_async() {
+    let (server, mut link) = setup_manual(
+        DhcpServer::builder().pool(Ipv4Addr::new(10, 0, 0, 100), Ipv4Addr::new(10, 0, 0, 110)),
+    );
+
+    // an offered address is held for its client, it is not a lease yet
+    let (msg_type, offered) = exchange(&mut link, CLIENT, DISCOVER, None).await;
+    assert_eq!((msg_type, offered), (OFFER, Ipv4Addr::new(10, 0, 0, 100)));
+    let (msg_type, other) = exchange(&mut link, OTHER, DISCOVER, None).await;
+    assert_eq!((msg_type, other), (OFFER, Ipv4Addr::new(10, 0, 0, 101)));
+    assert!(server.leases().is_empty());
+
+    let (msg_type, _) = exchange(&mut link, OTHER, REQUEST, Some(offered)).await;
+    assert_eq!(msg_type, NAK);
+
+    // a discover again gets the same offer
+    let (msg_type, addr) = exchange(&mut link, CLIENT, DISCOVER, None).await;
+    assert_eq!((msg_type, addr), (OFFER, offered));
+
+    let (msg_type, addr) = exchange(&mut link, CLIENT, REQUEST, Some(offered)).await;
+    assert_eq!((msg_type, addr), (ACK, offered));
+    assert_eq!(server.leases().len(), 1);
+}
+
+async fn dhcp_server_lease_expiry_async() {
+    lwip::time::pause();
+
+    let (server, mut link) = setup_manual(
+        DhcpServer::builder()
+            .pool(Ipv4Addr::new(10, 0, 0, 100), Ipv4Addr::new(10, 0, 0, 110))
+            .lease_time(Duration::from_secs(120)),
+    );
+
+    let (_, addr) = exchange(&mut link, CLIENT, DISCOVER, None).await;
+    let (msg_type, _) = exchange(&mut link, CLIENT, REQUEST, Some(addr)).await;
+    assert_eq!(msg_type, ACK);
+    assert_eq!(server.leases().len(), 1);
+
+    // leases expire on the stack clock
+    lwip::time::advance(Duration::from_secs(121)).await;
+    assert!(server.leases().is_empty());
+
+    let (msg_type, other) = exchange(&mut link, OTHER, DISCOVER, None).await;
+    assert_eq!((msg_type, other), (OFFER, addr));
+}
+
+async fn dhcp_server_store_panic_async() {
+    let (server, mut link) = setup_manual(
+        DhcpServer::builder()
+            .pool(Ipv4Addr::new(10, 0, 0, 100), Ipv4Addr::new(10, 0, 0, 110))
+            .store(PanickingStore::default()),
+    );
+
+    let (_, addr) = exchange(&mut link, CLIENT, DISCOVER, None).await;
+    link.write_all(&client_message(CLIENT, REQUEST, Some(addr)))
+        .await
+        .unwrap();
+
+    // the request was dropped, the next reply is the other client's offer
+    let (msg_type, _) = exchange(&mut link, OTHER, DISCOVER, None).await;
+    assert_eq!(msg_type, OFFER);
+    assert!(server.leases().is_empty());
+
+    let (msg_type, acked) = exchange(&mut link, CLIENT, REQUEST, Some(addr)).await;
+    assert_eq!((msg_type, acked), (ACK, addr));
+    assert_eq!(server.leases().len(), 1);
+}
@@ -0,0 +1,67 @@
+// lwIP debug output is only compiled in with the `debug` feature.
+#![cfg(feature = "debug")]
+
+#[macro_use]
+extern crate rusty_fork;
+
+use std::time::Duration;
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use parking_lot::{const_mutex, Mutex};
+use tokio::runtime;
+use tokio::time::timeout;
+
+use lwip::UdpSocket;
+
+static RECORDS: Mutex<Vec<(String, Level, String)>> = const_mutex(Vec::new());
+
+/// Only lwIP's UDP module is enabled.
+struct UdpLogger;
+
+impl Log for UdpLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.target() == "lwip::udp"
+    }
+
+    fn log(&self, record: &Record) {
+        RECORDS.lock().push((
+            record.target().to_string(),
+            record.level(),
+            record.args().to_string(),
+        ));
+    }
+
+    fn flush(&self) {}
+}
+
+rusty_fork_test! {
+#[test]
+fn diag_udp() {
+    log::set_logger(&UdpLogger).unwrap();
+    log::set_max_level(LevelFilter::Trace);
+
+    let mut rt = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_time()
+        .build()
+        .unwrap();
+    rt.block_on(async {
+        timeout(Duration::from_secs(30), UdpSocket::bind("0.0.0.0:5000"))
+            .await
+            .unwrap()
+            .unwrap()
+    });
+
+    let records = RECORDS.lock();
+    assert!(records.iter().all(|(target, _, _)| target == "lwip::udp"));
+
+    // one record per line, even when lwIP prints it in several parts
+    let (_, level, msg) = records
+        .iter()
+        .find(|(_, _, msg)| msg.starts_with("udp_bind(ipaddr = "))
+        .unwrap();
+    assert_eq!(*level, Level::Trace);
+    assert!(msg.ends_with(", port = 5000)"));
+    assert!(!msg.contains('\n'));
+}
+}
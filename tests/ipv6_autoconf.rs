@@ -0,0 +1,176 @@
+#[macro_use]
+extern crate rusty_fork;
+
+use std::net::Ipv6Addr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use ipnetwork::Ipv6Network;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::runtime;
+use tokio::stream::StreamExt;
+
+use lwip::dev::{Link, LinkEnd};
+use lwip::{Ipv6AddrState, Lifetime, NetIfEvent};
+
+#[allow(dead_code)]
+mod common;
+
+const HWADDR: [u8; 6] = [0x02, 0, 0, 0, 0, 0x01];
+
+rusty_fork_test! {
+#[test]
+fn ipv6_slaac() {
+    let mut rt = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_time()
+        .build()
+        .unwrap();
+    rt.block_on(ipv6_slaac_async());
+}
+
+#[test]
+fn ipv6_static_no_solicitation() {
+    let mut rt = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_time()
+        .build()
+        .unwrap();
+    rt.block_on(ipv6_static_no_solicitation_async());
+}
+}
+
+fn router() -> Ipv6Addr {
+    "fe80::1".parse().unwrap()
+}
+
+/// Router advertisement for 2001:db8:1::/64, sent to all nodes.
+fn advertisement() -> Vec<u8> {
+    let dst: Ipv6Addr = "ff02::1".parse().unwrap();
+
+    // type, code, checksum, hop limit, flags, router lifetime,
+    // reachable time, retrans timer
+    let mut icmp = vec![134, 0, 0, 0, 64, 0];
+    icmp.extend_from_slice(&1800u16.to_be_bytes());
+    icmp.extend_from_slice(&[0; 8]);
+    // prefix information: on-link and autonomous
+    icmp.extend_from_slice(&[3, 4, 64, 0xc0]);
+    icmp.extend_from_slice(&3600u32.to_be_bytes());
+    icmp.extend_from_slice(&1800u32.to_be_bytes());
+    icmp.extend_from_slice(&[0; 4]);
+    icmp.extend_from_slice(&"2001:db8:1::".parse::<Ipv6Addr>().unwrap().octets());
+    common::ipv6_icmp(router(), dst, icmp)
+}
+
+/// Answers router solicitations.
+async fn serve_router(mut link: LinkEnd) {
+    let mut buf = vec![0u8; 2048];
+
+    loop {
+        let len = link.read(&mut buf).await.unwrap();
+        if is_solicitation(&buf[..len]) {
+            link.write_all(&advertisement()).await.unwrap();
+        }
+    }
+}
+
+fn is_solicitation(pkt: &[u8]) -> bool {
+    pkt.len() > 40 && pkt[0] >> 4 == 6 && pkt[6] == 58 && pkt[40] == 133
+}
+
+/// Counts the router solicitations sent on the link.
+async fn count_solicitations(mut link: LinkEnd, count: Arc<AtomicUsize>) {
+    let mut buf = vec![0u8; 2048];
+
+    loop {
+        let len = link.read(&mut buf).await.unwrap();
+        if is_solicitation(&buf[..len]) {
+            count.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+}
+
+async fn ipv6_slaac_async() {
+    lwip::time::pause();
+
+    let (dev, link) = Link::new();
+    let dev = lwip::DeviceBuilder::default()
+        .hwaddr(HWADDR)
+        .ipv6_autoconfig()
+        .build(dev)
+        .unwrap();
+    let netif = dev.netif_as_ref().clone();
+    let mut events = netif.watch();
+
+    tokio::spawn(dev.drive());
+    tokio::spawn(serve_router(link));
+
+    // link-local from the MAC address, then the router solicitation
+    // (every 4s) and the SLAAC address DAD.
+    lwip::time::advance(Duration::from_secs(12)).await;
+
+    let link_local: Ipv6Addr = "fe80::ff:fe00:1".parse().unwrap();
+    let slaac: Ipv6Addr = "2001:db8:1::ff:fe00:1".parse().unwrap();
+
+    let mut dad = Vec::new();
+    while dad.len() < 2 {
+        if let NetIfEvent::DadFinished { addr, duplicate } = events.next().await.unwrap() {
+            assert!(!duplicate);
+            dad.push(addr);
+        }
+    }
+    assert_eq!(dad, vec![link_local, slaac]);
+
+    let autoconf = netif.ipv6_autoconf().unwrap();
+
+    let ll = autoconf.link_local.unwrap();
+    assert_eq!(ll.addr, link_local);
+    assert_eq!(ll.state, Ipv6AddrState::Preferred);
+
+    assert_eq!(autoconf.addrs.len(), 1);
+    assert_eq!(autoconf.addrs[0].addr, slaac);
+    assert_eq!(autoconf.addrs[0].state, Ipv6AddrState::Preferred);
+    assert!(match autoconf.addrs[0].valid_life {
+        Lifetime::Finite(d) => d > Duration::from_secs(3500) && d <= Duration::from_secs(3600),
+        _ => false,
+    });
+
+    assert_eq!(autoconf.prefixes.len(), 1);
+    assert_eq!(
+        autoconf.prefixes[0].prefix,
+        "2001:db8:1::/64".parse::<Ipv6Network>().unwrap()
+    );
+
+    assert_eq!(autoconf.routers.len(), 1);
+    assert_eq!(autoconf.routers[0].addr, router());
+    assert!(autoconf.routers[0].lifetime > Duration::from_secs(1700));
+
+    // both show up among the interface addresses
+    let addrs: Vec<_> = netif.list_addrs().iter().map(|a| a.ip()).collect();
+    assert!(addrs.contains(&link_local.into()));
+    assert!(addrs.contains(&slaac.into()));
+}
+
+async fn ipv6_static_no_solicitation_async() {
+    lwip::time::pause();
+
+    let (dev, link) = Link::new();
+    let dev = lwip::DeviceBuilder::default()
+        .hwaddr(HWADDR)
+        .ipv6("2001:db8:1::2".parse().unwrap(), 64)
+        .build(dev)
+        .unwrap();
+    let netif = dev.netif_as_ref().clone();
+
+    let solicitations = Arc::new(AtomicUsize::new(0));
+    tokio::spawn(dev.drive());
+    tokio::spawn(count_solicitations(link, solicitations.clone()));
+
+    // lwIP arms the solicitations again whenever the interface comes up
+    netif.set_down().unwrap();
+    netif.set_up().unwrap();
+    lwip::time::advance(Duration::from_secs(12)).await;
+
+    assert_eq!(solicitations.load(Ordering::SeqCst), 0);
+}
@@ -0,0 +1,143 @@
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::runtime;
+use tokio::time::{timeout, Instant};
+
+use lwip::dev::Link;
+
+fn run<F: std::future::Future>(f: F) -> F::Output {
+    let mut rt = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_time()
+        .build()
+        .unwrap();
+    rt.block_on(f)
+}
+
+#[test]
+fn link_perfect() {
+    run(async {
+        let (mut a, mut b) = Link::new();
+
+        a.write(b"ping").await.unwrap();
+        b.write(b"pong").await.unwrap();
+
+        let mut buf = vec![0; 4];
+        assert_eq!(b.read(&mut buf).await.unwrap(), 4);
+        assert_eq!(buf, b"ping");
+        assert_eq!(a.read(&mut buf).await.unwrap(), 4);
+        assert_eq!(buf, b"pong");
+    });
+}
+
+#[test]
+fn link_latency() {
+    run(async {
+        let (mut a, mut b) = Link::builder().latency(Duration::from_millis(50)).build();
+
+        let start = Instant::now();
+        a.write(b"ping").await.unwrap();
+
+        let mut buf = vec![0; 4];
+        b.read(&mut buf).await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    });
+}
+
+#[test]
+fn link_loss_and_mtu() {
+    run(async {
+        let (mut a, mut b) = Link::builder().loss(1.0).build();
+        a.write(b"ping").await.unwrap();
+
+        let mut buf = vec![0; 4];
+        assert!(timeout(Duration::from_millis(50), b.read(&mut buf))
+            .await
+            .is_err());
+        assert_eq!(a.stats().lost, 1);
+
+        let (mut a, mut b) = Link::builder().mtu(3).build();
+        a.write(b"ping").await.unwrap();
+        assert!(timeout(Duration::from_millis(50), b.read(&mut buf))
+            .await
+            .is_err());
+        assert_eq!(a.stats().oversized, 1);
+    });
+}
+
+#[test]
+fn link_duplicate() {
+    run(async {
+        let (mut a, mut b) = Link::builder().duplicate(1.0).build();
+        a.write(b"ping").await.unwrap();
+
+        let mut buf = vec![0; 4];
+        b.read(&mut buf).await.unwrap();
+        b.read(&mut buf).await.unwrap();
+        assert_eq!(buf, b"ping");
+        assert_eq!(a.stats().duplicated, 1);
+    });
+}
+
+#[test]
+fn link_reorder() {
+    run(async {
+        // reordered packets skip the latency and overtake the ones in flight.
+        let (mut a, mut b) = Link::builder()
+            .latency(Duration::from_millis(20))
+            .reorder(0.5)
+            .seed(42)
+            .build();
+
+        let sent: Vec<u8> = (0..20).collect();
+        for i in &sent {
+            a.write(&[*i]).await.unwrap();
+        }
+
+        let mut received = Vec::new();
+        let mut buf = vec![0; 1];
+        for _ in &sent {
+            b.read(&mut buf).await.unwrap();
+            received.push(buf[0]);
+        }
+
+        let stats = a.stats();
+        assert!(stats.reordered > 0);
+        assert_eq!(stats.delivered, 20);
+        assert_ne!(received, sent);
+        received.sort();
+        assert_eq!(received, sent);
+    });
+}
+
+#[test]
+fn link_rate() {
+    run(async {
+        // 1000 bytes at 80 kbit/s take 100 ms.
+        let (mut a, mut b) = Link::builder().rate(80_000).build();
+
+        let start = Instant::now();
+        a.write(&[0; 500]).await.unwrap();
+        a.write(&[0; 500]).await.unwrap();
+
+        let mut buf = vec![0; 500];
+        b.read(&mut buf).await.unwrap();
+        b.read(&mut buf).await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    });
+}
+
+#[test]
+fn link_rate_unlimited() {
+    run(async {
+        // a rate of 0 does not limit the link.
+        let (mut a, mut b) = Link::builder().rate(0).build();
+
+        a.write(&[0; 500]).await.unwrap();
+
+        let mut buf = vec![0; 500];
+        let read = timeout(Duration::from_secs(1), b.read(&mut buf)).await;
+        assert_eq!(read.unwrap().unwrap(), 500);
+    });
+}
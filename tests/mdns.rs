@@ -0,0 +1,514 @@
+#[macro_use]
+extern crate rusty_fork;
+
+use std::net::Ipv4Addr;
+
+use ipnetwork::Ipv4Network;
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::runtime;
+use tokio::time::timeout;
+
+use lwip::dev::{Link, LinkEnd};
+use lwip::{MdnsResponder, MdnsService};
+
+#[allow(dead_code)]
+mod common;
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_AAAA: u16 = 28;
+const TYPE_SRV: u16 = 33;
+
+const CLASS_CACHE_FLUSH: u16 = 0x8000;
+
+const GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+const RESPONDER: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+const PEER: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 2);
+
+rusty_fork_test! {
+#[test]
+fn mdns_query() {
+    let mut rt = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_time()
+        .build()
+        .unwrap();
+    rt.block_on(async { timeout(Duration::from_secs(30), mdns_query_async()).await })
+        .unwrap();
+}
+
+#[test]
+fn mdns_announce() {
+    let mut rt = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_time()
+        .build()
+        .unwrap();
+    rt.block_on(async { timeout(Duration::from_secs(30), mdns_announce_async()).await })
+        .unwrap();
+}
+
+#[test]
+fn mdns_probe_conflict() {
+    let mut rt = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_time()
+        .build()
+        .unwrap();
+    rt.block_on(async { timeout(Duration::from_secs(30), mdns_probe_conflict_async()).await })
+        .unwrap();
+}
+
+#[test]
+fn mdns_addr_change() {
+    let mut rt = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_time()
+        .build()
+        .unwrap();
+    rt.block_on(async { timeout(Duration::from_secs(30), mdns_addr_change_async()).await })
+        .unwrap();
+}
+}
+
+fn encode_name(name: &str, buf: &mut Vec<u8>) {
+    for label in name.split('.') {
+        buf.push(label.len() as u8);
+        buf.extend_from_slice(label.as_bytes());
+    }
+    buf.push(0);
+}
+
+/// UDP datagram from the peer to the mDNS port.
+fn datagram(dst: Ipv4Addr, src_port: u16, payload: &[u8]) -> Vec<u8> {
+    common::ipv4_udp(PEER, dst, src_port, 5353, payload)
+}
+
+/// A response from the peer with the A record of `name`.
+fn a_response(name: &str, addr: Ipv4Addr) -> Vec<u8> {
+    let mut buf = vec![0, 0, 0x84, 0, 0, 0, 0, 1, 0, 0, 0, 0];
+    encode_name(name, &mut buf);
+    buf.extend_from_slice(&TYPE_A.to_be_bytes());
+    buf.extend_from_slice(&(1 | CLASS_CACHE_FLUSH).to_be_bytes());
+    buf.extend_from_slice(&120u32.to_be_bytes());
+    buf.extend_from_slice(&4u16.to_be_bytes());
+    buf.extend_from_slice(&addr.octets());
+    buf
+}
+
+fn query(id: u16, name: &str, qtype: u16) -> Vec<u8> {
+    let mut buf = id.to_be_bytes().to_vec();
+    buf.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, 0, 0, 0]);
+    encode_name(name, &mut buf);
+    buf.extend_from_slice(&qtype.to_be_bytes());
+    buf.extend_from_slice(&[0, 1]);
+    buf
+}
+
+#[derive(Debug)]
+struct Record {
+    name: String,
+    rtype: u16,
+    class: u16,
+    ttl: u32,
+    data: Vec<u8>,
+}
+
+#[derive(Debug)]
+struct Response {
+    src: Ipv4Addr,
+    dst: Ipv4Addr,
+    id: u16,
+    questions: usize,
+    answers: Vec<Record>,
+    additional: Vec<Record>,
+}
+
+impl Response {
+    fn answer(&self, rtype: u16) -> &Record {
+        self.answers.iter().find(|r| r.rtype == rtype).unwrap()
+    }
+
+    fn additional(&self, rtype: u16) -> &Record {
+        self.additional.iter().find(|r| r.rtype == rtype).unwrap()
+    }
+}
+
+/// The responder does not compress names.
+fn parse_name(buf: &[u8], pos: &mut usize) -> String {
+    let mut labels = Vec::new();
+    loop {
+        let len = buf[*pos] as usize;
+        *pos += 1;
+        if len == 0 {
+            return labels.join(".");
+        }
+        labels.push(String::from_utf8(buf[*pos..*pos + len].to_vec()).unwrap());
+        *pos += len;
+    }
+}
+
+fn parse_record(buf: &[u8], pos: &mut usize) -> Record {
+    let word = |pos: usize| u16::from_be_bytes([buf[pos], buf[pos + 1]]);
+    let name = parse_name(buf, pos);
+    let len = word(*pos + 8) as usize;
+    let record = Record {
+        name,
+        rtype: word(*pos),
+        class: word(*pos + 2),
+        ttl: u32::from_be_bytes([buf[*pos + 4], buf[*pos + 5], buf[*pos + 6], buf[*pos + 7]]),
+        data: buf[*pos + 10..*pos + 10 + len].to_vec(),
+    };
+    *pos += 10 + len;
+    record
+}
+
+fn parse_response(src: Ipv4Addr, dst: Ipv4Addr, buf: &[u8]) -> Response {
+    let word = |pos: usize| u16::from_be_bytes([buf[pos], buf[pos + 1]]);
+    assert_eq!(word(2) & 0x8000, 0x8000);
+
+    let mut pos = 12;
+    for _ in 0..word(4) {
+        parse_name(buf, &mut pos);
+        pos += 4;
+    }
+
+    let mut records = Vec::new();
+    for _ in 0..word(6) as usize + word(10) as usize {
+        records.push(parse_record(buf, &mut pos));
+    }
+    let additional = records.split_off(word(6) as usize);
+
+    Response {
+        src,
+        dst,
+        id: word(0),
+        questions: word(4) as usize,
+        answers: records,
+        additional,
+    }
+}
+
+#[derive(Debug)]
+struct Probe {
+    name: String,
+    qtype: u16,
+    class: u16,
+    authority: Vec<Record>,
+}
+
+fn parse_probe(buf: &[u8]) -> Probe {
+    let word = |pos: usize| u16::from_be_bytes([buf[pos], buf[pos + 1]]);
+    assert_eq!(word(4), 1);
+    assert_eq!(word(6), 0);
+
+    let mut pos = 12;
+    let name = parse_name(buf, &mut pos);
+    let qtype = word(pos);
+    let class = word(pos + 2);
+    pos += 4;
+
+    let authority = (0..word(8)).map(|_| parse_record(buf, &mut pos)).collect();
+    Probe {
+        name,
+        qtype,
+        class,
+        authority,
+    }
+}
+
+/// Next mDNS response from the responder to `port`, over IPv4.
+async fn next_response(link: &mut LinkEnd, port: u16) -> Response {
+    loop {
+        let (src, dst, msg) = next_message(link, port).await;
+        if msg[2] & 0x80 != 0 {
+            return parse_response(src, dst, &msg);
+        }
+    }
+}
+
+/// Next probe from the responder, over IPv4.
+async fn next_probe(link: &mut LinkEnd) -> Probe {
+    loop {
+        let (_, dst, msg) = next_message(link, 5353).await;
+        if msg[2] & 0x80 == 0 {
+            assert_eq!(dst, GROUP);
+            return parse_probe(&msg);
+        }
+    }
+}
+
+/// Next mDNS message from the responder to `port`, over IPv4.
+async fn next_message(link: &mut LinkEnd, port: u16) -> (Ipv4Addr, Ipv4Addr, Vec<u8>) {
+    let mut buf = vec![0u8; 2048];
+
+    loop {
+        let len = link.read(&mut buf).await.unwrap();
+        let pkt = &buf[..len];
+        if len <= 28 || pkt[0] >> 4 != 4 || pkt[9] != 17 {
+            continue;
+        }
+
+        let udp = &pkt[(pkt[0] & 0xf) as usize * 4..];
+        if u16::from_be_bytes([udp[0], udp[1]]) != 5353
+            || u16::from_be_bytes([udp[2], udp[3]]) != port
+        {
+            continue;
+        }
+
+        let src = Ipv4Addr::new(pkt[12], pkt[13], pkt[14], pkt[15]);
+        let dst = Ipv4Addr::new(pkt[16], pkt[17], pkt[18], pkt[19]);
+        assert_eq!(pkt[8], 255);
+        return (src, dst, udp[8..].to_vec());
+    }
+}
+
+fn setup() -> (lwip::NetDevice<lwip::DeviceWrapper<LinkEnd>>, LinkEnd) {
+    let (dev, link) = Link::new();
+    let dev = lwip::DeviceBuilder::default()
+        .ipv4(RESPONDER, 24)
+        .ipv6("fd00::1".parse().unwrap(), 64)
+        .build(dev)
+        .unwrap();
+    (dev, link)
+}
+
+fn service() -> MdnsService {
+    MdnsService::new("Web", "_http._tcp", 80).txt("path=/")
+}
+
+async fn mdns_query_async() {
+    let (dev, mut link) = setup();
+    let responder = MdnsResponder::builder()
+        .hostname("appliance")
+        .service(service())
+        .bind(dev.netif_as_ref())
+        .unwrap();
+    assert_eq!(responder.hostname(), "appliance.local");
+    tokio::spawn(dev.drive());
+
+    // the announcements, 1s apart
+    for _ in 0..2 {
+        assert_eq!(next_response(&mut link, 5353).await.src, RESPONDER);
+    }
+
+    // a plain resolver gets a unicast answer to its port
+    let pkt = datagram(RESPONDER, 40000, &query(7, "appliance.local", TYPE_A));
+    link.write_all(&pkt).await.unwrap();
+    let response = next_response(&mut link, 40000).await;
+    assert_eq!(response.src, RESPONDER);
+    assert_eq!(response.dst, PEER);
+    assert_eq!(response.id, 7);
+    assert_eq!(response.questions, 1);
+    let a = response.answer(TYPE_A);
+    assert_eq!(a.name, "appliance.local");
+    assert_eq!(a.data, RESPONDER.octets());
+    assert_eq!(a.class, 1);
+    assert!(a.ttl <= 10);
+    assert!(response.answers.iter().all(|r| r.rtype == TYPE_A));
+
+    let pkt = datagram(RESPONDER, 40000, &query(8, "APPLIANCE.local", TYPE_AAAA));
+    link.write_all(&pkt).await.unwrap();
+    let response = next_response(&mut link, 40000).await;
+    assert!(response
+        .answers
+        .iter()
+        .any(|r| r.rtype == TYPE_AAAA && r.data[..2] == [0xfd, 0]));
+
+    // browsing the service type, answered on the group
+    let pkt = datagram(GROUP, 5353, &query(0, "_http._tcp.local", TYPE_PTR));
+    link.write_all(&pkt).await.unwrap();
+    let response = next_response(&mut link, 5353).await;
+    assert_eq!(response.dst, GROUP);
+    assert_eq!(response.id, 0);
+    assert_eq!(response.questions, 0);
+
+    let ptr = response.answer(TYPE_PTR);
+    assert_eq!(ptr.name, "_http._tcp.local");
+    assert_eq!(ptr.class, 1);
+    assert_eq!(ptr.ttl, 4500);
+    assert_eq!(parse_name(&ptr.data, &mut 0), "Web._http._tcp.local");
+
+    let srv = response.additional(TYPE_SRV);
+    assert_eq!(srv.name, "Web._http._tcp.local");
+    assert_eq!(srv.class, 1 | CLASS_CACHE_FLUSH);
+    assert_eq!(srv.data[4..6], 80u16.to_be_bytes());
+    assert_eq!(parse_name(&srv.data, &mut 6), "appliance.local");
+    assert_eq!(response.additional(TYPE_TXT).data, b"\x06path=/");
+    assert_eq!(response.additional(TYPE_A).data, RESPONDER.octets());
+
+    // service types
+    let pkt = datagram(
+        GROUP,
+        5353,
+        &query(0, "_services._dns-sd._udp.local", TYPE_PTR),
+    );
+    link.write_all(&pkt).await.unwrap();
+    let response = next_response(&mut link, 5353).await;
+    assert_eq!(response.answers.len(), 1);
+    assert_eq!(
+        parse_name(&response.answer(TYPE_PTR).data, &mut 0),
+        "_http._tcp.local"
+    );
+
+    // names of others are not answered
+    let pkt = datagram(RESPONDER, 40000, &query(9, "other.local", TYPE_A));
+    link.write_all(&pkt).await.unwrap();
+    let pkt = datagram(
+        RESPONDER,
+        40000,
+        &query(10, "Web._http._tcp.local", TYPE_TXT),
+    );
+    link.write_all(&pkt).await.unwrap();
+    let response = next_response(&mut link, 40000).await;
+    assert_eq!(response.id, 10);
+    assert_eq!(response.answer(TYPE_TXT).data, b"\x06path=/");
+}
+
+async fn mdns_announce_async() {
+    let (dev, mut link) = setup();
+    let responder = MdnsResponder::builder()
+        .hostname("appliance.local")
+        .bind(dev.netif_as_ref())
+        .unwrap();
+    tokio::spawn(dev.drive());
+
+    // probed for three times, 250ms apart, the first asking for unicast
+    // responses
+    for idx in 0..3 {
+        let probe = next_probe(&mut link).await;
+        assert_eq!(probe.name, "appliance.local");
+        assert_eq!(probe.qtype, 255);
+        assert_eq!(probe.class, if idx == 0 { 1 | 0x8000 } else { 1 });
+        let a = probe.authority.iter().find(|r| r.rtype == TYPE_A).unwrap();
+        assert_eq!(a.name, "appliance.local");
+        assert_eq!(a.class, 1);
+        assert_eq!(a.data, RESPONDER.octets());
+    }
+
+    // then announced twice
+    for _ in 0..2 {
+        let response = next_response(&mut link, 5353).await;
+        assert_eq!(response.src, RESPONDER);
+        assert_eq!(response.dst, GROUP);
+        let a = response.answer(TYPE_A);
+        assert_eq!(a.data, RESPONDER.octets());
+        assert_eq!(a.class, 1 | CLASS_CACHE_FLUSH);
+        assert_eq!(a.ttl, 120);
+    }
+
+    responder.add_service(service()).unwrap();
+    assert!(responder.add_service(service()).is_err());
+    let response = next_response(&mut link, 5353).await;
+    assert_eq!(response.answer(TYPE_PTR).name, "_http._tcp.local");
+    assert_eq!(response.answer(TYPE_SRV).ttl, 120);
+
+    responder.remove_service("Web", "_http._tcp").unwrap();
+    assert!(responder.remove_service("Web", "_http._tcp").is_err());
+    let response = next_response(&mut link, 5353).await;
+    assert!(response.answers.iter().all(|r| r.ttl == 0));
+    assert!(response
+        .answers
+        .iter()
+        .any(|r| r.rtype == TYPE_PTR && r.name == "_services._dns-sd._udp.local"));
+
+    // goodbye
+    drop(responder);
+    let response = next_response(&mut link, 5353).await;
+    assert_eq!(response.answer(TYPE_A).ttl, 0);
+}
+
+async fn mdns_probe_conflict_async() {
+    let (dev, mut link) = setup();
+    let responder = MdnsResponder::builder()
+        .hostname("appliance")
+        .bind(dev.netif_as_ref())
+        .unwrap();
+    tokio::spawn(dev.drive());
+
+    // the peer answers the first probe, it owns the name
+    assert_eq!(next_probe(&mut link).await.name, "appliance.local");
+    let pkt = datagram(GROUP, 5353, &a_response("appliance.local", PEER));
+    link.write_all(&pkt).await.unwrap();
+
+    let mut probes = 0;
+    loop {
+        let probe = next_probe(&mut link).await;
+        if probe.name == "appliance.local" {
+            // sent before the answer came in
+            continue;
+        }
+        assert_eq!(probe.name, "appliance-2.local");
+        probes += 1;
+        if probes == 3 {
+            break;
+        }
+    }
+    assert_eq!(responder.hostname(), "appliance-2.local");
+
+    let response = next_response(&mut link, 5353).await;
+    let a = response.answer(TYPE_A);
+    assert_eq!(a.name, "appliance-2.local");
+    assert_eq!(a.data, RESPONDER.octets());
+
+    // the old name is left to the peer
+    let pkt = datagram(RESPONDER, 40000, &query(1, "appliance.local", TYPE_A));
+    link.write_all(&pkt).await.unwrap();
+    let pkt = datagram(RESPONDER, 40000, &query(2, "appliance-2.local", TYPE_A));
+    link.write_all(&pkt).await.unwrap();
+    let response = next_response(&mut link, 40000).await;
+    assert_eq!(response.id, 2);
+
+    // a response with our own address is no conflict
+    let pkt = datagram(GROUP, 5353, &a_response("appliance-2.local", RESPONDER));
+    link.write_all(&pkt).await.unwrap();
+    let pkt = datagram(RESPONDER, 40000, &query(3, "appliance-2.local", TYPE_A));
+    link.write_all(&pkt).await.unwrap();
+    let response = next_response(&mut link, 40000).await;
+    assert_eq!(response.id, 3);
+    assert_eq!(responder.hostname(), "appliance-2.local");
+}
+
+async fn mdns_addr_change_async() {
+    let (dev, mut link) = setup();
+    let netif = dev.netif_as_ref().clone();
+    let _responder = MdnsResponder::builder()
+        .hostname("appliance")
+        .bind(&netif)
+        .unwrap();
+    tokio::spawn(dev.drive());
+
+    for _ in 0..2 {
+        next_response(&mut link, 5353).await;
+    }
+
+    // the old address is withdrawn and the new one announced twice
+    let addr = Ipv4Addr::new(10, 0, 0, 3);
+    netif.set_ipv4(Ipv4Network::new(addr, 24).unwrap()).unwrap();
+
+    let response = next_response(&mut link, 5353).await;
+    assert_eq!(response.answers.len(), 1);
+    let a = response.answer(TYPE_A);
+    assert_eq!(a.data, RESPONDER.octets());
+    assert_eq!(a.ttl, 0);
+
+    for _ in 0..2 {
+        let response = next_response(&mut link, 5353).await;
+        assert_eq!(response.src, addr);
+        let a = response.answer(TYPE_A);
+        assert_eq!(a.data, addr.octets());
+        assert_eq!(a.ttl, 120);
+    }
+
+    // removed: only a goodbye
+    netif
+        .set_ipv4(Ipv4Network::new(Ipv4Addr::UNSPECIFIED, 0).unwrap())
+        .unwrap();
+    let response = next_response(&mut link, 5353).await;
+    let a = response.answer(TYPE_A);
+    assert_eq!(a.data, addr.octets());
+    assert_eq!(a.ttl, 0);
+}
@@ -0,0 +1,123 @@
+#[macro_use]
+extern crate rusty_fork;
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::Duration;
+
+use futures::StreamExt;
+use ipnetwork::Ipv4Network;
+use tokio_test::block_on;
+
+use lwip::{Ipv6AddrInfo, Ipv6AddrState, Lifetime, NetIfAddr, NetIfEvent};
+
+rusty_fork_test! {
+#[test]
+fn netif_addrs() {
+    let dev = lwip::DeviceBuilder::loopback().unwrap();
+    let netif = dev.netif_as_ref();
+
+    let addrs = netif.list_addrs();
+    assert_eq!(
+        addrs,
+        vec![
+            NetIfAddr::V4(Ipv4Network::new(Ipv4Addr::LOCALHOST, 8).unwrap()),
+            NetIfAddr::V6(Ipv6AddrInfo::new(Ipv6Addr::LOCALHOST)),
+        ]
+    );
+
+    let addr: Ipv6Addr = "2001:db8::1".parse().unwrap();
+    netif
+        .add_addr(
+            Ipv6AddrInfo::new(addr)
+                .state(Ipv6AddrState::Deprecated)
+                .valid_life(Lifetime::Finite(Duration::from_secs(3600)))
+                .preferred_life(Lifetime::Finite(Duration::from_secs(60))),
+        )
+        .unwrap();
+
+    let info = netif
+        .list_addrs()
+        .into_iter()
+        .find(|a| a.ip() == IpAddr::V6(addr))
+        .unwrap();
+    assert_eq!(
+        info,
+        NetIfAddr::V6(Ipv6AddrInfo {
+            addr,
+            state: Ipv6AddrState::Deprecated,
+            valid_life: Lifetime::Finite(Duration::from_secs(3600)),
+            preferred_life: Lifetime::Finite(Duration::from_secs(60)),
+        })
+    );
+
+    netif.remove_addr(IpAddr::V6(addr)).unwrap();
+    assert!(netif.remove_addr(IpAddr::V6(addr)).is_err());
+
+    netif
+        .set_ipv4(Ipv4Network::new(Ipv4Addr::new(10, 0, 0, 1), 24).unwrap())
+        .unwrap();
+    assert_eq!(
+        netif.ipv4(),
+        Ipv4Network::new(Ipv4Addr::new(10, 0, 0, 1), 24).unwrap()
+    );
+}
+}
+
+rusty_fork_test! {
+#[test]
+fn netif_addrs_full() {
+    let dev = lwip::DeviceBuilder::loopback().unwrap();
+    let netif = dev.netif_as_ref();
+
+    // ::1 is already configured, fill the remaining slots.
+    let mut n = 1;
+    while netif
+        .add_addr(format!("2001:db8::{}", n).parse::<Ipv6Addr>().unwrap())
+        .is_ok()
+    {
+        n += 1;
+    }
+
+    let v6 = netif
+        .list_addrs()
+        .into_iter()
+        .filter(|a| a.ip().is_ipv6())
+        .count();
+    assert_eq!(v6, n);
+}
+}
+
+rusty_fork_test! {
+#[test]
+fn netif_events() {
+    let dev = lwip::DeviceBuilder::loopback().unwrap();
+    let netif = dev.netif_as_ref();
+
+    let mut events = netif.watch();
+
+    assert!(netif.is_up());
+    assert!(netif.is_link_up());
+
+    netif.set_link_down().unwrap();
+    netif.set_down().unwrap();
+    assert!(!netif.is_up());
+    assert!(!netif.is_link_up());
+
+    netif.set_up().unwrap();
+    netif.set_link_up().unwrap();
+
+    let addr: Ipv6Addr = "2001:db8::1".parse().unwrap();
+    netif.add_addr(addr).unwrap();
+    netif.remove_addr(IpAddr::V6(addr)).unwrap();
+
+    block_on(async {
+        assert_eq!(events.next().await, Some(NetIfEvent::LinkDown));
+        assert_eq!(events.next().await, Some(NetIfEvent::Down));
+        assert_eq!(events.next().await, Some(NetIfEvent::Up));
+        assert_eq!(events.next().await, Some(NetIfEvent::LinkUp));
+        assert_eq!(events.next().await, Some(NetIfEvent::AddrAdded(IpAddr::V6(addr))));
+        // no DAD ran, none is reported
+        assert_eq!(events.next().await, Some(NetIfEvent::AddrRemoved(IpAddr::V6(addr))));
+    });
+}
+}
@@ -0,0 +1,71 @@
+#[macro_use]
+extern crate rusty_fork;
+
+use std::time::Duration;
+
+use tokio::io::AsyncWriteExt;
+use tokio::runtime;
+use tokio::stream::StreamExt;
+use tokio::time::{delay_for, timeout};
+
+use lwip::tcp::{connections, Protocol};
+use lwip::TcpState;
+
+rusty_fork_test! {
+#[test]
+fn netstat() {
+    let mut rt = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_time()
+        .build()
+        .unwrap();
+    rt.block_on(async { timeout(Duration::from_secs(30), netstat_async()).await })
+        .unwrap();
+}
+}
+
+async fn netstat_async() {
+    let dev = lwip::DeviceBuilder::loopback().unwrap();
+    let _raw = lwip::RawSocket::bind_proto(lwip::Proto::Icmp, &dev).unwrap();
+    tokio::spawn(dev.drive());
+
+    let mut listener = lwip::TcpListener::bind("0.0.0.0:1234").await.unwrap();
+    let mut conn = lwip::TcpStream::connect("127.0.0.1:1234").await.unwrap();
+    let accepted = listener.next().await.unwrap().unwrap();
+    let local = conn.local().unwrap();
+
+    let list = connections();
+    let tcp = |state| {
+        list.iter()
+            .filter(|c| c.proto == Protocol::Tcp && c.state == Some(state))
+            .count()
+    };
+
+    assert_eq!(tcp(TcpState::Listen), 1);
+    // both ends of the connection
+    assert_eq!(tcp(TcpState::Established), 2);
+
+    let client = list.iter().find(|c| c.local == local).unwrap();
+    assert_eq!(client.remote, Some("127.0.0.1:1234".parse().unwrap()));
+    assert_eq!(client.send_queue, 0);
+
+    assert!(list.iter().any(|c| match c.proto {
+        Protocol::Raw(_) => c.netif.is_some(),
+        _ => false,
+    }));
+
+    // a smaller receive buffer does not count as queued data
+    accepted.set_recv_buffer_size(1024).unwrap();
+    conn.write_all(b"hello").await.unwrap();
+    let queued = || {
+        connections()
+            .into_iter()
+            .find(|c| c.remote == Some(local))
+            .unwrap()
+            .recv_queue
+    };
+    while queued() == 0 {
+        delay_for(Duration::from_millis(10)).await;
+    }
+    assert_eq!(queued(), 5);
+}
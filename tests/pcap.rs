@@ -0,0 +1,128 @@
+use std::io;
+use std::time::{Duration, UNIX_EPOCH};
+
+use byteorder::{ByteOrder, NativeEndian};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_test::{block_on, io::Builder};
+
+use lwip::{Capture, Direction, PcapFormat, PcapReader, PcapWriter};
+
+#[allow(dead_code)]
+mod common;
+use common::SharedBuf;
+
+#[test]
+fn pcap_writer() {
+    let ts = UNIX_EPOCH + Duration::from_micros(1_500_000);
+    let mut w = PcapWriter::new(Vec::new(), PcapFormat::Pcap).unwrap();
+    w.write_packet(ts, Direction::Inbound, b"hello").unwrap();
+
+    let buf = w.into_inner();
+    assert_eq!(buf.len(), 24 + 16 + 5);
+    assert_eq!(NativeEndian::read_u32(&buf[0..4]), 0xa1b2c3d4);
+    assert_eq!(NativeEndian::read_u32(&buf[20..24]), 101); // LINKTYPE_RAW
+    assert_eq!(NativeEndian::read_u32(&buf[24..28]), 1);
+    assert_eq!(NativeEndian::read_u32(&buf[28..32]), 500_000);
+    assert_eq!(NativeEndian::read_u32(&buf[32..36]), 5);
+    assert_eq!(&buf[40..], b"hello");
+}
+
+#[test]
+fn pcapng_writer() {
+    let ts = UNIX_EPOCH + Duration::from_micros(1_500_000);
+    let mut w = PcapWriter::new(Vec::new(), PcapFormat::PcapNg).unwrap();
+    w.write_packet(ts, Direction::Outbound, b"hello").unwrap();
+
+    let buf = w.into_inner();
+    assert_eq!(NativeEndian::read_u32(&buf[0..4]), 0x0a0d0d0a);
+    assert_eq!(NativeEndian::read_u32(&buf[28..32]), 1); // IDB
+    assert_eq!(NativeEndian::read_u16(&buf[36..38]), 101);
+
+    let epb = &buf[48..];
+    assert_eq!(NativeEndian::read_u32(&epb[0..4]), 6);
+    let len = NativeEndian::read_u32(&epb[4..8]) as usize;
+    assert_eq!(len, epb.len());
+    assert_eq!(len, 44 + 8);
+    assert_eq!(NativeEndian::read_u32(&epb[16..20]), 1_500_000);
+    assert_eq!(&epb[28..33], b"hello");
+    assert_eq!(NativeEndian::read_u16(&epb[36..38]), 2); // epb_flags
+    assert_eq!(NativeEndian::read_u32(&epb[40..44]), 0x2); // outbound
+}
+
+#[test]
+fn pcap_reader_invalid_lengths() {
+    let mut w = PcapWriter::new(Vec::new(), PcapFormat::Pcap).unwrap();
+    w.write_packet(UNIX_EPOCH, Direction::Inbound, b"hello")
+        .unwrap();
+    let pcap = w.into_inner();
+
+    // caplen above the snaplen of the file
+    let mut buf = pcap.clone();
+    NativeEndian::write_u32(&mut buf[32..36], 65536);
+    let err = PcapReader::new(&buf[..]).unwrap().next().unwrap();
+    assert_eq!(err.unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+    // caplen above what the reader allocates
+    let mut buf = pcap;
+    NativeEndian::write_u32(&mut buf[16..20], 0);
+    NativeEndian::write_u32(&mut buf[32..36], 0xffff_ffff);
+    let err = PcapReader::new(&buf[..]).unwrap().next().unwrap();
+    assert_eq!(err.unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+    let mut w = PcapWriter::new(Vec::new(), PcapFormat::PcapNg).unwrap();
+    w.write_packet(UNIX_EPOCH, Direction::Inbound, b"hello")
+        .unwrap();
+    let pcapng = w.into_inner();
+
+    // block lengths too short for the block header and trailer
+    for &len in &[0, 4, 8] {
+        let mut buf = pcapng.clone();
+        NativeEndian::write_u32(&mut buf[52..56], len);
+        let err = PcapReader::new(&buf[..]).unwrap().next().unwrap();
+        assert_eq!(err.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    let mut buf = pcapng.clone();
+    NativeEndian::write_u32(&mut buf[52..56], 0xffff_fffc);
+    let err = PcapReader::new(&buf[..]).unwrap().next().unwrap();
+    assert_eq!(err.unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+    // caplen above the snaplen of the interface
+    let mut buf = pcapng;
+    NativeEndian::write_u32(&mut buf[48 + 20..48 + 24], 65536);
+    let err = PcapReader::new(&buf[..]).unwrap().next().unwrap();
+    assert_eq!(err.unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+    let mut buf = vec![0; 4];
+    NativeEndian::write_u32(&mut buf, 0x0a0d0d0a);
+    buf.extend_from_slice(&[0; 4]);
+    NativeEndian::write_u32(&mut buf[4..8], 8);
+    buf.extend_from_slice(&[0x4d, 0x3c, 0x2b, 0x1a]);
+    let err = PcapReader::new(&buf[..]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn capture_device() {
+    let buf = SharedBuf::default();
+    let mock = Builder::new().read(b"ping").write(b"pong").build();
+    let mut dev = Capture::new(mock, buf.clone(), PcapFormat::PcapNg).unwrap();
+
+    block_on(async {
+        let mut pkt = vec![0; 4];
+        assert_eq!(dev.read(&mut pkt).await.unwrap(), 4);
+        assert_eq!(dev.write(b"pong").await.unwrap(), 4);
+    });
+    // waits for the packets to be written
+    drop(dev);
+
+    let buf = buf.0.lock().unwrap();
+    let epb = &buf[48..];
+    assert_eq!(NativeEndian::read_u32(&epb[4..8]), 48);
+    assert_eq!(&epb[28..32], b"ping");
+    assert_eq!(NativeEndian::read_u32(&epb[40..44]), 0x1); // inbound
+
+    let epb = &epb[48..];
+    assert_eq!(&epb[28..32], b"pong");
+    assert_eq!(NativeEndian::read_u32(&epb[40..44]), 0x2); // outbound
+}
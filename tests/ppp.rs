@@ -0,0 +1,452 @@
+#[macro_use]
+extern crate rusty_fork;
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::future::abortable;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+use tokio::runtime;
+use tokio::stream::StreamExt;
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+
+use lwip::dev::{Link, LinkEnd};
+use lwip::{NetIfEvent, NetIfWatch, PppAuth, PppDevice, UdpSocket};
+
+#[allow(dead_code)]
+mod common;
+
+const SERVER: Ipv4Addr = Ipv4Addr::new(10, 64, 0, 1);
+const CLIENT: Ipv4Addr = Ipv4Addr::new(10, 64, 0, 2);
+
+rusty_fork_test! {
+#[test]
+fn ppp_link() {
+    let mut rt = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_all()
+        .build()
+        .unwrap();
+    rt.block_on(async { timeout(Duration::from_secs(30), ppp_link_async()).await })
+        .unwrap();
+}
+
+#[test]
+fn ppp_pap() {
+    let mut rt = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_all()
+        .build()
+        .unwrap();
+    rt.block_on(async { timeout(Duration::from_secs(30), ppp_auth_async(PppAuth::Pap)).await })
+        .unwrap();
+}
+
+#[test]
+fn ppp_chap() {
+    let mut rt = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_all()
+        .build()
+        .unwrap();
+    rt.block_on(async { timeout(Duration::from_secs(30), ppp_auth_async(PppAuth::Chap)).await })
+        .unwrap();
+}
+
+#[test]
+fn ppp_drop_negotiating() {
+    let mut rt = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_all()
+        .build()
+        .unwrap();
+    rt.block_on(async { timeout(Duration::from_secs(30), ppp_drop_negotiating_async()).await })
+        .unwrap();
+}
+
+#[test]
+fn pppoe_link() {
+    let mut rt = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_all()
+        .build()
+        .unwrap();
+    rt.block_on(async { timeout(Duration::from_secs(30), pppoe_link_async()).await })
+        .unwrap();
+}
+}
+
+async fn wait_for(mut events: NetIfWatch, event: NetIfEvent) {
+    while events.next().await.unwrap() != event {}
+}
+
+async fn ppp_link_async() {
+    let (a, b) = UnixStream::pair().unwrap();
+
+    // one end hands out the addresses
+    let server = PppDevice::builder()
+        .local_addr(SERVER)
+        .peer_addr(CLIENT)
+        .build(a)
+        .unwrap();
+    let client = PppDevice::builder().build(b).unwrap();
+
+    let server_if = server.netif_as_ref().clone();
+    let client_if = client.netif_as_ref().clone();
+    assert!(!client_if.is_link_up());
+    let up = wait_for(client_if.watch(), NetIfEvent::LinkUp);
+
+    let (server, abort) = abortable(server.drive());
+    tokio::spawn(server);
+    let client = tokio::spawn(client.drive());
+
+    up.await;
+    assert!(client_if.is_link_up());
+    assert_eq!(client_if.ipv4().ip(), CLIENT);
+    assert_eq!(server_if.ipv4().ip(), SERVER);
+    // IPv6CP only brings up a link-local address
+    assert!(client_if.list_addrs().iter().any(|a| match a.ip() {
+        IpAddr::V6(addr) => addr.segments()[0] == 0xfe80,
+        _ => false,
+    }));
+
+    // traffic to the peer goes over the link
+    let mut echo = UdpSocket::bind((SERVER, 7)).await.unwrap();
+    let mut socket = UdpSocket::bind("0.0.0.0:0").await.unwrap();
+    socket.send_to(b"hello", (SERVER, 7)).await.unwrap();
+    let mut buf = vec![0; 16];
+    let (len, from) = echo.recv_from(&mut buf).await.unwrap();
+    assert_eq!(&buf[..len], b"hello");
+    assert_eq!(from.ip(), IpAddr::V4(CLIENT));
+    // the interfaces count the IP packets, not the HDLC frames
+    assert_eq!(client_if.stats().drops_out, 0);
+    assert!(client_if.stats().packets_out > 0);
+    assert!(client_if.stats().bytes_out >= 33);
+    assert!(server_if.stats().packets_in > 0);
+    assert!(server_if.stats().bytes_in >= 33);
+
+    // the client sees the stream close when the server goes away
+    let down = wait_for(client_if.watch(), NetIfEvent::LinkDown);
+    abort.abort();
+    client.await.unwrap().unwrap();
+    down.await;
+    assert!(!client_if.is_link_up());
+}
+
+/// A link between a client with `client_auth` credentials and a server
+/// requiring `server_auth`, driven until it is up or fails.
+async fn ppp_auth_link(
+    client_auth: (PppAuth, &str, &str),
+    server_auth: (PppAuth, &str, &str),
+) -> io::Result<()> {
+    let (a, b) = UnixStream::pair().unwrap();
+
+    let server = PppDevice::builder()
+        .require_auth(server_auth.0, server_auth.1, server_auth.2)
+        .local_addr(SERVER)
+        .peer_addr(CLIENT)
+        .build(a)
+        .unwrap();
+    let client = PppDevice::builder()
+        .auth(client_auth.0, client_auth.1, client_auth.2)
+        .build(b)
+        .unwrap();
+    let client_if = client.netif_as_ref().clone();
+    let mut events = client_if.watch();
+
+    let (server, abort_server) = abortable(server.drive());
+    tokio::spawn(server);
+    let (client, abort_client) = abortable(client.drive());
+    let client = tokio::spawn(client);
+
+    let ret = tokio::select! {
+        ret = client => ret.unwrap().unwrap(),
+        _ = async { while events.next().await.unwrap() != NetIfEvent::LinkUp {} } => {
+            assert_eq!(client_if.ipv4().ip(), CLIENT);
+            Ok(())
+        }
+    };
+    abort_server.abort();
+    abort_client.abort();
+    ret
+}
+
+async fn ppp_auth_async(auth: PppAuth) {
+    ppp_auth_link((auth, "modem", "secret"), (auth, "modem", "secret"))
+        .await
+        .unwrap();
+    // whichever the server asks for
+    ppp_auth_link((PppAuth::Any, "modem", "secret"), (auth, "modem", "secret"))
+        .await
+        .unwrap();
+
+    let e = ppp_auth_link((auth, "modem", "wrong"), (auth, "modem", "secret"))
+        .await
+        .unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::PermissionDenied);
+    let e = ppp_auth_link((auth, "other", "secret"), (auth, "modem", "secret"))
+        .await
+        .unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::PermissionDenied);
+}
+
+const ETHERTYPE_PPPOE_DISC: u16 = 0x8863;
+const ETHERTYPE_PPPOE: u16 = 0x8864;
+
+const PPPOE_PADI: u8 = 0x09;
+const PPPOE_PADO: u8 = 0x07;
+const PPPOE_PADR: u8 = 0x19;
+const PPPOE_PADS: u8 = 0x65;
+
+const PPPOE_TAG_SNAME: u16 = 0x0101;
+const PPPOE_TAG_ACNAME: u16 = 0x0102;
+const PPPOE_TAG_HUNIQUE: u16 = 0x0103;
+
+const CONCENTRATOR: [u8; 6] = [0x02, 0, 0, 0, 0, 0x01];
+const SESSION: u16 = 0x1234;
+
+async fn ppp_drop_negotiating_async() {
+    lwip::time::set_clock(lwip::time::Clock::Virtual);
+
+    let (a, mut b) = UnixStream::pair().unwrap();
+    let client = PppDevice::builder().build(a).unwrap();
+    let events = client.netif_as_ref().watch();
+    let (drive, abort) = abortable(client.drive());
+    let drive = tokio::spawn(drive);
+
+    // LCP is negotiating, the peer never answers
+    let mut buf = vec![0; 1500];
+    assert!(b.read(&mut buf).await.unwrap() > 0);
+    abort.abort();
+    assert!(drive.await.unwrap().is_err());
+
+    // the PCB terminates the link first, then goes away with its interface
+    lwip::time::advance_clock(Duration::from_secs(10));
+    wait_for(events, NetIfEvent::Removed).await;
+}
+
+fn pppoe(code: u8, session: u16, payload: &[u8]) -> Vec<u8> {
+    let mut pkt = vec![0x11, code];
+    pkt.extend_from_slice(&session.to_be_bytes());
+    pkt.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+    pkt.extend_from_slice(payload);
+    pkt
+}
+
+fn pppoe_tag(tag: u16, value: &[u8], buf: &mut Vec<u8>) {
+    buf.extend_from_slice(&tag.to_be_bytes());
+    buf.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    buf.extend_from_slice(value);
+}
+
+// RFC 1662, appendix C
+fn fcs16(data: &[u8]) -> u16 {
+    let mut fcs = 0xffffu16;
+    for &b in data {
+        fcs ^= b as u16;
+        for _ in 0..8 {
+            fcs = if fcs & 1 != 0 {
+                (fcs >> 1) ^ 0x8408
+            } else {
+                fcs >> 1
+            };
+        }
+    }
+    fcs
+}
+
+/// PPP packet, from its protocol field, as an HDLC frame with all control
+/// characters escaped.
+fn hdlc_encode(pkt: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0xff, 0x03];
+    frame.extend_from_slice(pkt);
+    let fcs = !fcs16(&frame);
+    frame.extend_from_slice(&fcs.to_le_bytes());
+
+    let mut buf = vec![0x7e];
+    for &b in frame.iter() {
+        if b == 0x7e || b == 0x7d || b < 0x20 {
+            buf.push(0x7d);
+            buf.push(b ^ 0x20);
+        } else {
+            buf.push(b);
+        }
+    }
+    buf.push(0x7e);
+    buf
+}
+
+/// PPP packet of an unescaped HDLC frame, from its protocol field.
+fn hdlc_decode(frame: &[u8]) -> Option<Vec<u8>> {
+    if frame.len() < 4 || fcs16(frame) != 0xf0b8 {
+        return None;
+    }
+    let mut pkt = &frame[..frame.len() - 2];
+    if pkt[..2] == [0xff, 0x03] {
+        pkt = &pkt[2..];
+    }
+    let mut pkt = pkt.to_vec();
+    // protocol field compression
+    if pkt[0] & 1 != 0 {
+        pkt.insert(0, 0);
+    }
+    Some(pkt)
+}
+
+/// Access concentrator on `link`, bridging the PPPoE session to an HDLC
+/// `stream`.
+async fn concentrator(link: LinkEnd, stream: UnixStream) {
+    let (mut link_rx, mut link_tx) = tokio::io::split(link);
+    let (mut stream_rx, mut stream_tx) = tokio::io::split(stream);
+    let (tx, mut rx) = mpsc::unbounded_channel::<Vec<u8>>();
+    let client = Arc::new(Mutex::new(None));
+
+    tokio::spawn(async move {
+        while let Some(frame) = rx.recv().await {
+            link_tx.write_all(&frame).await.unwrap();
+        }
+    });
+
+    // from the server
+    let session_tx = tx.clone();
+    let session_client = client.clone();
+    tokio::spawn(async move {
+        let mut buf = vec![0; 2048];
+        let mut frame = Vec::new();
+        let mut escaped = false;
+        loop {
+            let len = match stream_rx.read(&mut buf).await {
+                Ok(0) | Err(_) => return,
+                Ok(len) => len,
+            };
+            for &b in buf[..len].iter() {
+                match b {
+                    0x7e => {
+                        let pkt = hdlc_decode(&frame);
+                        let dst = *session_client.lock().unwrap();
+                        if let (Some(pkt), Some(dst)) = (pkt, dst) {
+                            let pkt = pppoe(0, SESSION, &pkt);
+                            let frame =
+                                common::ethernet_frame(dst, CONCENTRATOR, ETHERTYPE_PPPOE, &pkt);
+                            let _ = session_tx.send(frame);
+                        }
+                        frame.clear();
+                    }
+                    0x7d => escaped = true,
+                    b if escaped => {
+                        frame.push(b ^ 0x20);
+                        escaped = false;
+                    }
+                    b => frame.push(b),
+                }
+            }
+        }
+    });
+
+    let mut buf = vec![0; 2048];
+    loop {
+        let len = link_rx.read(&mut buf).await.unwrap();
+        let frame = &buf[..len];
+        if len < 20 || frame[14] != 0x11 {
+            continue;
+        }
+        let mut src = [0; 6];
+        src.copy_from_slice(&frame[6..12]);
+        let ethertype = u16::from_be_bytes([frame[12], frame[13]]);
+        let session = u16::from_be_bytes([frame[16], frame[17]]);
+        let payload_len = u16::from_be_bytes([frame[18], frame[19]]) as usize;
+        let payload = &frame[20..20 + payload_len];
+
+        if ethertype == ETHERTYPE_PPPOE {
+            if session == SESSION {
+                stream_tx.write_all(&hdlc_encode(payload)).await.unwrap();
+            }
+            continue;
+        }
+        if ethertype != ETHERTYPE_PPPOE_DISC {
+            continue;
+        }
+
+        // the client's Host-Uniq is echoed
+        let mut tags = Vec::new();
+        let mut pos = 0;
+        while pos + 4 <= payload.len() {
+            let tag = u16::from_be_bytes([payload[pos], payload[pos + 1]]);
+            let len = u16::from_be_bytes([payload[pos + 2], payload[pos + 3]]) as usize;
+            if tag == PPPOE_TAG_HUNIQUE {
+                pppoe_tag(tag, &payload[pos + 4..pos + 4 + len], &mut tags);
+            }
+            pos += 4 + len;
+        }
+        pppoe_tag(PPPOE_TAG_SNAME, b"", &mut tags);
+
+        let reply = match frame[15] {
+            PPPOE_PADI => {
+                pppoe_tag(PPPOE_TAG_ACNAME, b"test", &mut tags);
+                pppoe(PPPOE_PADO, 0, &tags)
+            }
+            PPPOE_PADR => {
+                *client.lock().unwrap() = Some(src);
+                pppoe(PPPOE_PADS, SESSION, &tags)
+            }
+            _ => continue,
+        };
+        let reply = common::ethernet_frame(src, CONCENTRATOR, ETHERTYPE_PPPOE_DISC, &reply);
+        tx.send(reply).unwrap();
+    }
+}
+
+async fn pppoe_link_async() {
+    let (dev, link) = Link::new();
+    let dev = lwip::DeviceBuilder::default()
+        .ethernet()
+        .build(dev)
+        .unwrap();
+    let ethernet = dev.netif_as_ref().clone();
+    tokio::spawn(dev.drive());
+
+    // the session ends on a PPP server
+    let (a, b) = UnixStream::pair().unwrap();
+    let server = PppDevice::builder()
+        .local_addr(SERVER)
+        .peer_addr(CLIENT)
+        .build(a)
+        .unwrap();
+    tokio::spawn(server.drive());
+    tokio::spawn(concentrator(link, b));
+
+    let client = PppDevice::builder().build_pppoe(&ethernet).unwrap();
+    let client_if = client.netif_as_ref().clone();
+    let up = wait_for(client_if.watch(), NetIfEvent::LinkUp);
+    let (client, abort) = abortable(client.drive());
+    tokio::spawn(client);
+
+    up.await;
+    assert_eq!(client_if.ipv4().ip(), CLIENT);
+
+    // traffic to the peer goes over the session
+    let mut echo = UdpSocket::bind((SERVER, 7)).await.unwrap();
+    let mut socket = UdpSocket::bind("0.0.0.0:0").await.unwrap();
+    socket.send_to(b"hello", (SERVER, 7)).await.unwrap();
+    let mut buf = vec![0; 16];
+    let (len, from) = echo.recv_from(&mut buf).await.unwrap();
+    assert_eq!(&buf[..len], b"hello");
+    assert_eq!(from.ip(), IpAddr::V4(CLIENT));
+    assert!(client_if.stats().packets_out > 0);
+    assert!(ethernet.stats().packets_out > client_if.stats().packets_out);
+
+    // only Ethernet interfaces carry PPPoE
+    let (dev, _link) = Link::new();
+    let dev = lwip::DeviceBuilder::default().build(dev).unwrap();
+    assert!(PppDevice::builder()
+        .build_pppoe(dev.netif_as_ref())
+        .is_err());
+
+    let down = wait_for(client_if.watch(), NetIfEvent::LinkDown);
+    abort.abort();
+    down.await;
+}
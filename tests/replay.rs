@@ -0,0 +1,161 @@
+#[macro_use]
+extern crate rusty_fork;
+
+use std::net::Ipv4Addr;
+use std::time::{Duration, SystemTime};
+
+use packet::{builder::Builder as PBuilder, ip, tcp};
+use tokio::io::AsyncReadExt;
+use tokio::runtime;
+use tokio::time::{delay_for, timeout};
+
+use lwip::{Direction, PcapDevice, PcapFormat, PcapWriter, Timing};
+
+fn capture(pkts: &[(Direction, Vec<u8>)]) -> Vec<u8> {
+    let mut w = PcapWriter::new(Vec::new(), PcapFormat::PcapNg).unwrap();
+    for (direction, pkt) in pkts {
+        w.write_packet(SystemTime::now(), *direction, pkt).unwrap();
+    }
+    w.into_inner()
+}
+
+fn icmp4() -> Vec<u8> {
+    ip::v4::Builder::default()
+        .id(0x42)
+        .unwrap()
+        .ttl(64)
+        .unwrap()
+        .source("1.2.3.4".parse().unwrap())
+        .unwrap()
+        .destination("5.6.7.8".parse().unwrap())
+        .unwrap()
+        .icmp()
+        .unwrap()
+        .echo()
+        .unwrap()
+        .request()
+        .unwrap()
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn replay_skips_outbound() {
+    let pcap = capture(&[
+        (Direction::Inbound, icmp4()),
+        (Direction::Outbound, icmp4()),
+        (Direction::Inbound, icmp4()),
+    ]);
+
+    let dev = PcapDevice::from_reader(&pcap[..]).unwrap();
+    assert_eq!(dev.remaining(), 2);
+}
+
+#[test]
+fn replay_invalid_timing() {
+    let pcap = capture(&[(Direction::Inbound, icmp4())]);
+
+    for &factor in [0.0, -1.0, std::f64::NAN, std::f64::INFINITY].iter() {
+        let err = PcapDevice::from_reader(&pcap[..])
+            .unwrap()
+            .timing(Timing::Accelerated(factor))
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    }
+}
+
+rusty_fork_test! {
+#[test]
+fn replay_bottom_up_icmp4() {
+    let mut rt = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_time()
+        .build()
+        .unwrap();
+    rt.block_on(async { timeout(Duration::from_secs(30), replay_bottom_up_icmp4_async()).await })
+        .unwrap();
+}
+}
+
+async fn replay_bottom_up_icmp4_async() {
+    let pkt = icmp4();
+    let pcap = capture(&[(Direction::Inbound, pkt.clone())]);
+
+    let dev = PcapDevice::from_reader(&pcap[..])
+        .unwrap()
+        .timing(Timing::Immediate)
+        .unwrap()
+        .ipv4(Ipv4Addr::LOCALHOST, 8);
+    let dev = lwip::NetDevice::new(dev).unwrap();
+
+    let mut raw = lwip::RawSocket::bind_proto(lwip::Proto::Icmp, &dev).unwrap();
+    tokio::spawn(dev.drive());
+
+    let mut buf = vec![0; pkt.len()];
+    let len = raw.read(&mut buf).await.unwrap();
+    assert_eq!(len, pkt.len());
+    assert_eq!(buf, pkt);
+}
+
+rusty_fork_test! {
+#[test]
+fn replay_tcp_syn_closed_port() {
+    let mut rt = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_time()
+        .build()
+        .unwrap();
+    rt.block_on(async { timeout(Duration::from_secs(30), replay_tcp_syn_closed_port_async()).await })
+        .unwrap();
+}
+}
+
+async fn replay_tcp_syn_closed_port_async() {
+    let syn = ip::v4::Builder::default()
+        .id(0x42)
+        .unwrap()
+        .ttl(64)
+        .unwrap()
+        .source("10.0.0.2".parse().unwrap())
+        .unwrap()
+        .destination("10.0.0.1".parse().unwrap())
+        .unwrap()
+        .tcp()
+        .unwrap()
+        .source(1234)
+        .unwrap()
+        .destination(80)
+        .unwrap()
+        .sequence(1)
+        .unwrap()
+        .window(65535)
+        .unwrap()
+        .flags(tcp::flag::SYN)
+        .unwrap()
+        .build()
+        .unwrap();
+    let pcap = capture(&[(Direction::Inbound, syn)]);
+
+    let dev = PcapDevice::from_reader(&pcap[..])
+        .unwrap()
+        .timing(Timing::Accelerated(10.0))
+        .unwrap()
+        .ipv4(Ipv4Addr::new(10, 0, 0, 1), 24);
+    let sent = dev.sent();
+
+    let dev = lwip::NetDevice::new(dev).unwrap();
+    tokio::spawn(dev.drive());
+
+    while sent.is_empty() {
+        delay_for(Duration::from_millis(10)).await;
+    }
+
+    // the stack answers with a RST from 10.0.0.1:80
+    let rst = &sent.packets()[0];
+    assert_eq!(rst[9], 6);
+    assert_eq!(&rst[12..16], &[10, 0, 0, 1]);
+    assert_eq!(&rst[16..20], &[10, 0, 0, 2]);
+    let tcp = &rst[((rst[0] & 0xf) as usize * 4)..];
+    assert_eq!(&tcp[0..2], &80u16.to_be_bytes());
+    assert_ne!(tcp[13] & 0x04, 0);
+}
@@ -0,0 +1,215 @@
+#[macro_use]
+extern crate rusty_fork;
+
+use std::net::Ipv6Addr;
+use std::time::Duration;
+
+use ipnetwork::Ipv6Network;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::runtime;
+use tokio::stream::StreamExt;
+
+use lwip::dev::{Link, LinkEnd};
+use lwip::{NetIfEvent, RouterAdvertiser};
+
+const ROUTER_HWADDR: [u8; 6] = [0x02, 0, 0, 0, 0, 0x01];
+const HOST_HWADDR: [u8; 6] = [0x02, 0, 0, 0, 0, 0x02];
+
+rusty_fork_test! {
+#[test]
+fn router_advert_options() {
+    let mut rt = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_time()
+        .build()
+        .unwrap();
+    rt.block_on(router_advert_options_async());
+}
+
+#[test]
+fn router_advert_slaac() {
+    let mut rt = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_time()
+        .build()
+        .unwrap();
+    rt.block_on(router_advert_slaac_async());
+}
+}
+
+fn router() -> Ipv6Addr {
+    "fe80::1".parse().unwrap()
+}
+
+fn prefix() -> Ipv6Network {
+    "2001:db8:1::/64".parse().unwrap()
+}
+
+fn dns() -> Ipv6Addr {
+    "2001:db8:1::53".parse().unwrap()
+}
+
+fn ipv6(buf: &[u8]) -> Ipv6Addr {
+    let mut octets = [0; 16];
+    octets.copy_from_slice(&buf[..16]);
+    octets.into()
+}
+
+/// Router solicitation from `src`, to all routers.
+fn solicitation(src: Ipv6Addr) -> Vec<u8> {
+    let dst: Ipv6Addr = "ff02::2".parse().unwrap();
+
+    common::ipv6_icmp(src, dst, vec![133, 0, 0, 0, 0, 0, 0, 0])
+}
+
+struct Advertisement {
+    dst: Ipv6Addr,
+    router_lifetime: u16,
+    options: Vec<(u8, Vec<u8>)>,
+}
+
+impl Advertisement {
+    fn option(&self, code: u8) -> &[u8] {
+        &self.options.iter().find(|(c, _)| *c == code).unwrap().1
+    }
+}
+
+/// Next router advertisement read from the link, checked for validity.
+async fn next_advertisement(link: &mut LinkEnd) -> Advertisement {
+    let mut buf = vec![0u8; 2048];
+
+    loop {
+        let len = link.read(&mut buf).await.unwrap();
+        let pkt = &buf[..len];
+        if len <= 40 || pkt[0] >> 4 != 6 || pkt[6] != 58 || pkt[40] != 134 {
+            continue;
+        }
+
+        let (src, dst) = (ipv6(&pkt[8..]), ipv6(&pkt[24..]));
+        assert_eq!(src, router());
+        assert_eq!(pkt[7], 255);
+        assert_eq!(common::icmpv6_checksum(src, dst, &pkt[40..]), 0);
+
+        let icmp = &pkt[40..];
+        let mut options = Vec::new();
+        let mut opts = &icmp[16..];
+        while !opts.is_empty() {
+            let len = opts[1] as usize * 8;
+            options.push((opts[0], opts[2..len].to_vec()));
+            opts = &opts[len..];
+        }
+
+        return Advertisement {
+            dst,
+            router_lifetime: u16::from_be_bytes([icmp[6], icmp[7]]),
+            options,
+        };
+    }
+}
+
+async fn router_advert_options_async() {
+    lwip::time::pause();
+
+    let (dev, mut link) = Link::new();
+    let dev = lwip::DeviceBuilder::default()
+        .hwaddr(ROUTER_HWADDR)
+        .ipv6(router(), 64)
+        .build(dev)
+        .unwrap();
+
+    let advertiser = RouterAdvertiser::builder()
+        .prefix(prefix())
+        .rdnss(dns())
+        .mtu(1280)
+        .bind(dev.netif_as_ref())
+        .unwrap();
+    tokio::spawn(dev.drive());
+
+    // sent right away
+    let ra = next_advertisement(&mut link).await;
+    assert_eq!(ra.dst, "ff02::1".parse::<Ipv6Addr>().unwrap());
+    assert_eq!(ra.router_lifetime, 1800);
+    assert_eq!(ra.option(1), &ROUTER_HWADDR[..]);
+    assert_eq!(ra.option(5), &[0, 0, 0, 0, 5, 0][..]);
+
+    let info = ra.option(3);
+    assert_eq!(info[..2], [64, 0xc0]);
+    assert_eq!(info[2..6], 86400u32.to_be_bytes());
+    assert_eq!(info[6..10], 14400u32.to_be_bytes());
+    assert_eq!(ipv6(&info[14..]), prefix().network());
+
+    let rdnss = ra.option(25);
+    assert_eq!(rdnss[2..6], 1800u32.to_be_bytes());
+    assert_eq!(ipv6(&rdnss[6..]), dns());
+
+    // solicited ones go back to the host, no sooner than 3s after the
+    // last one. Solicitations waiting for an answer get only one.
+    let host: Ipv6Addr = "fe80::2".parse().unwrap();
+    link.write_all(&solicitation(host)).await.unwrap();
+    link.write_all(&solicitation(host)).await.unwrap();
+    lwip::time::advance(Duration::from_millis(2900)).await;
+    assert_eq!(advertiser.sent(), 1);
+    lwip::time::advance(Duration::from_millis(200)).await;
+    assert_eq!(next_advertisement(&mut link).await.dst, host);
+    assert_eq!(advertiser.sent(), 2);
+
+    // and the next ones 3s after it as well
+    link.write_all(&solicitation(host)).await.unwrap();
+    lwip::time::advance(Duration::from_millis(3500)).await;
+    assert_eq!(next_advertisement(&mut link).await.dst, host);
+    assert_eq!(advertiser.sent(), 3);
+
+    // the initial unsolicited ones are at most 16s apart
+    lwip::time::advance(Duration::from_secs(10)).await;
+    let ra = next_advertisement(&mut link).await;
+    assert_eq!(ra.dst, "ff02::1".parse::<Ipv6Addr>().unwrap());
+    assert_eq!(advertiser.sent(), 4);
+
+    // the last one withdraws the router
+    drop(advertiser);
+    assert_eq!(next_advertisement(&mut link).await.router_lifetime, 0);
+}
+
+async fn router_advert_slaac_async() {
+    lwip::time::pause();
+
+    let (dev0, dev1) = Link::new();
+    let router_dev = lwip::DeviceBuilder::default()
+        .hwaddr(ROUTER_HWADDR)
+        .ipv6(router(), 64)
+        .build(dev0)
+        .unwrap();
+    let _advertiser = RouterAdvertiser::builder()
+        .prefix(prefix())
+        .bind(router_dev.netif_as_ref())
+        .unwrap();
+
+    let host_dev = lwip::DeviceBuilder::default()
+        .hwaddr(HOST_HWADDR)
+        .ipv6_autoconfig()
+        .build(dev1)
+        .unwrap();
+    let netif = host_dev.netif_as_ref().clone();
+    let mut events = netif.watch();
+
+    tokio::spawn(router_dev.drive());
+    tokio::spawn(host_dev.drive());
+
+    lwip::time::advance(Duration::from_secs(12)).await;
+
+    let slaac: Ipv6Addr = "2001:db8:1::ff:fe00:2".parse().unwrap();
+    loop {
+        match events.next().await.unwrap() {
+            NetIfEvent::DadFinished { addr, duplicate } if addr == slaac => {
+                assert!(!duplicate);
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    let autoconf = netif.ipv6_autoconf().unwrap();
+    assert_eq!(autoconf.addrs[0].addr, slaac);
+    assert_eq!(autoconf.prefixes[0].prefix, prefix());
+    assert_eq!(autoconf.routers[0].addr, router());
+}
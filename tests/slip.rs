@@ -0,0 +1,109 @@
+#[macro_use]
+extern crate rusty_fork;
+
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+use tokio::runtime;
+use tokio::time::timeout;
+use tokio_test::{block_on, io::Builder};
+
+use lwip::{DeviceBuilder, SlipDevice, UdpSocket};
+
+#[test]
+fn slip_read() {
+    // escapes split across reads, and leading or back to back ENDs
+    let mock = Builder::new()
+        .read(b"\xc0\xc0a\xdb")
+        .read(b"\xdcb\xdb\xddc\xc0")
+        .read(b"\xc0hello\xc0\xc0")
+        .read(b"partial")
+        .build();
+    let mut dev = SlipDevice::new(mock);
+
+    block_on(async {
+        let mut pkt = vec![0; 16];
+        assert_eq!(dev.read(&mut pkt).await.unwrap(), 5);
+        assert_eq!(&pkt[..5], b"a\xc0b\xdbc");
+        assert_eq!(dev.read(&mut pkt).await.unwrap(), 5);
+        assert_eq!(&pkt[..5], b"hello");
+        assert_eq!(dev.read(&mut pkt).await.unwrap(), 0);
+    });
+}
+
+#[test]
+fn slip_write() {
+    let mock = Builder::new()
+        .write(b"\xc0a\xdb\xdcb\xdb\xddc\xc0")
+        .write(b"\xc0hello\xc0")
+        .build();
+    let mut dev = SlipDevice::new(mock);
+
+    block_on(async {
+        assert_eq!(dev.write(b"a\xc0b\xdbc").await.unwrap(), 5);
+        assert_eq!(dev.write(b"hello").await.unwrap(), 5);
+    });
+}
+
+#[test]
+fn slip_write_pending() {
+    // the packet is taken while its frame does not fit in the stream, the
+    // rest goes out before the next packet
+    let mock = Builder::new()
+        .write(b"\xc0he")
+        .wait(Duration::from_millis(10))
+        .write(b"llo\xc0")
+        .write(b"\xc0world\xc0")
+        .write(b"\xc0!")
+        .wait(Duration::from_millis(10))
+        .write(b"\xc0")
+        .build();
+    let mut dev = SlipDevice::new(mock);
+
+    block_on(async {
+        assert_eq!(dev.write(b"hello").await.unwrap(), 5);
+        assert_eq!(dev.write(b"world").await.unwrap(), 5);
+        assert_eq!(dev.write(b"!").await.unwrap(), 1);
+        dev.flush().await.unwrap();
+    });
+}
+
+rusty_fork_test! {
+#[test]
+fn slip_netif() {
+    let mut rt = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_all()
+        .build()
+        .unwrap();
+    rt.block_on(async { timeout(Duration::from_secs(10), slip_netif_async()).await })
+        .unwrap();
+}
+}
+
+async fn slip_netif_async() {
+    let (a, b) = UnixStream::pair().unwrap();
+    let dev = DeviceBuilder::default()
+        .ipv4(Ipv4Addr::new(10, 0, 0, 1), 24)
+        .build(SlipDevice::new(a))
+        .unwrap();
+    tokio::spawn(dev.drive());
+    let mut peer = SlipDevice::new(b);
+
+    let mut socket = UdpSocket::bind("0.0.0.0:0").await.unwrap();
+    socket
+        .send_to(b"hello", (Ipv4Addr::new(10, 0, 0, 2), 7))
+        .await
+        .unwrap();
+
+    // one IPv4/UDP packet per frame
+    let mut pkt = vec![0; 1500];
+    let len = peer.read(&mut pkt).await.unwrap();
+    assert_eq!(len, 20 + 8 + 5);
+    assert_eq!(pkt[0] >> 4, 4);
+    assert_eq!(pkt[9], 17);
+    assert_eq!(&pkt[16..20], &[10, 0, 0, 2]);
+    assert_eq!(&pkt[28..len], b"hello");
+}
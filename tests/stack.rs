@@ -0,0 +1,158 @@
+#[macro_use]
+extern crate rusty_fork;
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::runtime;
+use tokio::stream::StreamExt;
+use tokio::time::timeout;
+
+use lwip::Stack;
+
+rusty_fork_test! {
+#[test]
+fn stack_restart() {
+    let mut rt = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_time()
+        .build()
+        .unwrap();
+    rt.block_on(async { timeout(Duration::from_secs(30), stack_restart_async()).await })
+        .unwrap();
+}
+}
+
+async fn echo_once() -> lwip::TcpStream {
+    let mut echo = lwip::TcpListener::bind("0.0.0.0:1234").await.unwrap();
+    tokio::spawn(async move {
+        if let Some(Ok(conn)) = echo.next().await {
+            let (mut r, mut w) = tokio::io::split(conn);
+            let _ = tokio::io::copy(&mut r, &mut w).await;
+        }
+    });
+
+    let mut conn = lwip::TcpStream::connect("127.0.0.1:1234").await.unwrap();
+    conn.write(b"hello").await.unwrap();
+    let mut buf = vec![0; 5];
+    conn.read(&mut buf).await.unwrap();
+    assert_eq!(buf, b"hello");
+    conn
+}
+
+async fn stack_restart_async() {
+    for _ in 0..2 {
+        let dev = lwip::DeviceBuilder::loopback().unwrap();
+        let netif = dev.netif_as_ref().clone();
+        tokio::spawn(dev.drive());
+        assert!(Stack::is_running());
+
+        let mut conn = echo_once().await;
+
+        Stack::shutdown_timeout(Duration::from_millis(100))
+            .await
+            .unwrap();
+        assert!(!Stack::is_running());
+        assert!(!netif.is_up());
+
+        // the netif was removed with its stack.
+        let err = netif.set_up().unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::NotConnected);
+        let err = netif
+            .add_addr(Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 1))
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::NotConnected);
+        assert!(netif.list_addrs().is_empty());
+        assert!(netif.dhcp_lease().is_none());
+        netif.stop_dhcp();
+        assert!(netif.watch().next().await.is_none());
+
+        // the connection was aborted.
+        let mut buf = vec![0; 5];
+        assert!(conn.read(&mut buf).await.is_err());
+    }
+}
+
+rusty_fork_test! {
+#[test]
+fn stack_restart_servers() {
+    let mut rt = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_time()
+        .build()
+        .unwrap();
+    rt.block_on(async { timeout(Duration::from_secs(30), stack_restart_servers_async()).await })
+        .unwrap();
+}
+}
+
+async fn stack_restart_servers_async() {
+    let addr = Ipv4Addr::new(10, 0, 0, 1);
+    // kept past the shutdown of their stack
+    let mut servers = Vec::new();
+
+    for _ in 0..2 {
+        let (dev, _link) = lwip::dev::Link::new();
+        let dev = lwip::DeviceBuilder::default()
+            .ipv4(addr, 24)
+            .ethernet()
+            .build(dev)
+            .unwrap();
+        tokio::spawn(dev.drive());
+
+        // the UDP PCB of the previous server does not hold the port.
+        let dhcp = lwip::DhcpServer::builder()
+            .pool(Ipv4Addr::new(10, 0, 0, 100), Ipv4Addr::new(10, 0, 0, 199))
+            .bind(dev.netif_as_ref())
+            .unwrap();
+        servers.push(dhcp);
+
+        Stack::shutdown().await.unwrap();
+        assert!(!Stack::is_running());
+    }
+}
+
+rusty_fork_test! {
+#[test]
+fn stack_config() {
+    let mut rt = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_time()
+        .build()
+        .unwrap();
+    rt.block_on(async { timeout(Duration::from_secs(30), stack_config_async()).await })
+        .unwrap();
+}
+}
+
+async fn stack_config_async() {
+    assert!(lwip::StackConfig::new()
+        .tcp_mss(1460)
+        .tcp_wnd(1000)
+        .start()
+        .is_err());
+    assert!(!Stack::is_running());
+
+    let config = lwip::StackConfig::new()
+        .tcp_mss(1460)
+        .tcp_wnd(0xffff)
+        .tcp_snd_buf(16 * 1460)
+        .tcp_snd_queuelen(64)
+        .time_wait(Duration::from_secs(1));
+    config.clone().start().unwrap();
+    assert!(Stack::is_running());
+
+    // cannot reconfigure a running stack.
+    assert_eq!(
+        config.clone().start().unwrap_err().kind(),
+        std::io::ErrorKind::AlreadyExists
+    );
+
+    let dev = lwip::DeviceBuilder::loopback().unwrap();
+    tokio::spawn(dev.drive());
+    echo_once().await;
+
+    Stack::shutdown().await.unwrap();
+    config.start().unwrap();
+}
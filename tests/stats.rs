@@ -0,0 +1,99 @@
+#[macro_use]
+extern crate rusty_fork;
+
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::runtime;
+use tokio::stream::StreamExt;
+use tokio::time::timeout;
+
+rusty_fork_test! {
+#[test]
+fn stats_tcp_echo() {
+    let mut rt = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_time()
+        .build()
+        .unwrap();
+    rt.block_on(async { timeout(Duration::from_secs(30), stats_tcp_echo_async()).await })
+        .unwrap();
+}
+}
+
+async fn stats_tcp_echo_async() {
+    let dev = lwip::DeviceBuilder::loopback().unwrap();
+    let netif = dev.netif_as_ref().clone();
+
+    let before = lwip::stats();
+    assert_eq!(netif.stats(), lwip::NetIfStats::default());
+
+    let mut echo = lwip::TcpListener::bind("0.0.0.0:1234").await.unwrap();
+    tokio::spawn(async move {
+        if let Some(Ok(conn)) = echo.next().await {
+            let (mut r, mut w) = tokio::io::split(conn);
+            tokio::io::copy(&mut r, &mut w).await.unwrap();
+        }
+    });
+
+    tokio::spawn(dev.drive());
+
+    let mut conn = lwip::TcpStream::connect("127.0.0.1:1234").await.unwrap();
+    conn.write(b"hello").await.unwrap();
+    let mut buf = vec![0; 5];
+    conn.read(&mut buf).await.unwrap();
+
+    let after = lwip::stats();
+    assert!(after.tcp.xmit > before.tcp.xmit);
+    assert!(after.tcp.recv > before.tcp.recv);
+    assert_eq!(after.tcp.chkerr, before.tcp.chkerr);
+    assert!(after.tcp_mib.active_opens > before.tcp_mib.active_opens);
+    assert!(!after.memp.is_empty());
+
+    // the echo went out through the loopback and came back in.
+    let s = netif.stats();
+    assert!(s.packets_out > 0);
+    assert!(s.bytes_out > 0);
+    assert!(s.packets_in > 0);
+    assert!(s.bytes_in > 0);
+    assert_eq!(s.drops_in, 0);
+    assert_eq!(s.drops_out, 0);
+    assert_eq!(s.checksum_errors, 0);
+    assert_eq!(s.retransmits, 0);
+}
+
+rusty_fork_test! {
+#[test]
+fn stats_checksum_errors() {
+    let mut rt = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_time()
+        .build()
+        .unwrap();
+    rt.block_on(async { timeout(Duration::from_secs(30), stats_checksum_errors_async()).await })
+        .unwrap();
+}
+}
+
+async fn stats_checksum_errors_async() {
+    let (dev, mut link) = lwip::dev::Link::new();
+    let dev = lwip::DeviceBuilder::default()
+        .ipv4(Ipv4Addr::new(10, 0, 0, 1), 24)
+        .build(dev)
+        .unwrap();
+    let netif = dev.netif_as_ref().clone();
+    tokio::spawn(dev.drive());
+
+    // IPv4/UDP to us, the header checksum left to 0
+    let mut pkt = vec![0x45, 0, 0, 28, 0, 0, 0, 0, 64, 17, 0, 0];
+    pkt.extend_from_slice(&[10, 0, 0, 2, 10, 0, 0, 1]);
+    pkt.extend_from_slice(&[0x9c, 0x40, 0, 7, 0, 8, 0, 0]);
+    link.write_all(&pkt).await.unwrap();
+
+    while netif.stats().checksum_errors == 0 {
+        tokio::time::delay_for(Duration::from_millis(10)).await;
+    }
+    assert_eq!(netif.stats().checksum_errors, 1);
+    assert_eq!(netif.stats().packets_in, 1);
+}
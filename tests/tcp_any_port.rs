@@ -1,93 +1,10 @@
-use std::io;
 use std::net::Ipv4Addr;
-use std::pin::Pin;
-use std::sync::{Arc, Mutex};
-use std::task::{Context, Poll};
 use std::time::Duration;
 
-use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::runtime;
 use tokio::stream::StreamExt;
-use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
 use tokio::time::timeout;
 
-#[derive(Debug)]
-struct SubDeviceInner {
-    txqueue: UnboundedSender<Vec<u8>>,
-    rxqueue: UnboundedReceiver<Vec<u8>>,
-}
-
-#[derive(Debug)]
-pub struct SubDevice(Arc<Mutex<SubDeviceInner>>);
-
-pub struct DevicePair;
-
-impl DevicePair {
-    pub fn new() -> (SubDevice, SubDevice) {
-        let (tx0, rx0) = unbounded_channel();
-        let (tx1, rx1) = unbounded_channel();
-
-        let inner0 = SubDeviceInner {
-            txqueue: tx0,
-            rxqueue: rx1,
-        };
-
-        let inner1 = SubDeviceInner {
-            txqueue: tx1,
-            rxqueue: rx0,
-        };
-
-        (
-            SubDevice(Arc::new(Mutex::new(inner0))),
-            SubDevice(Arc::new(Mutex::new(inner1))),
-        )
-    }
-}
-
-impl AsyncRead for SubDevice {
-    fn poll_read(
-        self: Pin<&mut Self>,
-        cx: &mut Context<'_>,
-        buf: &mut [u8],
-    ) -> Poll<io::Result<usize>> {
-        let mut inner = self.0.lock().unwrap();
-
-        match inner.rxqueue.poll_recv(cx) {
-            Poll::Ready(Some(pkt)) => {
-                buf[..pkt.len()].copy_from_slice(&pkt);
-                Poll::Ready(Ok(pkt.len()))
-            }
-            _ => Poll::Pending,
-        }
-    }
-}
-
-impl AsyncWrite for SubDevice {
-    fn poll_write(
-        self: Pin<&mut Self>,
-        _: &mut Context<'_>,
-        buf: &[u8],
-    ) -> Poll<Result<usize, io::Error>> {
-        let inner = self.0.lock().unwrap();
-
-        Poll::Ready(match inner.txqueue.send(buf.to_vec()) {
-            Ok(()) => Ok(buf.len()),
-            _ => Err(io::Error::new(
-                io::ErrorKind::Other,
-                "unable to transmit pkt",
-            )),
-        })
-    }
-
-    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
-        Poll::Ready(Ok(()))
-    }
-
-    fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
-        Poll::Ready(Ok(()))
-    }
-}
-
 #[test]
 fn tcp_any_port() {
     let mut rt = runtime::Builder::new()
@@ -100,7 +17,7 @@ fn tcp_any_port() {
 }
 
 async fn tcp_any_port_async() {
-    let (dev0, dev1) = DevicePair::new();
+    let (dev0, dev1) = lwip::dev::Link::new();
 
     let dev0 = lwip::DeviceBuilder::default()
         .mtu(1500)
@@ -135,7 +52,7 @@ async fn tcp_any_port_async() {
     }
 
     // TODO: improve this:
-    // DevicePair can cause a deadlock if drops happens in a specific order.
+    // the stacks can deadlock if drops happens in a specific order.
     // Sleeping for a while solves this issue:
     std::thread::sleep(std::time::Duration::from_secs(1));
 }
@@ -0,0 +1,212 @@
+#[macro_use]
+extern crate rusty_fork;
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::runtime;
+use tokio::time::timeout;
+
+use lwip::{CcState, CongestionControl, Cubic, FixedRate, Loss, Reno};
+
+#[allow(dead_code)]
+mod common;
+
+const MSS: u32 = 1000;
+
+fn state(cwnd: u32, ssthresh: u32) -> CcState {
+    CcState {
+        cwnd,
+        ssthresh,
+        mss: MSS,
+        bytes_in_flight: cwnd,
+        rtt: Some(Duration::from_millis(100)),
+        now: Duration::from_secs(10),
+        in_recovery: false,
+    }
+}
+
+#[test]
+fn cc_reno() {
+    let mut reno = Reno::new();
+
+    // slow start
+    let mut s = state(2 * MSS, 10 * MSS);
+    reno.on_ack(&mut s, MSS);
+    assert_eq!(s.cwnd, 3 * MSS);
+
+    // congestion avoidance: one MSS per window
+    let mut s = state(10 * MSS, 5 * MSS);
+    for _ in 0..9 {
+        reno.on_ack(&mut s, MSS);
+    }
+    assert_eq!(s.cwnd, 10 * MSS);
+    reno.on_ack(&mut s, MSS);
+    assert_eq!(s.cwnd, 11 * MSS);
+
+    reno.on_loss(&mut s, Loss::FastRetransmit);
+    assert_eq!(s.ssthresh, 5 * MSS);
+    assert_eq!(s.cwnd, 8 * MSS);
+
+    reno.on_loss(&mut s, Loss::Timeout);
+    assert_eq!(s.cwnd, MSS);
+}
+
+#[test]
+fn cc_cubic() {
+    let mut cubic = Cubic::new();
+
+    let mut s = state(100 * MSS, 50 * MSS);
+    cubic.on_loss(&mut s, Loss::FastRetransmit);
+    assert_eq!(s.ssthresh, 70 * MSS);
+    assert_eq!(s.cwnd, 70 * MSS);
+
+    // grows back towards the previous maximum
+    let mut last = s.cwnd;
+    for i in 0..1000 {
+        s.now += Duration::from_millis(10);
+        cubic.on_ack(&mut s, MSS);
+        assert!(s.cwnd >= last, "window shrank at ack {}", i);
+        last = s.cwnd;
+    }
+    assert!(s.cwnd > 90 * MSS);
+
+    // the stack clock went back
+    s.now = Duration::from_secs(1);
+    cubic.on_ack(&mut s, MSS);
+    assert!(s.cwnd >= last);
+}
+
+#[test]
+fn cc_fixed_rate() {
+    let mut fixed = FixedRate::new(8_000_000);
+
+    let mut s = state(10 * MSS, 5 * MSS);
+    fixed.on_ack(&mut s, MSS);
+    // 1MB/s * 100ms
+    assert_eq!(s.cwnd, 100_000);
+
+    fixed.on_loss(&mut s, Loss::Timeout);
+    assert_eq!(s.cwnd, 100_000);
+}
+
+#[derive(Default)]
+struct Events {
+    acked: u64,
+    losses: u64,
+    rtt: bool,
+}
+
+struct Recorder(Arc<Mutex<Events>>, Reno);
+
+impl CongestionControl for Recorder {
+    fn name(&self) -> &'static str {
+        "recorder"
+    }
+
+    fn on_ack(&mut self, state: &mut CcState, acked: u32) {
+        let mut events = self.0.lock().unwrap();
+        events.acked += acked as u64;
+        events.rtt |= state.rtt.is_some();
+        self.1.on_ack(state, acked)
+    }
+
+    fn on_loss(&mut self, state: &mut CcState, loss: Loss) {
+        self.0.lock().unwrap().losses += 1;
+        self.1.on_loss(state, loss)
+    }
+}
+
+struct Panicking;
+
+impl CongestionControl for Panicking {
+    fn name(&self) -> &'static str {
+        "panicking"
+    }
+
+    fn on_ack(&mut self, _: &mut CcState, _: u32) {
+        panic!("on_ack");
+    }
+
+    fn on_loss(&mut self, _: &mut CcState, _: Loss) {
+        panic!("on_loss");
+    }
+}
+
+rusty_fork_test! {
+#[test]
+fn cc_panic() {
+    let mut rt = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_time()
+        .build()
+        .unwrap();
+    rt.block_on(async { timeout(Duration::from_secs(30), cc_panic_async()).await })
+        .unwrap();
+}
+
+#[test]
+fn cc_lossy_link() {
+    let mut rt = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_time()
+        .build()
+        .unwrap();
+    rt.block_on(async { timeout(Duration::from_secs(60), cc_lossy_link_async()).await })
+        .unwrap();
+}
+}
+
+async fn cc_lossy_link_async() {
+    lwip::StackConfig::new()
+        .tcp_mss(1460)
+        .tcp_wnd(64 * 1460)
+        .tcp_snd_buf(32 * 1460)
+        .tcp_snd_queuelen(128)
+        .start()
+        .unwrap();
+
+    let (dev0, dev1) = lwip::dev::Link::builder()
+        .latency(Duration::from_millis(5))
+        .loss(0.02)
+        .seed(3)
+        .build();
+    common::link_pair(0, dev0, dev1);
+
+    let listener = lwip::TcpListener::bind("10.0.0.2:80").await.unwrap();
+    let server = common::sink(listener);
+
+    let mut conn = lwip::TcpStream::connect_from("10.0.0.1:0", "10.0.0.2:80")
+        .await
+        .unwrap();
+    let events = Arc::new(Mutex::new(Events::default()));
+    conn.set_congestion_control(Recorder(events.clone(), Reno::new()))
+        .unwrap();
+
+    let len = 256 * 1024;
+    common::send_all(&mut conn, len).await;
+    assert_eq!(server.await.unwrap(), len);
+
+    let events = events.lock().unwrap();
+    assert!(events.acked >= len as u64);
+    assert!(events.losses > 0);
+    assert!(events.rtt);
+}
+
+async fn cc_panic_async() {
+    let (dev0, dev1) = lwip::dev::Link::new();
+    common::link_pair(0, dev0, dev1);
+
+    let listener = lwip::TcpListener::bind("10.0.0.2:80").await.unwrap();
+    let server = common::sink(listener);
+
+    let mut conn = lwip::TcpStream::connect_from("10.0.0.1:0", "10.0.0.2:80")
+        .await
+        .unwrap();
+    conn.set_congestion_control(Panicking).unwrap();
+
+    // lwIP's own congestion control takes over
+    let len = 64 * 1024;
+    common::send_all(&mut conn, len).await;
+    assert_eq!(server.await.unwrap(), len);
+}
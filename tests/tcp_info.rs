@@ -0,0 +1,63 @@
+#[macro_use]
+extern crate rusty_fork;
+
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::runtime;
+use tokio::stream::StreamExt;
+use tokio::time::timeout;
+
+use lwip::TcpState;
+
+rusty_fork_test! {
+#[test]
+fn tcp_info() {
+    let mut rt = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_time()
+        .build()
+        .unwrap();
+    rt.block_on(async { timeout(Duration::from_secs(30), tcp_info_async()).await })
+        .unwrap();
+}
+}
+
+async fn tcp_info_async() {
+    let dev = lwip::DeviceBuilder::loopback().unwrap();
+    tokio::spawn(dev.drive());
+
+    let mut echo = lwip::TcpListener::bind("0.0.0.0:1234").await.unwrap();
+    tokio::spawn(async move {
+        if let Some(Ok(conn)) = echo.next().await {
+            let (mut r, mut w) = tokio::io::split(conn);
+            tokio::io::copy(&mut r, &mut w).await.unwrap();
+        }
+    });
+
+    let mut conn = lwip::TcpStream::connect("127.0.0.1:1234").await.unwrap();
+    let info = conn.info().unwrap();
+    assert_eq!(info.state, TcpState::Established);
+    assert!(info.mss > 0);
+    assert!(info.cwnd >= info.mss as u32);
+    assert!(info.rcv_wnd > 0);
+    assert!(info.snd_wnd > 0);
+    assert_eq!(info.bytes_in_flight, 0);
+    assert_eq!(info.unsent, 0);
+    assert_eq!(info.retransmits, 0);
+
+    conn.write(b"hello").await.unwrap();
+    let mut buf = vec![0; 5];
+    conn.read(&mut buf).await.unwrap();
+
+    // everything was acknowledged.
+    let info = conn.info().unwrap();
+    assert_eq!(info.unacked, 0);
+    assert_eq!(info.bytes_in_flight, 0);
+    assert!(info.rto > Duration::from_millis(0));
+
+    // the echo closes its side once ours is closed.
+    conn.shutdown().await.unwrap();
+    assert_eq!(conn.read(&mut buf).await.unwrap(), 0);
+    assert_eq!(conn.info().unwrap().state, TcpState::TimeWait);
+}
@@ -0,0 +1,214 @@
+#![cfg(feature = "sack")]
+
+#[macro_use]
+extern crate rusty_fork;
+
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::runtime;
+use tokio::stream::StreamExt;
+use tokio::time::{delay_for, timeout};
+
+use lwip::dev::LinkEnd;
+
+#[allow(dead_code)]
+mod common;
+
+const DATA: usize = 32 * 1460;
+// data segment lost on the way, the first is 0
+const LOST: usize = 2;
+
+#[derive(Debug, Default)]
+struct Holes {
+    // the lost segment, [start, end)
+    lost: Option<(u32, u32)>,
+    // ACK number and first SACK block of the ACKs carrying some
+    sacks: Vec<(u32, (u32, u32))>,
+}
+
+/// Sender side of the link: loses the `LOST`th data segment, and its
+/// retransmissions while `hold` is set, and records the SACK blocks
+/// received.
+struct Probe {
+    link: LinkEnd,
+    segments: usize,
+    hold: Arc<AtomicBool>,
+    holes: Arc<Mutex<Holes>>,
+}
+
+// Returns the TCP segment of an IPv4 packet.
+fn tcp(pkt: &[u8]) -> Option<&[u8]> {
+    if pkt.len() < 40 || pkt[0] >> 4 != 4 || pkt[9] != 6 {
+        return None;
+    }
+    let ihl = (pkt[0] & 0xf) as usize * 4;
+    Some(&pkt[ihl..])
+}
+
+fn tcp_options(tcp: &[u8]) -> &[u8] {
+    let off = (tcp[12] >> 4) as usize * 4;
+    &tcp[20..off]
+}
+
+fn be32(b: &[u8]) -> u32 {
+    u32::from_be_bytes([b[0], b[1], b[2], b[3]])
+}
+
+// Returns the first SACK block of the options.
+fn sack_block(mut opts: &[u8]) -> Option<(u32, u32)> {
+    loop {
+        let (kind, len) = match opts.first() {
+            None | Some(0) => return None,
+            Some(1) => (1, 1),
+            Some(_) if opts.len() < 2 || opts[1] < 2 => return None,
+            Some(&kind) => (kind, (opts[1] as usize).min(opts.len())),
+        };
+        if kind == 5 && len >= 10 {
+            return Some((be32(&opts[2..]), be32(&opts[6..])));
+        }
+        opts = &opts[len..];
+    }
+}
+
+impl Probe {
+    // Whether the data segment is lost.
+    fn lost(&mut self, tcp: &[u8]) -> bool {
+        let seq = be32(&tcp[4..]);
+        let len = (tcp.len() - (tcp[12] >> 4) as usize * 4) as u32;
+        if len == 0 {
+            return false;
+        }
+
+        let mut holes = self.holes.lock().unwrap();
+        match holes.lost {
+            Some((start, _)) => start == seq && self.hold.load(Ordering::SeqCst),
+            None => {
+                let lost = self.segments == LOST;
+                if lost {
+                    holes.lost = Some((seq, seq.wrapping_add(len)));
+                }
+                self.segments += 1;
+                lost
+            }
+        }
+    }
+}
+
+impl AsyncRead for Probe {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        match Pin::new(&mut this.link).poll_read(cx, buf) {
+            Poll::Ready(Ok(len)) => {
+                if let Some(tcp) = tcp(&buf[..len]) {
+                    if let Some(block) = sack_block(tcp_options(tcp)) {
+                        let ack = be32(&tcp[8..]);
+                        this.holes.lock().unwrap().sacks.push((ack, block));
+                    }
+                }
+                Poll::Ready(Ok(len))
+            }
+            poll => poll,
+        }
+    }
+}
+
+impl AsyncWrite for Probe {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        let this = self.get_mut();
+
+        if let Some(tcp) = tcp(buf) {
+            if this.lost(tcp) {
+                return Poll::Ready(Ok(buf.len()));
+            }
+        }
+        Pin::new(&mut this.link).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        Pin::new(&mut self.get_mut().link).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        Pin::new(&mut self.get_mut().link).poll_shutdown(cx)
+    }
+}
+
+rusty_fork_test! {
+#[test]
+fn tcp_sack_hole() {
+    let mut rt = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_time()
+        .build()
+        .unwrap();
+    rt.block_on(async { timeout(Duration::from_secs(60), tcp_sack_hole_async()).await })
+        .unwrap();
+}
+}
+
+async fn tcp_sack_hole_async() {
+    assert!(lwip::StackConfig::sack());
+
+    lwip::StackConfig::new()
+        .tcp_mss(1460)
+        .tcp_wnd(64 * 1460)
+        .tcp_snd_buf(32 * 1460)
+        .tcp_snd_queuelen(128)
+        .start()
+        .unwrap();
+
+    let (dev0, dev1) = lwip::dev::Link::builder()
+        .latency(Duration::from_millis(5))
+        .build();
+    let hold = Arc::new(AtomicBool::new(true));
+    let holes = Arc::new(Mutex::new(Holes::default()));
+    let dev0 = Probe {
+        link: dev0,
+        segments: 0,
+        hold: hold.clone(),
+        holes: holes.clone(),
+    };
+    common::link_pair(0, dev0, dev1);
+
+    let mut listener = lwip::TcpListener::bind("10.0.0.2:80").await.unwrap();
+    let mut conn = lwip::TcpStream::connect_from("10.0.0.1:0", "10.0.0.2:80")
+        .await
+        .unwrap();
+    let mut server = listener.next().await.unwrap().unwrap();
+    tokio::spawn(async move { common::send_all(&mut conn, DATA).await });
+
+    // the receiver reports the data past the hole
+    while holes.lock().unwrap().sacks.is_empty() {
+        delay_for(Duration::from_millis(10)).await;
+    }
+    {
+        let holes = holes.lock().unwrap();
+        let (start, end) = holes.lost.unwrap();
+        for &(ack, (left, right)) in holes.sacks.iter() {
+            assert_eq!(ack, start);
+            assert_eq!(left, end);
+            assert!(right.wrapping_sub(left) as i32 > 0);
+        }
+    }
+
+    // and keeps it meanwhile
+    assert!(server.info().unwrap().ooseq > 0);
+
+    hold.store(false, Ordering::SeqCst);
+    assert_eq!(common::read_all(&mut server).await, DATA);
+}
@@ -0,0 +1,66 @@
+#[macro_use]
+extern crate rusty_fork;
+
+use std::time::Duration;
+
+use tokio::runtime;
+use tokio::stream::StreamExt;
+use tokio::time::timeout;
+
+#[allow(dead_code)]
+mod common;
+
+const WND: usize = 256 * 1024;
+
+rusty_fork_test! {
+#[test]
+fn tcp_window_scale() {
+    let mut rt = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_time()
+        .build()
+        .unwrap();
+    rt.block_on(async { timeout(Duration::from_secs(30), tcp_window_scale_async()).await })
+        .unwrap();
+}
+}
+
+async fn tcp_window_scale_async() {
+    lwip::StackConfig::new()
+        .tcp_mss(1460)
+        .tcp_wnd(WND as u32)
+        .tcp_snd_buf(64 * 1460)
+        .tcp_snd_queuelen(256)
+        .start()
+        .unwrap();
+
+    let (dev0, dev1) = lwip::dev::Link::builder()
+        .latency(Duration::from_millis(20))
+        .build();
+    common::link_pair(0, dev0, dev1);
+
+    let mut listener = lwip::TcpListener::bind("10.0.0.2:80").await.unwrap();
+    let server = tokio::spawn(async move {
+        let mut conn = listener.next().await.unwrap().unwrap();
+        assert_eq!(conn.recv_buffer_size().unwrap(), WND);
+        common::read_all(&mut conn).await
+    });
+
+    let mut conn = lwip::TcpStream::connect_from("10.0.0.1:0", "10.0.0.2:80")
+        .await
+        .unwrap();
+
+    // the window is larger than 64KB: scaling was negotiated.
+    assert_eq!(conn.recv_buffer_size().unwrap(), WND);
+    assert!(conn.set_recv_buffer_size(WND + 1).is_err());
+    conn.set_recv_buffer_size(64 * 1024).unwrap();
+    assert_eq!(conn.recv_buffer_size().unwrap(), 64 * 1024);
+
+    assert_eq!(conn.send_buffer_size().unwrap(), 64 * 1460);
+    assert!(conn.set_send_buffer_size(100).is_err());
+    conn.set_send_buffer_size(128 * 1460).unwrap();
+    assert_eq!(conn.send_buffer_size().unwrap(), 128 * 1460);
+
+    common::send_all(&mut conn, 1024 * 1024).await;
+    assert_eq!(server.await.unwrap(), 1024 * 1024);
+}
@@ -0,0 +1,119 @@
+#[macro_use]
+extern crate rusty_fork;
+
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+use tokio::runtime;
+
+rusty_fork_test! {
+#[test]
+fn virtual_clock_syn_retransmit() {
+    let mut rt = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_time()
+        .build()
+        .unwrap();
+    rt.block_on(virtual_clock_syn_retransmit_async());
+}
+
+#[test]
+fn tokio_clock_syn_retransmit() {
+    let mut rt = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_time()
+        .build()
+        .unwrap();
+    rt.block_on(tokio_clock_syn_retransmit_async());
+}
+
+#[test]
+fn netif_syn_retransmit() {
+    let mut rt = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_time()
+        .build()
+        .unwrap();
+    rt.block_on(netif_syn_retransmit_async());
+}
+}
+
+/// Connects over a link losing every SYN on the way, from the returned
+/// interface.
+async fn lost_syn() -> lwip::NetIf {
+    let (dev0, dev1) = lwip::dev::Link::builder().loss(1.0).build();
+
+    let dev0 = lwip::DeviceBuilder::default()
+        .ipv4(Ipv4Addr::new(10, 0, 0, 1), 24)
+        .build(dev0)
+        .unwrap();
+    let netif = dev0.netif_as_ref().clone();
+    let dev1 = lwip::DeviceBuilder::default()
+        .ipv4(Ipv4Addr::new(10, 0, 0, 2), 24)
+        .build(dev1)
+        .unwrap();
+
+    tokio::spawn(dev0.drive());
+    tokio::spawn(dev1.drive());
+
+    tokio::spawn(lwip::TcpStream::connect_from("10.0.0.1:0", "10.0.0.2:80"));
+    tokio::task::yield_now().await;
+    netif
+}
+
+async fn virtual_clock_syn_retransmit_async() {
+    lwip::time::pause();
+    lost_syn().await;
+
+    let before = lwip::stats().tcp_mib.retransmits;
+    let start = lwip::time::now();
+
+    // wall-clock time does not move the stack clock.
+    std::thread::sleep(Duration::from_millis(500));
+    assert_eq!(lwip::time::now(), start);
+    assert_eq!(lwip::stats().tcp_mib.retransmits, before);
+
+    // the initial SYN retransmission timeout is 3s.
+    lwip::time::advance(Duration::from_secs(4)).await;
+    assert_eq!(lwip::time::now().wrapping_sub(start), 4000);
+    assert!(lwip::stats().tcp_mib.retransmits > before);
+}
+
+async fn tokio_clock_syn_retransmit_async() {
+    lwip::time::pause();
+    lost_syn().await;
+
+    let before = lwip::stats().tcp_mib.retransmits;
+    let start = lwip::time::now();
+
+    // tokio's own clock drives the lwIP timers, once its timers run.
+    tokio::time::advance(Duration::from_secs(4)).await;
+    tokio::time::delay_for(Duration::from_millis(1)).await;
+    assert!(lwip::time::now().wrapping_sub(start) >= 4000);
+    assert!(lwip::stats().tcp_mib.retransmits > before);
+
+    // as when tokio skips ahead to its next timer.
+    let before = lwip::stats().tcp_mib.retransmits;
+    tokio::time::delay_for(Duration::from_secs(10)).await;
+    assert!(lwip::time::now().wrapping_sub(start) >= 14000);
+    assert!(lwip::stats().tcp_mib.retransmits > before);
+}
+
+async fn netif_syn_retransmit_async() {
+    lwip::time::pause();
+
+    let (other, _link) = lwip::dev::Link::new();
+    let other = lwip::DeviceBuilder::default()
+        .ipv4(Ipv4Addr::new(10, 0, 1, 1), 24)
+        .build(other)
+        .unwrap();
+    let other_netif = other.netif_as_ref().clone();
+    tokio::spawn(other.drive());
+
+    let netif = lost_syn().await;
+    lwip::time::advance(Duration::from_secs(4)).await;
+
+    // counted on the interface of the connection only.
+    assert!(netif.stats().retransmits > 0);
+    assert_eq!(other_netif.stats().retransmits, 0);
+}
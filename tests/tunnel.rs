@@ -0,0 +1,156 @@
+#[macro_use]
+extern crate rusty_fork;
+
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{UdpSocket as HostUdpSocket, UnixStream};
+use tokio::runtime;
+use tokio::time::timeout;
+use tokio_test::{block_on, io::Builder};
+
+use lwip::{DeviceBuilder, StreamTunnel, UdpSocket, UdpTunnel};
+
+const LOCAL: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+const PEER: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 2);
+
+#[test]
+fn stream_tunnel_read() {
+    // lengths and packets split across reads, empty packets are skipped
+    let mock = Builder::new()
+        .read(b"\x00")
+        .read(b"\x04pi")
+        .read(b"ng\x00\x00\x00\x04pong")
+        .build();
+    let mut dev = StreamTunnel::new(mock);
+
+    block_on(async {
+        let mut pkt = vec![0; 16];
+        assert_eq!(dev.read(&mut pkt).await.unwrap(), 4);
+        assert_eq!(&pkt[..4], b"ping");
+        assert_eq!(dev.read(&mut pkt).await.unwrap(), 4);
+        assert_eq!(&pkt[..4], b"pong");
+        assert_eq!(dev.read(&mut pkt).await.unwrap(), 0);
+    });
+}
+
+#[test]
+fn stream_tunnel_truncated() {
+    let mock = Builder::new().read(b"\x00\x04pi").build();
+    let mut dev = StreamTunnel::new(mock);
+
+    block_on(async {
+        let mut pkt = vec![0; 16];
+        let err = dev.read(&mut pkt).await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+    });
+}
+
+#[test]
+fn stream_tunnel_write() {
+    let mock = Builder::new()
+        .write(b"\x00\x04ping")
+        .write(b"\x00\x04pong")
+        .build();
+    let mut dev = StreamTunnel::new(mock);
+
+    block_on(async {
+        assert_eq!(dev.write(b"ping").await.unwrap(), 4);
+        assert_eq!(dev.write(b"pong").await.unwrap(), 4);
+    });
+}
+
+#[test]
+fn stream_tunnel_write_pending() {
+    // the packet is taken while the stream is busy, it goes out before the
+    // next one
+    let mock = Builder::new()
+        .write(b"\x00\x04pi")
+        .wait(Duration::from_millis(10))
+        .write(b"ng")
+        .write(b"\x00\x04pong")
+        .build();
+    let mut dev = StreamTunnel::new(mock);
+
+    block_on(async {
+        assert_eq!(dev.write(b"ping").await.unwrap(), 4);
+        assert_eq!(dev.write(b"pong").await.unwrap(), 4);
+        dev.flush().await.unwrap();
+    });
+}
+
+rusty_fork_test! {
+#[test]
+fn stream_tunnel_netif() {
+    let mut rt = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_all()
+        .build()
+        .unwrap();
+    rt.block_on(async { timeout(Duration::from_secs(10), stream_tunnel_netif_async()).await })
+        .unwrap();
+}
+
+#[test]
+fn udp_tunnel_netif() {
+    let mut rt = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_all()
+        .build()
+        .unwrap();
+    rt.block_on(async { timeout(Duration::from_secs(10), udp_tunnel_netif_async()).await })
+        .unwrap();
+}
+}
+
+async fn send_hello() {
+    let mut socket = UdpSocket::bind("0.0.0.0:0").await.unwrap();
+    socket.send_to(b"hello", (PEER, 7)).await.unwrap();
+}
+
+/// Checks that `pkt` is the IPv4/UDP packet sent by `send_hello`.
+fn assert_hello(pkt: &[u8]) {
+    assert_eq!(pkt.len(), 20 + 8 + 5);
+    assert_eq!(pkt[0] >> 4, 4);
+    assert_eq!(pkt[9], 17);
+    assert_eq!(&pkt[16..20], &PEER.octets());
+    assert_eq!(&pkt[28..], b"hello");
+}
+
+async fn stream_tunnel_netif_async() {
+    let (a, b) = UnixStream::pair().unwrap();
+    let dev = DeviceBuilder::default()
+        .ipv4(LOCAL, 24)
+        .build(StreamTunnel::new(a))
+        .unwrap();
+    tokio::spawn(dev.drive());
+    let mut peer = StreamTunnel::new(b);
+
+    send_hello().await;
+    let mut pkt = vec![0; 1500];
+    let len = peer.read(&mut pkt).await.unwrap();
+    assert_hello(&pkt[..len]);
+}
+
+async fn udp_tunnel_netif_async() {
+    let a = HostUdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let b = HostUdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let (a_addr, b_addr) = (a.local_addr().unwrap(), b.local_addr().unwrap());
+
+    let dev = DeviceBuilder::default()
+        .ipv4(LOCAL, 24)
+        .build(UdpTunnel::new(a, b_addr))
+        .unwrap();
+    tokio::spawn(dev.drive());
+    let mut peer = UdpTunnel::new(b, a_addr);
+
+    // only the peer is listened to
+    let mut other = HostUdpSocket::bind("127.0.0.1:0").await.unwrap();
+    other.send_to(b"junk", b_addr).await.unwrap();
+
+    send_hello().await;
+    let mut pkt = vec![0; 1500];
+    let len = peer.read(&mut pkt).await.unwrap();
+    assert_hello(&pkt[..len]);
+}
@@ -0,0 +1,265 @@
+#[macro_use]
+extern crate rusty_fork;
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::runtime;
+use tokio::stream::StreamExt;
+use tokio::time::timeout;
+
+use lwip::dev::{Link, LinkEnd};
+use lwip::{NetIf, NetIfEvent, UdpSocket};
+
+#[allow(dead_code)]
+mod common;
+
+rusty_fork_test! {
+#[test]
+fn udp_echo() {
+    let mut rt = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_time()
+        .build()
+        .unwrap();
+    rt.block_on(async { timeout(Duration::from_secs(30), udp_echo_async()).await })
+        .unwrap();
+}
+
+#[test]
+fn udp_multicast_v4() {
+    let mut rt = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_time()
+        .build()
+        .unwrap();
+    rt.block_on(async { timeout(Duration::from_secs(30), udp_multicast_v4_async()).await })
+        .unwrap();
+}
+
+#[test]
+fn udp_multicast_v6() {
+    let mut rt = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_time()
+        .build()
+        .unwrap();
+    rt.block_on(async { timeout(Duration::from_secs(30), udp_multicast_v6_async()).await })
+        .unwrap();
+}
+
+#[test]
+fn udp_broadcast() {
+    let mut rt = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_time()
+        .build()
+        .unwrap();
+    rt.block_on(async { timeout(Duration::from_secs(30), udp_broadcast_async()).await })
+        .unwrap();
+}
+}
+
+const GROUP_V4: Ipv4Addr = Ipv4Addr::new(239, 1, 2, 3);
+
+fn group_v6() -> Ipv6Addr {
+    "ff02::fb".parse().unwrap()
+}
+
+/// Two interfaces on the same link: 10.0.0.1 and fd00::1, 10.0.0.2 and
+/// fd00::2.
+fn setup() -> (NetIf, NetIf) {
+    let (dev0, dev1) = Link::new();
+
+    let a = lwip::DeviceBuilder::default()
+        .ipv4(Ipv4Addr::new(10, 0, 0, 1), 24)
+        .ipv6("fd00::1".parse().unwrap(), 64)
+        .build(dev0)
+        .unwrap();
+    let b = lwip::DeviceBuilder::default()
+        .ipv4(Ipv4Addr::new(10, 0, 0, 2), 24)
+        .ipv6("fd00::2".parse().unwrap(), 64)
+        .build(dev1)
+        .unwrap();
+    let netifs = (a.netif_as_ref().clone(), b.netif_as_ref().clone());
+
+    tokio::spawn(a.drive());
+    tokio::spawn(b.drive());
+    netifs
+}
+
+async fn try_recv(socket: &mut UdpSocket) -> Option<(Vec<u8>, SocketAddr)> {
+    let mut buf = vec![0; 64];
+    match timeout(Duration::from_millis(200), socket.recv_from(&mut buf)).await {
+        Ok(res) => {
+            let (len, addr) = res.unwrap();
+            buf.truncate(len);
+            Some((buf, addr))
+        }
+        Err(_) => None,
+    }
+}
+
+async fn udp_echo_async() {
+    let dev = lwip::DeviceBuilder::loopback().unwrap();
+    tokio::spawn(dev.drive());
+
+    let mut server = UdpSocket::bind("127.0.0.1:7").await.unwrap();
+    let mut client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let client_addr = client.local_addr().unwrap();
+    assert_ne!(client_addr.port(), 0);
+
+    client.send_to(b"hello", "127.0.0.1:7").await.unwrap();
+    let (data, from) = try_recv(&mut server).await.unwrap();
+    assert_eq!(data, b"hello");
+    assert_eq!(from, client_addr);
+
+    server.send_to(&data, from).await.unwrap();
+    client.connect("127.0.0.1:7").await.unwrap();
+    let mut buf = vec![0; 3];
+    // truncated to the buffer
+    assert_eq!(client.recv(&mut buf).await.unwrap(), 3);
+    assert_eq!(buf, b"hel");
+
+    client.send(b"again").await.unwrap();
+    assert_eq!(try_recv(&mut server).await.unwrap().0, b"again");
+}
+
+async fn udp_multicast_v4_async() {
+    let (a, b) = setup();
+    let mut events = b.watch();
+
+    let mut receiver = UdpSocket::bind("0.0.0.0:5000").await.unwrap();
+    receiver
+        .join_multicast_v4(GROUP_V4, Ipv4Addr::new(10, 0, 0, 2))
+        .unwrap();
+    assert!(b.multicast_groups().contains(&IpAddr::V4(GROUP_V4)));
+    assert!(!a.multicast_groups().contains(&IpAddr::V4(GROUP_V4)));
+    loop {
+        if events.next().await.unwrap() == NetIfEvent::MulticastJoined(IpAddr::V4(GROUP_V4)) {
+            break;
+        }
+    }
+
+    let mut sender = UdpSocket::bind("10.0.0.1:0").await.unwrap();
+    sender
+        .set_multicast_if_v4(Ipv4Addr::new(10, 0, 0, 1))
+        .unwrap();
+    sender.set_multicast_ttl_v4(1).unwrap();
+    assert_eq!(sender.multicast_ttl_v4().unwrap(), 1);
+
+    sender.send_to(b"hello", (GROUP_V4, 5000)).await.unwrap();
+    let (data, from) = try_recv(&mut receiver).await.unwrap();
+    assert_eq!(data, b"hello");
+    assert_eq!(from, sender.local_addr().unwrap());
+
+    // once left, the group is filtered out again
+    receiver
+        .leave_multicast_v4(GROUP_V4, Ipv4Addr::new(10, 0, 0, 2))
+        .unwrap();
+    assert!(!b.multicast_groups().contains(&IpAddr::V4(GROUP_V4)));
+    loop {
+        if events.next().await.unwrap() == NetIfEvent::MulticastLeft(IpAddr::V4(GROUP_V4)) {
+            break;
+        }
+    }
+    sender.send_to(b"lost", (GROUP_V4, 5000)).await.unwrap();
+    assert!(try_recv(&mut receiver).await.is_none());
+
+    // a member on the sending interface only gets it looped back
+    receiver
+        .join_multicast_v4(GROUP_V4, Ipv4Addr::new(10, 0, 0, 1))
+        .unwrap();
+    sender.send_to(b"lost", (GROUP_V4, 5000)).await.unwrap();
+    assert!(try_recv(&mut receiver).await.is_none());
+
+    sender.set_multicast_loop_v4(true).unwrap();
+    assert!(sender.multicast_loop_v4().unwrap());
+    sender.send_to(b"looped", (GROUP_V4, 5000)).await.unwrap();
+    assert_eq!(try_recv(&mut receiver).await.unwrap().0, b"looped");
+
+    // dropping the socket leaves its groups
+    drop(receiver);
+    assert!(!a.multicast_groups().contains(&IpAddr::V4(GROUP_V4)));
+}
+
+async fn udp_multicast_v6_async() {
+    let (a, b) = setup();
+
+    let mut receiver = UdpSocket::bind("[::]:5353").await.unwrap();
+    receiver
+        .join_multicast_v6(&group_v6(), b.index() as u32)
+        .unwrap();
+    assert!(b.multicast_groups().contains(&IpAddr::V6(group_v6())));
+    assert!(!a.multicast_groups().contains(&IpAddr::V6(group_v6())));
+
+    let mut sender = UdpSocket::bind("[::]:0").await.unwrap();
+    sender.set_multicast_if_v6(a.index() as u32).unwrap();
+    sender.send_to(b"hello", (group_v6(), 5353)).await.unwrap();
+    let (data, from) = try_recv(&mut receiver).await.unwrap();
+    assert_eq!(data, b"hello");
+    assert_eq!(from.ip(), "fd00::1".parse::<IpAddr>().unwrap());
+
+    receiver
+        .leave_multicast_v6(&group_v6(), b.index() as u32)
+        .unwrap();
+    assert!(!b.multicast_groups().contains(&IpAddr::V6(group_v6())));
+    sender.send_to(b"lost", (group_v6(), 5353)).await.unwrap();
+    assert!(try_recv(&mut receiver).await.is_none());
+
+    // leaving a group not joined
+    assert!(receiver
+        .leave_multicast_v6(&group_v6(), b.index() as u32)
+        .is_err());
+}
+
+/// Next UDP/IPv4 packet sent on the link.
+async fn next_udp(link: &mut LinkEnd) -> Vec<u8> {
+    let mut buf = vec![0u8; 2048];
+
+    loop {
+        let len = link.read(&mut buf).await.unwrap();
+        if len > 28 && buf[0] == 0x45 && buf[9] == 17 {
+            return buf[..len].to_vec();
+        }
+    }
+}
+
+async fn udp_broadcast_async() {
+    let (dev, mut link) = Link::new();
+    let dev = lwip::DeviceBuilder::default()
+        .ipv4(Ipv4Addr::new(10, 0, 0, 1), 24)
+        .build(dev)
+        .unwrap();
+    tokio::spawn(dev.drive());
+
+    let mut socket = UdpSocket::bind("10.0.0.1:0").await.unwrap();
+    assert!(!socket.broadcast().unwrap());
+    assert!(socket
+        .send_to(b"denied", (Ipv4Addr::BROADCAST, 9))
+        .await
+        .is_err());
+    assert!(socket.send_to(b"denied", "10.0.0.255:9").await.is_err());
+
+    // limited and directed broadcast
+    socket.set_broadcast(true).unwrap();
+    assert!(socket.broadcast().unwrap());
+    for dst in [Ipv4Addr::BROADCAST, Ipv4Addr::new(10, 0, 0, 255)].iter() {
+        socket.send_to(b"hello", (*dst, 9)).await.unwrap();
+        let pkt = next_udp(&mut link).await;
+        assert_eq!(pkt[12..16], [10, 0, 0, 1]);
+        assert_eq!(pkt[16..20], dst.octets());
+        assert_eq!(&pkt[28..], b"hello");
+    }
+
+    // received without the option
+    let mut receiver = UdpSocket::bind("0.0.0.0:9").await.unwrap();
+    for dst in [Ipv4Addr::BROADCAST, Ipv4Addr::new(10, 0, 0, 255)].iter() {
+        let pkt = common::ipv4_udp(Ipv4Addr::new(10, 0, 0, 2), *dst, 40000, 9, b"wake");
+        link.write_all(&pkt).await.unwrap();
+        let (data, from) = try_recv(&mut receiver).await.unwrap();
+        assert_eq!(data, b"wake");
+        assert_eq!(from, "10.0.0.2:40000".parse().unwrap());
+    }
+}